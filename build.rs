@@ -0,0 +1,31 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // `bright self update` downloads the release asset of this target
+    println!(
+        "cargo:rustc-env=BRIGHT_TARGET={}",
+        std::env::var("TARGET").unwrap()
+    );
+    println!("cargo:rerun-if-env-changed=BRIGHT_UPDATE_PUBLIC_KEY");
+    #[cfg(feature = "ffi")]
+    header();
+}
+
+/// Generates the C header for `src/ffi.rs` into `OUT_DIR`, a test checks that the committed
+/// `include/bright.h` matches it
+#[cfg(feature = "ffi")]
+fn header() {
+    use std::{env, path::PathBuf};
+
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(dir.join("src/ffi.rs"))
+        .generate()
+        .expect("can't generate the C header")
+        .write_to_file(out.join("bright.h"));
+}
@@ -0,0 +1,62 @@
+#![no_main]
+
+use bright::{
+    animation::easing::EasingKind,
+    brightness::{
+        ast::{Ast, functions::FunctionPolicy},
+        lexer::lexer,
+    },
+    device::{BrightnessRead, DeviceClass, errors::DeviceReadError},
+};
+use libfuzzer_sys::fuzz_target;
+
+struct FuzzDevice {
+    max: u32,
+    current: u32,
+}
+
+impl BrightnessRead for FuzzDevice {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn class(&self) -> DeviceClass {
+        DeviceClass::Backlight
+    }
+
+    fn max(&self) -> u32 {
+        self.max
+    }
+
+    fn current(&self) -> Result<u32, DeviceReadError> {
+        Ok(self.current)
+    }
+}
+
+fuzz_target!(|input: (u32, u32, &str)| {
+    let (max, current, expression) = input;
+    let Ok(tokens) = lexer(expression) else {
+        return;
+    };
+    let Ok(ast) = Ast::parse_tokens(&mut tokens.into_iter().peekable()) else {
+        return;
+    };
+
+    // `restore` would read the state directory
+    let policy = FunctionPolicy {
+        disabled: Vec::new(),
+        sandbox: true,
+    };
+    if ast.check_functions(&policy).is_err() {
+        return;
+    }
+
+    let device = FuzzDevice {
+        max,
+        current: current.min(max),
+    };
+    for easing in ["x", "x^2.2"] {
+        let easing: EasingKind = easing.parse().unwrap();
+        let _ = ast.evaluate(&device, &easing);
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use bright::desired_state::DesiredState;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    let _ = input.parse::<DesiredState>();
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use bright::{
+    animation::easing::{Easing, EasingKind},
+    config::Easings,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    let _ = input.parse::<Easings>();
+    if let Ok(easing) = input.parse::<EasingKind>() {
+        for x in [0.0, 0.5, 1.0] {
+            let _ = easing.to_actual(x);
+            let _ = easing.from_actual(x);
+        }
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use bright::brightness::lexer::lexer;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    let _ = lexer(input);
+});
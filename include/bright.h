@@ -0,0 +1,113 @@
+#ifndef BRIGHT_H
+#define BRIGHT_H
+
+/* Generated from src/ffi.rs by cbindgen, don't edit it by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The result of every call, `BRIGHT_STATUS_OK` if it succeeded
+ */
+typedef enum BrightStatus {
+  BRIGHT_STATUS_OK = 0,
+  /**
+   * A required pointer was `NULL`
+   */
+  BRIGHT_STATUS_NULL_POINTER,
+  /**
+   * A string wasn't valid UTF-8
+   */
+  BRIGHT_STATUS_INVALID_UTF8,
+  /**
+   * The settings or easings can't be read
+   */
+  BRIGHT_STATUS_CONFIG,
+  BRIGHT_STATUS_DEVICE_NOT_FOUND,
+  BRIGHT_STATUS_READ_FAILED,
+  BRIGHT_STATUS_WRITE_FAILED,
+  /**
+   * The expression can't be parsed or uses a disabled function
+   */
+  BRIGHT_STATUS_INVALID_EXPRESSION,
+  BRIGHT_STATUS_EVALUATION_FAILED,
+} BrightStatus;
+
+/**
+ * The names of all devices, see [`bright_list_devices`]
+ */
+typedef struct BrightDeviceList BrightDeviceList;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Lists all devices, free the list using [`bright_device_list_free`]
+ *
+ * # Safety
+ * `list` must be valid for writes
+ */
+enum BrightStatus bright_list_devices(struct BrightDeviceList **list);
+
+/**
+ * # Safety
+ * `list` must be `NULL` or returned by [`bright_list_devices`]
+ */
+size_t bright_device_list_len(const struct BrightDeviceList *list);
+
+/**
+ * The name at `index`, or `NULL` if it's out of range. It lives as long as the list
+ *
+ * # Safety
+ * `list` must be `NULL` or returned by [`bright_list_devices`]
+ */
+const char *bright_device_list_get(const struct BrightDeviceList *list, size_t index);
+
+/**
+ * # Safety
+ * `list` must be `NULL` or returned by [`bright_list_devices`] and not be used afterwards
+ */
+void bright_device_list_free(struct BrightDeviceList *list);
+
+/**
+ * Reads the current and the maximal brightness of a device
+ *
+ * # Safety
+ * `device` must be `NULL` or a NUL-terminated string, `current` and `max` must be valid for
+ * writes
+ */
+enum BrightStatus bright_get(const char *device, uint32_t *current, uint32_t *max);
+
+/**
+ * Sets the brightness of a device to a hardware level without animating. Like the command line
+ * it waits for other invocations changing the device and keeps to its `min` setting
+ *
+ * # Safety
+ * `device` must be `NULL` or a NUL-terminated string
+ */
+enum BrightStatus bright_set(const char *device, uint32_t value);
+
+/**
+ * Evaluates a brightness expression like `10%+` for a device without setting it, functions
+ * disabled in the settings are rejected
+ *
+ * # Safety
+ * `device` must be `NULL` or a NUL-terminated string, `expression` must be a NUL-terminated
+ * string and `value` must be valid for writes
+ */
+enum BrightStatus bright_evaluate(const char *device, const char *expression, uint32_t *value);
+
+/**
+ * A static description of a status, which must not be freed
+ */
+const char *bright_status_message(enum BrightStatus status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BRIGHT_H */
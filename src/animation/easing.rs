@@ -10,6 +10,53 @@ pub trait Easing {
     fn to_actual(&self, user_facing: f64) -> f64;
     #[allow(clippy::wrong_self_convention)]
     fn from_actual(&self, actual: f64) -> f64;
+
+    /// Samples the easing to check that it is usable, which is especially useful for custom
+    /// easings.
+    /// An easing must map 0..=1 into 0..=1, be monotonically increasing and `from_actual` must
+    /// invert `to_actual`
+    fn verify(&self) -> Result<(), EasingVerifyError> {
+        const SAMPLES: u32 = 64;
+        const TOLERANCE: f64 = 1e-6;
+
+        let mut previous = f64::NEG_INFINITY;
+        for i in 0..=SAMPLES {
+            let user_facing = f64::from(i) / f64::from(SAMPLES);
+            let actual = self.to_actual(user_facing);
+
+            if !(-TOLERANCE..=1.0 + TOLERANCE).contains(&actual) {
+                return Err(EasingVerifyError::OutOfRange {
+                    user_facing,
+                    actual,
+                });
+            }
+            if actual < previous {
+                return Err(EasingVerifyError::Decreasing { user_facing });
+            }
+
+            let reversed = self.from_actual(actual);
+            if (reversed - user_facing).abs() > TOLERANCE {
+                return Err(EasingVerifyError::NotInvertible {
+                    user_facing,
+                    reversed,
+                });
+            }
+
+            previous = actual;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum EasingVerifyError {
+    #[error("{user_facing} is mapped to {actual} which is outside of 0..=1")]
+    OutOfRange { user_facing: f64, actual: f64 },
+    #[error("the easing decreases at {user_facing}")]
+    Decreasing { user_facing: f64 },
+    #[error("{user_facing} is reversed to {reversed}")]
+    NotInvertible { user_facing: f64, reversed: f64 },
 }
 
 #[derive(Debug, Error)]
@@ -18,6 +65,8 @@ pub enum EasingParseError {
     InvalidPattern,
     #[error("invalid number")]
     InvalidNum,
+    #[error("easings can't be nested more than {max} levels deep")]
+    TooDeep { max: usize },
     #[error("can't parse float")]
     ParseFloat(
         #[source]
@@ -141,12 +190,217 @@ impl FromStr for Linear {
     }
 }
 
-#[derive(Default, Clone, Copy, Debug)]
+/// `a*x + b`, mostly useful at the end of a chain to reserve a minimum brightness. Like every
+/// easing it maps 0..=1 into 0..=1, so `b` can't be negative and `a + b` can't exceed 1
+#[derive(Clone, Copy, Debug)]
+pub struct Affine {
+    scale: f64,
+    offset: f64,
+}
+
+impl Affine {
+    fn try_new(scale: f64, offset: f64) -> Option<Self> {
+        // with some leeway for rounding, like `0.7*x + 0.3`
+        let in_range = offset >= 0.0 && scale + offset <= 1.0 + 1e-9;
+        (scale > 0.0 && in_range).then_some(Self { scale, offset })
+    }
+}
+
+impl Easing for Affine {
+    fn to_actual(&self, user_facing: f64) -> f64 {
+        user_facing.mul_add(self.scale, self.offset)
+    }
+
+    fn from_actual(&self, actual: f64) -> f64 {
+        // values below the offset can't be reached by the user
+        ((actual - self.offset) / self.scale).clamp(0.0, 1.0)
+    }
+}
+
+impl FromStr for Affine {
+    type Err = EasingParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let Some((scale, offset)) = s.split_once('x') else {
+            return Err(EasingParseError::InvalidPattern);
+        };
+
+        let scale = match scale {
+            "" => None,
+            scale => Some(
+                scale
+                    .strip_suffix('*')
+                    .ok_or(EasingParseError::InvalidPattern)?
+                    .parse()?,
+            ),
+        };
+        let offset = match offset.chars().next() {
+            None => None,
+            Some('+') => Some(offset[1..].parse()?),
+            Some('-') => Some(offset.parse()?),
+            Some(_) => return Err(EasingParseError::InvalidPattern),
+        };
+
+        // a plain `x` is linear
+        if scale.is_none() && offset.is_none() {
+            return Err(EasingParseError::InvalidPattern);
+        }
+
+        Self::try_new(scale.unwrap_or(1.0), offset.unwrap_or(0.0))
+            .ok_or(EasingParseError::InvalidNum)
+    }
+}
+
+impl Display for Affine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}*x + {}", self.scale, self.offset)
+    }
+}
+
+/// How deeply `range(..)` may be nested, deeper easings are rejected instead of overflowing the
+/// stack while being parsed
+const MAX_DEPTH: usize = 16;
+
+/// Maps the full user facing range onto a part of the hardware's range using
+/// `range(min, max, inner)`, e.g. for panels which flicker at low brightnesses
+#[derive(Clone, Debug)]
+pub struct Range {
+    min: f64,
+    max: f64,
+    inner: Box<EasingKind>,
+}
+
+impl Range {
+    fn try_new(min: f64, max: f64, inner: EasingKind) -> Option<Self> {
+        (0.0 <= min && min < max && max <= 1.0).then(|| Self {
+            min,
+            max,
+            inner: Box::new(inner),
+        })
+    }
+}
+
+impl Easing for Range {
+    fn to_actual(&self, user_facing: f64) -> f64 {
+        self.inner
+            .to_actual(user_facing)
+            .mul_add(self.max - self.min, self.min)
+    }
+
+    fn from_actual(&self, actual: f64) -> f64 {
+        let inner = ((actual - self.min) / (self.max - self.min)).clamp(0.0, 1.0);
+        self.inner.from_actual(inner)
+    }
+}
+
+impl FromStr for Range {
+    type Err = EasingParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_at_depth(s, 0)
+    }
+}
+
+impl Range {
+    fn parse_at_depth(s: &str, depth: usize) -> Result<Self, EasingParseError> {
+        let arguments = s
+            .strip_prefix("range(")
+            .and_then(|s| s.strip_suffix(')'))
+            .ok_or(EasingParseError::InvalidPattern)?;
+
+        // the inner easing may contain commas itself
+        let mut arguments = arguments.splitn(3, ',').map(str::trim);
+        let (Some(min), Some(max), Some(inner)) =
+            (arguments.next(), arguments.next(), arguments.next())
+        else {
+            return Err(EasingParseError::InvalidPattern);
+        };
+        if depth >= MAX_DEPTH {
+            return Err(EasingParseError::TooDeep { max: MAX_DEPTH });
+        }
+
+        let inner = EasingKind::parse_at_depth(inner, depth + 1)?;
+        Self::try_new(min.parse()?, max.parse()?, inner).ok_or(EasingParseError::InvalidNum)
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "range({}, {}, {})", self.min, self.max, self.inner)
+    }
+}
+
+/// Easings applied one after another, written as `x^2.2 | 0.9*x + 0.1`
+#[derive(Clone, Debug)]
+pub struct EasingChain(Vec<EasingKind>);
+
+impl Easing for EasingChain {
+    fn to_actual(&self, user_facing: f64) -> f64 {
+        self.0
+            .iter()
+            .fold(user_facing, |value, easing| easing.to_actual(value))
+    }
+
+    fn from_actual(&self, actual: f64) -> f64 {
+        self.0
+            .iter()
+            .rev()
+            .fold(actual, |value, easing| easing.from_actual(value))
+    }
+}
+
+impl FromStr for EasingChain {
+    type Err = EasingParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_at_depth(s, 0)
+    }
+}
+
+impl EasingChain {
+    fn parse_at_depth(s: &str, depth: usize) -> Result<Self, EasingParseError> {
+        split_top_level(s, '|')
+            .map(|part| EasingKind::parse_single(part.trim(), depth))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl Display for EasingChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, easing) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" | ")?;
+            }
+            write!(f, "{easing}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits at every `separator` which isn't inside of parentheses
+pub(crate) fn split_top_level(s: &str, separator: char) -> impl Iterator<Item = &str> {
+    let mut depth = 0_usize;
+    s.split(move |c| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        c == separator && depth == 0
+    })
+}
+
+#[derive(Default, Clone, Debug)]
 pub enum EasingKind {
     #[default]
     Linear,
     Exponential(Exponential),
     Polynomial(Polynomial),
+    Affine(Affine),
+    Chain(EasingChain),
+    Range(Range),
 }
 
 #[cfg(test)]
@@ -166,6 +420,9 @@ impl Display for EasingKind {
             Self::Linear => Linear,
             Self::Exponential(exp) => exp,
             Self::Polynomial(pol) => pol,
+            Self::Affine(aff) => aff,
+            Self::Chain(chain) => chain,
+            Self::Range(range) => range,
         } {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
         }
@@ -178,6 +435,9 @@ impl Easing for EasingKind {
             Self::Linear => Linear,
             Self::Exponential(exp) => exp,
             Self::Polynomial(pol) => pol,
+            Self::Affine(aff) => aff,
+            Self::Chain(chain) => chain,
+            Self::Range(range) => range,
         } {
             fn to_actual(&self, user_facing: f64) -> f64;
             fn from_actual(&self, actual: f64) -> f64;
@@ -185,10 +445,23 @@ impl Easing for EasingKind {
     }
 }
 
-impl FromStr for EasingKind {
-    type Err = EasingParseError;
+impl EasingKind {
+    /// Parses an easing nested into `depth` ranges
+    fn parse_at_depth(s: &str, depth: usize) -> Result<Self, EasingParseError> {
+        if split_top_level(s, '|').nth(1).is_some() {
+            EasingChain::parse_at_depth(s, depth).map(Self::Chain)
+        } else {
+            Self::parse_single(s, depth)
+        }
+    }
+
+    /// Parses an easing which isn't a chain
+    fn parse_single(s: &str, depth: usize) -> Result<Self, EasingParseError> {
+        match Range::parse_at_depth(s, depth).map(Self::Range) {
+            Err(EasingParseError::InvalidPattern) => {}
+            result => return result,
+        }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.parse().map(Self::Exponential) {
             Err(EasingParseError::InvalidPattern) => {}
             result => return result,
@@ -199,13 +472,43 @@ impl FromStr for EasingKind {
             result => return result,
         }
 
+        match s.parse().map(Self::Affine) {
+            Err(EasingParseError::InvalidPattern) => {}
+            result => return result,
+        }
+
         s.parse::<Linear>().map(|_| Self::Linear)
     }
 }
 
+impl FromStr for EasingKind {
+    type Err = EasingParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_at_depth(s, 0)
+    }
+}
+
+/// Encoded like in the easings file, e.g. `"range(0.05, 1, x^2)"`
+#[cfg(feature = "serde")]
+impl serde::Serialize for EasingKind {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for EasingKind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     fn extremes(easing: impl Easing) -> (f64, f64) {
         (easing.to_actual(0.0), easing.to_actual(1.0))
@@ -271,4 +574,145 @@ mod tests {
             COORDINATES
         );
     }
+
+    #[test]
+    fn test_verify() {
+        assert_eq!(EasingKind::Linear.verify(), Ok(()));
+        assert_eq!(EasingKind::new_polynomial(2.2).unwrap().verify(), Ok(()));
+        assert_eq!(EasingKind::new_exponential(0.3).unwrap().verify(), Ok(()));
+
+        struct Broken;
+        impl Easing for Broken {
+            fn to_actual(&self, user_facing: f64) -> f64 {
+                user_facing / 2.0
+            }
+            fn from_actual(&self, actual: f64) -> f64 {
+                actual
+            }
+        }
+        assert!(matches!(
+            Broken.verify(),
+            Err(EasingVerifyError::NotInvertible { .. })
+        ));
+    }
+
+    #[test]
+    fn test_chain() {
+        let easing: EasingKind = "x^2 | 0.9*x + 0.1".parse().unwrap();
+        assert_eq!(easing.to_string(), "x^2 | 0.9*x + 0.1");
+        assert_eq!(extremes(easing.clone()), (0.1, 1.0));
+        assert!((easing.to_actual(0.5) - 0.325).abs() < 1e-9);
+        assert_eq!(easing.verify(), Ok(()));
+
+        // the floor can't be undercut
+        assert_eq!(easing.from_actual(0.0), 0.0);
+
+        assert_eq!(
+            "0.5*x+0.5".parse::<EasingKind>().unwrap().to_string(),
+            "0.5*x + 0.5"
+        );
+        // both would leave 0..=1
+        assert!(matches!(
+            "x - 0.5".parse::<EasingKind>(),
+            Err(EasingParseError::InvalidNum)
+        ));
+        assert!(matches!(
+            "0.9*x + 0.2".parse::<EasingKind>(),
+            Err(EasingParseError::InvalidNum)
+        ));
+        assert!(matches!(
+            "x^2 | x * 2".parse::<EasingKind>(),
+            Err(EasingParseError::InvalidPattern)
+        ));
+        assert!(matches!(
+            "-1*x".parse::<EasingKind>(),
+            Err(EasingParseError::InvalidNum)
+        ));
+    }
+
+    #[test]
+    fn test_range() {
+        let easing: EasingKind = "range(0.05, 0.9, x^2 | x)".parse().unwrap();
+        assert_eq!(easing.to_string(), "range(0.05, 0.9, x^2 | x)");
+        assert_eq!(extremes(easing.clone()), (0.05, 0.9));
+        assert_eq!(extremes_rev(easing.clone()), (0.0, 1.0));
+        assert_eq!(easing.verify(), Ok(()));
+
+        let nested: EasingKind = "x | range(0.1, 1, range(0, 0.5, x))".parse().unwrap();
+        assert!((nested.to_actual(1.0) - 0.55).abs() < 1e-9);
+
+        assert!(matches!(
+            "range(0.9, 0.05, x)".parse::<EasingKind>(),
+            Err(EasingParseError::InvalidNum)
+        ));
+        assert!(matches!(
+            "range(0.05, 0.9)".parse::<EasingKind>(),
+            Err(EasingParseError::InvalidPattern)
+        ));
+
+        // deep nesting is rejected before it overflows the stack
+        let nest = |depth| format!("{}x{}", "range(0, 1, ".repeat(depth), ")".repeat(depth));
+        assert!(nest(MAX_DEPTH).parse::<EasingKind>().is_ok());
+        assert!(matches!(
+            nest(100_000).parse::<EasingKind>(),
+            Err(EasingParseError::TooDeep { max: MAX_DEPTH })
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let easing: EasingKind = "x^2 | range(0.1, 1, x)".parse().unwrap();
+        let json = serde_json::to_string(&easing).unwrap();
+        assert_eq!(json, r#""x^2 | range(0.1, 1, x)""#);
+        let decoded: EasingKind = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.to_string(), easing.to_string());
+        assert!(serde_json::from_str::<EasingKind>(r#""x^""#).is_err());
+    }
+
+    fn easing_kind() -> impl Strategy<Value = EasingKind> {
+        prop_oneof![
+            Just(EasingKind::Linear),
+            (0.05..20.0).prop_map(|exp| EasingKind::new_polynomial(exp).unwrap()),
+            (0.01..100.0)
+                .prop_filter("base 1 isn't exponential", |base| (base - 1.0_f64).abs()
+                    > 1e-3)
+                .prop_map(|base| EasingKind::new_exponential(base).unwrap()),
+            affine(),
+            (0.0..0.5, 0.3..0.5, composable()).prop_map(|(min, width, inner)| EasingKind::Range(
+                Range::try_new(min, min + width, inner).unwrap()
+            )),
+            prop::collection::vec(composable(), 2..=3)
+                .prop_map(|easings| EasingKind::Chain(EasingChain(easings))),
+        ]
+    }
+
+    fn affine() -> impl Strategy<Value = EasingKind> {
+        (0.3..=1.0, 0.0..=1.0).prop_map(|(scale, share): (f64, f64)| {
+            EasingKind::Affine(Affine::try_new(scale, (1.0 - scale) * share).unwrap())
+        })
+    }
+
+    /// Parts of ranges and chains. An offset after a flat start like `x^3` rounds its low end
+    /// away, so those can't be inverted precisely and aren't generated
+    fn composable() -> impl Strategy<Value = EasingKind> {
+        prop_oneof![
+            Just(EasingKind::Linear),
+            (0.3..=1.0).prop_map(|exp| EasingKind::new_polynomial(exp).unwrap()),
+            (0.2..5.0)
+                .prop_filter("base 1 isn't exponential", |base| (base - 1.0_f64).abs()
+                    > 0.1)
+                .prop_map(|base| EasingKind::new_exponential(base).unwrap()),
+            affine(),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_invertibility(easing in easing_kind(), x in 0.0..=1.0) {
+            let reversed = easing.from_actual(easing.to_actual(x));
+            prop_assert!((reversed - x).abs() < 1e-6, "{easing}: {x} became {reversed}");
+            prop_assert_eq!(easing.verify(), Ok(()));
+        }
+    }
 }
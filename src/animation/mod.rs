@@ -1,20 +1,21 @@
 pub mod easing;
+pub mod sink;
 
-use crate::animation::easing::Easing;
+use crate::{animation::easing::Easing, brightness::perceptual::PerceptualMapper};
 use std::{iter::FusedIterator, num::NonZero};
 
 pub struct AnimationIter<T: Easing> {
-    current: u16,
+    current: u32,
     frame_count: usize,
 
     // constant
-    desired: u16,
-    max: u16,
+    desired: u32,
+    max: u32,
     easing: T,
 }
 
 impl<T: Easing> Iterator for AnimationIter<T> {
-    type Item = (u16, bool);
+    type Item = (u32, bool);
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.frame_count {
@@ -27,17 +28,13 @@ impl<T: Easing> Iterator for AnimationIter<T> {
             _ => {}
         }
 
-        let current_actual = f64::from(self.current) / f64::from(self.max);
-        let desired_actual = f64::from(self.desired) / f64::from(self.max);
+        let mapper = PerceptualMapper::new(&self.easing, self.max);
+        let current_userfacing = mapper.perceived(self.current);
 
-        let current_userfacing = self.easing.from_actual(current_actual);
+        let user_step =
+            (mapper.perceived(self.desired) - current_userfacing) / self.frame_count as f64;
 
-        let user_step = (self.easing.from_actual(desired_actual) - current_userfacing)
-            / self.frame_count as f64;
-
-        let new_actual = self.easing.to_actual(current_userfacing + user_step);
-
-        self.current = (new_actual * f64::from(self.max)).round() as u16;
+        self.current = mapper.level(current_userfacing + user_step);
         self.frame_count -= 1;
 
         Some((self.current, false))
@@ -53,8 +50,8 @@ impl<T: Easing> ExactSizeIterator for AnimationIter<T> {}
 
 impl<T: Easing> AnimationIter<T> {
     pub fn new(
-        (current, desired): (u16, u16),
-        max: u16,
+        (current, desired): (u32, u32),
+        max: u32,
         frame_count: NonZero<usize>,
         easing: T,
     ) -> Self {
@@ -66,12 +63,19 @@ impl<T: Easing> AnimationIter<T> {
             easing,
         }
     }
+
+    /// Continues the animation from `current`, e.g. because the brightness was changed by
+    /// something else. The remaining frames are spread over the new distance
+    pub fn resync(&mut self, current: u32) {
+        self.current = current;
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::animation::easing::EasingKind;
+    use proptest::prelude::*;
 
     #[test]
     fn test_animation_iter_linear() {
@@ -83,4 +87,51 @@ mod tests {
         assert_eq!(animation.next(), Some((10, true)));
         assert_eq!(animation.next(), None);
     }
+
+    #[test]
+    fn test_animation_iter_resync() {
+        let mut animation =
+            AnimationIter::new((0, 100), 100, NonZero::new(4).unwrap(), EasingKind::Linear);
+        assert_eq!(animation.next(), Some((25, false)));
+        animation.resync(70);
+        assert_eq!(animation.next(), Some((80, false)));
+        assert_eq!(animation.next(), Some((90, false)));
+        assert_eq!(animation.next(), Some((100, true)));
+    }
+
+    proptest! {
+        #[test]
+        fn test_animation_iter_monotonic(
+            max in 1_u32..=u32::MAX,
+            (current, desired) in (0.0..=1.0, 0.0..=1.0),
+            frames in 1_usize..200,
+            exponent in 0.1..5.0,
+        ) {
+            let easing = format!("x^{exponent}").parse::<EasingKind>().unwrap();
+            let current = (current * f64::from(max)) as u32;
+            let desired = (desired * f64::from(max)) as u32;
+
+            let values: Vec<_> = AnimationIter::new(
+                (current, desired),
+                max,
+                NonZero::new(frames).unwrap(),
+                easing,
+            )
+            .collect();
+
+            prop_assert_eq!(values.len(), frames);
+            prop_assert_eq!(values.last(), Some(&(desired, true)));
+            prop_assert!(values[..frames - 1].iter().all(|(_, is_last)| !is_last));
+
+            let mut previous = current;
+            for &(value, _) in &values {
+                if desired >= current {
+                    prop_assert!(previous <= value && value <= desired);
+                } else {
+                    prop_assert!(previous >= value && value >= desired);
+                }
+                previous = value;
+            }
+        }
+    }
 }
@@ -0,0 +1,58 @@
+use std::{
+    io,
+    process::{Command, Stdio},
+};
+
+/// Receives every value that was applied to a device during an animation
+pub trait FrameSink {
+    fn frame(&mut self, value: u32, is_last: bool) -> io::Result<()>;
+}
+
+impl<F> FrameSink for F
+where
+    F: FnMut(u32, bool) -> io::Result<()>,
+{
+    fn frame(&mut self, value: u32, is_last: bool) -> io::Result<()> {
+        self(value, is_last)
+    }
+}
+
+/// Runs a shell command for every frame, the frame is described using environment variables
+pub struct CommandSink {
+    command: String,
+    device: String,
+    max: u32,
+}
+
+impl CommandSink {
+    pub fn new<S: ToString>(command: String, device: S, max: u32) -> Self {
+        Self {
+            command,
+            device: device.to_string(),
+            max,
+        }
+    }
+}
+
+impl FrameSink for CommandSink {
+    fn frame(&mut self, value: u32, is_last: bool) -> io::Result<()> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("BRIGHT_VALUE", value.to_string())
+            .env("BRIGHT_MAX", self.max.to_string())
+            .env("BRIGHT_DEVICE", &self.device)
+            .env("BRIGHT_LAST_FRAME", if is_last { "1" } else { "0" })
+            .stdin(Stdio::null())
+            .status()?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "`{}` exited with {status}",
+                self.command
+            )))
+        }
+    }
+}
@@ -1,5 +1,9 @@
-use super::{Ast, BrightnessEvaluationError};
-use crate::{animation::easing::Easing, device::Device};
+use super::{Ast, BrightnessEvaluationError, EvalContext};
+use crate::{
+    animation::easing::EasingKind,
+    brightness::perceptual::PerceptualMapper,
+    device::{BrightnessRead, DeviceClass, errors::DeviceReadError},
+};
 
 pub struct ArgumentCount {
     pub min: usize,
@@ -34,13 +38,13 @@ impl ArgumentCount {
 pub trait Function {
     fn name(&self) -> &'static str;
     fn argument_count(&self) -> ArgumentCount;
+    /// Whether the function can be used in sandboxed mode, which it can't if it accesses
+    /// anything besides the device
+    fn sandboxed(&self) -> bool {
+        true
+    }
 
-    fn call(
-        &self,
-        arguments: &[Ast],
-        device: &dyn Device,
-        easing: &dyn Easing,
-    ) -> Result<u16, BrightnessEvaluationError>;
+    fn call(&self, arguments: &[Ast], ctx: &EvalContext) -> Result<u32, BrightnessEvaluationError>;
 }
 
 pub struct Clamp;
@@ -53,16 +57,15 @@ impl Function for Clamp {
         ArgumentCount::exactly(3)
     }
 
-    fn call(
-        &self,
-        arguments: &[Ast],
-        device: &dyn Device,
-        easing: &dyn Easing,
-    ) -> Result<u16, BrightnessEvaluationError> {
-        let min = arguments[0].evaluate(device, easing)?;
-        let optimal = arguments[1].evaluate(device, easing)?;
-        let max = arguments[2].evaluate(device, easing)?;
-        Ok(optimal.clamp(min, max))
+    fn call(&self, arguments: &[Ast], ctx: &EvalContext) -> Result<u32, BrightnessEvaluationError> {
+        let min = arguments[0].evaluate_in(ctx)?;
+        let optimal = arguments[1].evaluate_in(ctx)?;
+        let max = arguments[2].evaluate_in(ctx)?;
+        let clamped = optimal.clamp(min, max);
+        if clamped != optimal {
+            ctx.note(|| format!("{optimal} is clamped to {clamped}"));
+        }
+        Ok(clamped)
     }
 }
 
@@ -76,13 +79,8 @@ impl Function for Current {
         ArgumentCount::empty()
     }
 
-    fn call(
-        &self,
-        _: &[Ast],
-        device: &dyn Device,
-        _: &dyn Easing,
-    ) -> Result<u16, BrightnessEvaluationError> {
-        Ok(device.current()?)
+    fn call(&self, _: &[Ast], ctx: &EvalContext) -> Result<u32, BrightnessEvaluationError> {
+        Ok(ctx.current()?)
     }
 }
 
@@ -96,13 +94,8 @@ impl Function for Max {
         ArgumentCount::new(1, None)
     }
 
-    fn call(
-        &self,
-        arguments: &[Ast],
-        device: &dyn Device,
-        easing: &dyn Easing,
-    ) -> Result<u16, BrightnessEvaluationError> {
-        let mut arguments = arguments.iter().map(|ast| ast.evaluate(device, easing));
+    fn call(&self, arguments: &[Ast], ctx: &EvalContext) -> Result<u32, BrightnessEvaluationError> {
+        let mut arguments = arguments.iter().map(|ast| ast.evaluate_in(ctx));
 
         let mut max = arguments
             .next()
@@ -128,13 +121,8 @@ impl Function for Min {
         ArgumentCount::new(1, None)
     }
 
-    fn call(
-        &self,
-        arguments: &[Ast],
-        device: &dyn Device,
-        easing: &dyn Easing,
-    ) -> Result<u16, BrightnessEvaluationError> {
-        let mut arguments = arguments.iter().map(|ast| ast.evaluate(device, easing));
+    fn call(&self, arguments: &[Ast], ctx: &EvalContext) -> Result<u32, BrightnessEvaluationError> {
+        let mut arguments = arguments.iter().map(|ast| ast.evaluate_in(ctx));
 
         let mut min = arguments
             .next()
@@ -150,29 +138,125 @@ impl Function for Min {
     }
 }
 
+/// The number of levels a blend factor is evaluated with, fine enough that no percentage is
+/// noticeably rounded
+const FACTOR_LEVELS: u32 = 10_000;
+
+pub struct Blend;
+
+impl Function for Blend {
+    fn name(&self) -> &'static str {
+        "blend"
+    }
+    fn argument_count(&self) -> ArgumentCount {
+        ArgumentCount::exactly(3)
+    }
+
+    fn call(&self, arguments: &[Ast], ctx: &EvalContext) -> Result<u32, BrightnessEvaluationError> {
+        let mapper = PerceptualMapper::new(ctx.easing, ctx.device.max());
+        let perceptual = |ast: &Ast| ast.evaluate_in(ctx).map(|value| mapper.perceived(value));
+
+        let from = perceptual(&arguments[0])?;
+        let to = perceptual(&arguments[1])?;
+        let factor = blend_factor(&arguments[2], ctx)?;
+
+        let blended = factor.mul_add(to - from, from);
+        Ok(mapper.level(blended))
+    }
+}
+
+/// Evaluates the factor as a percentage of the perceived brightness, so `50%`, `clamp(..)` and
+/// `current()` alike aren't rounded to the levels of a coarse device
+fn blend_factor(factor: &Ast, ctx: &EvalContext) -> Result<f64, BrightnessEvaluationError> {
+    let scale = PerceivedScale { ctx };
+    let linear = EasingKind::Linear;
+    let factor_ctx = EvalContext::new(&scale, &linear);
+
+    let level = factor.evaluate_in(&factor_ctx);
+    for warning in factor_ctx.into_warnings() {
+        ctx.warn(warning);
+    }
+    let factor = f64::from(level?) / f64::from(FACTOR_LEVELS);
+    ctx.note(|| format!("the factor is {:.2}%", factor * 100.0));
+    Ok(factor)
+}
+
+/// The device's perceived brightness as [`FACTOR_LEVELS`] linear levels
+struct PerceivedScale<'a> {
+    ctx: &'a EvalContext<'a>,
+}
+
+impl BrightnessRead for PerceivedScale<'_> {
+    fn name(&self) -> Option<&str> {
+        self.ctx.device.name()
+    }
+
+    fn class(&self) -> DeviceClass {
+        self.ctx.device.class()
+    }
+
+    fn max(&self) -> u32 {
+        FACTOR_LEVELS
+    }
+
+    fn current(&self) -> Result<u32, DeviceReadError> {
+        let current = self.ctx.current()?;
+        let perceived =
+            PerceptualMapper::new(self.ctx.easing, self.ctx.device.max()).perceived(current);
+        Ok(PerceptualMapper::new(&EasingKind::Linear, FACTOR_LEVELS).level(perceived))
+    }
+}
+
+/// The names of all functions [`get_function`] knows
+pub const NAMES: [&str; 6] = ["current", "clamp", "max", "min", "blend", "restore"];
+
 pub fn get_function(name: &str) -> Option<Box<dyn Function>> {
     match name {
         "current" => Some(Box::new(Current)),
         "clamp" => Some(Box::new(Clamp)),
         "max" => Some(Box::new(Max)),
         "min" => Some(Box::new(Min)),
+        "blend" => Some(Box::new(Blend)),
         "restore" => Some(Box::new(restoration::Restore)),
         _ => None,
     }
 }
 
+/// Decides which functions may be called
+#[derive(Default, Debug, Clone)]
+pub struct FunctionPolicy {
+    pub disabled: Vec<String>,
+    /// Only allow functions which don't access anything besides the device
+    pub sandbox: bool,
+}
+
+impl FunctionPolicy {
+    pub fn allows(&self, function: &dyn Function) -> bool {
+        !self.disabled.iter().any(|name| name == function.name())
+            && (!self.sandbox || function.sandboxed())
+    }
+}
+
 pub mod restoration {
     use thiserror::Error;
 
-    use super::{super::BrightnessEvaluationError, ArgumentCount, Function};
-    use crate::device::UNNAMED;
-    use std::error::Error as StdError;
-    use std::{
-        fs::{self, File, read_to_string},
-        io::{self, ErrorKind, Write},
-        path::PathBuf,
+    use super::{
+        super::{Ast, BrightnessEvaluationError, EvalContext, EvalWarning},
+        ArgumentCount, Function,
+    };
+    use crate::{
+        animation::easing::{Easing, EasingKind},
+        brightness::perceptual::PerceptualMapper,
+        device::{BrightnessRead, DeviceClass, UNNAMED, discovery::sysfs_entry, qualified_name},
+        state::{self, SavedBrightness, StateReadError},
     };
+    use std::{fs, io, path::PathBuf, time::Duration};
 
+    /// Restoring a brightness saved longer ago than this most likely isn't intended
+    const STALE_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Restores the saved brightness of the device, or of another device given by name like
+    /// `restore("intel_backlight")`. A name without a class like `leds:` is of the device's class
     pub struct Restore;
 
     impl Function for Restore {
@@ -181,55 +265,115 @@ pub mod restoration {
         }
 
         fn argument_count(&self) -> ArgumentCount {
-            ArgumentCount::empty()
+            ArgumentCount::new(0, Some(1))
+        }
+
+        fn sandboxed(&self) -> bool {
+            false
         }
 
         fn call(
             &self,
-            _: &[crate::brightness::ast::Ast],
-            device: &dyn crate::device::Device,
-            _: &dyn crate::animation::easing::Easing,
-        ) -> Result<u16, BrightnessEvaluationError> {
-            let path = device_restore_path(device.name().unwrap_or(UNNAMED));
-            let value = read_to_string(&path).map_err(|err| {
-                if err.kind() == ErrorKind::NotFound {
-                    BrightnessEvaluationError::MissingFile(path)
-                } else {
-                    BrightnessEvaluationError::Other(Box::new(err) as Box<dyn StdError>)
+            arguments: &[Ast],
+            ctx: &EvalContext,
+        ) -> Result<u32, BrightnessEvaluationError> {
+            let (class, name) = match arguments.first() {
+                None => (ctx.device.class(), ctx.device.name().unwrap_or(UNNAMED)),
+                // LED names contain colons too, so only a known class is split off
+                Some(Ast::Text(text)) => {
+                    let (class, name) = text
+                        .split_once(':')
+                        .and_then(|(class, name)| Some((class.parse().ok()?, name)))
+                        .unwrap_or((ctx.device.class(), text));
+                    // the name becomes a file in the state directory, so it mustn't leave it
+                    let name = sysfs_entry(name).ok_or_else(|| {
+                        BrightnessEvaluationError::InvalidArgument {
+                            function: self.name(),
+                            reason: format!("`{text}` isn't a device name"),
+                        }
+                    })?;
+                    (class, name)
                 }
+                Some(_) => {
+                    return Err(BrightnessEvaluationError::InvalidArgument {
+                        function: self.name(),
+                        reason: "expected a quoted device name".to_string(),
+                    });
+                }
+            };
+
+            let path = device_restore_path(class, name);
+            // older versions saved under the bare name, a directory is one of the state's own
+            let legacy = state::state_dir().join(name);
+            let path = if !path.exists() && legacy.is_file() {
+                legacy
+            } else {
+                path
+            };
+            let saved = state::read_saved(&path).map_err(|err| match err {
+                StateReadError::Missing(path) => BrightnessEvaluationError::MissingFile(path),
+                err => BrightnessEvaluationError::SavedState(err),
             })?;
 
-            value
-                .parse()
-                .map_err(|err| BrightnessEvaluationError::Other(Box::new(err) as Box<dyn StdError>))
+            if let Some(ago) = saved.expired_for() {
+                return Err(BrightnessEvaluationError::ExpiredSave {
+                    path,
+                    ago: Duration::from_secs(ago.as_secs()).into(),
+                });
+            }
+            if let Some(age) = saved.age().filter(|age| *age > STALE_AFTER) {
+                ctx.warn(EvalWarning::StaleRestore {
+                    age: Duration::from_secs(age.as_secs()).into(),
+                });
+            }
+            Ok(rescale(&saved, ctx))
         }
     }
 
-    fn device_restore_path(device_name: &str) -> PathBuf {
-        PathBuf::from(format!("/tmp/{}/{device_name}", env!("CARGO_PKG_NAME")))
+    /// Keeps the perceived brightness if the saved one belongs to a device with another maximum
+    fn rescale(saved: &SavedBrightness, ctx: &EvalContext) -> u32 {
+        let Some(saved_max) = saved.max.filter(|max| *max != ctx.device.max()) else {
+            return saved.value;
+        };
+
+        let saved_easing: Option<EasingKind> = saved
+            .easing
+            .as_deref()
+            .and_then(|easing| easing.parse().ok());
+        let saved_easing = saved_easing
+            .as_ref()
+            .map_or(ctx.easing, |easing| easing as &dyn Easing);
+        let perceived = PerceptualMapper::new(saved_easing, saved_max).perceived(saved.value);
+        PerceptualMapper::new(ctx.easing, ctx.device.max()).level(perceived)
     }
 
-    pub fn write_brightness(device_name: &str, brightness: u16) -> Result<PathBuf, WriteError> {
-        let path = device_restore_path(device_name);
+    fn device_restore_path(class: DeviceClass, name: &str) -> PathBuf {
+        state::state_dir().join(qualified_name(class, name))
+    }
+
+    pub fn write_brightness(
+        device: &dyn BrightnessRead,
+        brightness: u32,
+        easing: &EasingKind,
+        expires: Option<Duration>,
+    ) -> Result<PathBuf, WriteError> {
+        let path = device_restore_path(device.class(), device.name().unwrap_or(UNNAMED));
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent).map_err(WriteError::DirCreate)?;
         }
 
-        let mut file = File::create(&path).map_err(WriteError::FileCreate)?;
-        let content = brightness.to_string();
-        let content = content.as_bytes();
-        match file.write_all(content) {
-            Ok(()) => Ok(path),
-            Err(err) => Err(WriteError::FileWrite(err)),
+        let mut saved = SavedBrightness::new(brightness, easing).with_max(device.max());
+        if let Some(expires) = expires {
+            saved = saved.expiring(expires);
         }
+        state::write_atomic(&path, &saved.to_string()).map_err(WriteError::FileWrite)?;
+        Ok(path)
     }
 
     #[derive(Debug, Error)]
     pub enum WriteError {
         #[error("error at directory creation: {_0}")]
         DirCreate(#[source] io::Error),
-        #[error("error at file creation: {_0}")]
-        FileCreate(#[source] io::Error),
         #[error("error when writing to file: {_0}")]
         FileWrite(#[source] io::Error),
     }
@@ -244,4 +388,11 @@ mod tests {
         assert!(ArgumentCount::new(0, Some(0)).valid(0));
         assert!(ArgumentCount::new(2, None).valid(4));
     }
+
+    #[test]
+    fn test_names() {
+        for name in NAMES {
+            assert_eq!(get_function(name).unwrap().name(), name);
+        }
+    }
 }
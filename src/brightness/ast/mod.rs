@@ -1,33 +1,93 @@
 pub mod functions;
 
 use super::lexer::Token;
-use super::lexer::{TokenCategory, UnsupportedCharError, lexer};
+use super::lexer::{LexerError, TokenCategory, lexer};
+use super::perceptual::PerceptualMapper;
 use crate::{
     animation::easing::Easing,
-    device::{Device, errors::DeviceReadError},
+    device::{BrightnessRead, errors::DeviceReadError},
+    state::StateReadError,
+};
+use derive_more::Display;
+use std::{
+    cell::{OnceCell, RefCell},
+    fmt,
+    iter::Peekable,
+    path::PathBuf,
+    str::FromStr,
 };
-use std::{iter::Peekable, path::PathBuf, str::FromStr};
 use thiserror::Error;
 
-#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+/// How deeply function calls may be nested by default, like `max(min(…))`
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Displayed as the suffix it's written with
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum ChangeDirection {
+    #[display("+")]
     Inc,
     #[default]
+    #[display("")]
     Abs,
+    #[display("-")]
     Dec,
 }
 
+/// With the `serde` feature an expression is encoded as its tree, which unlike the parser doesn't
+/// limit how deeply functions are nested
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Ast {
     Literal {
         direction: ChangeDirection,
-        value: u16,
+        value: u32,
         percent: bool,
     },
+    /// A raw hardware level written as `level:N`, which unlike a plain number must exist
+    Level {
+        direction: ChangeDirection,
+        value: u32,
+    },
+    /// A percentage of the hardware maximum written as `N%%`, which ignores the easing
+    RawPercent {
+        direction: ChangeDirection,
+        value: u32,
+    },
     Function {
         name: String,
         arguments: Vec<Ast>,
     },
+    /// A quoted string, which only functions like `restore("intel_backlight")` accept
+    Text(String),
+}
+
+/// Written the way it's parsed, so it can be parsed again
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Literal {
+                direction,
+                value,
+                percent,
+            } => write!(f, "{value}{}{direction}", if *percent { "%" } else { "" }),
+            Self::Level { direction, value } => write!(f, "level:{value}{direction}"),
+            Self::RawPercent { direction, value } => write!(f, "{value}%%{direction}"),
+            Self::Function { name, arguments } => {
+                write!(f, "{name}(")?;
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{argument}")?;
+                }
+                write!(f, ")")
+            }
+            Self::Text(text) => write!(f, "\"{text}\""),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -51,8 +111,26 @@ pub enum BrightnessEvaluationError {
         min: usize, // Maybe replace these with `ArgumentCount` not totally sure
         max: Option<usize>,
     },
+    #[error("`{_0}` is disabled")]
+    DisabledFunction(String),
+    #[error("\"{_0}\" is text, not a brightness")]
+    TextAsBrightness(String),
+    #[error("invalid argument for `{function}`: {reason}")]
+    InvalidArgument {
+        function: &'static str,
+        reason: String,
+    },
+    #[error("level {level} doesn't exist, the device only has levels 0-{max}")]
+    LevelOutOfRange { level: u32, max: u32 },
     #[error("file {} doesn't exist", _0.display())]
     MissingFile(PathBuf),
+    #[error("the brightness saved in {} expired {ago} ago", path.display())]
+    ExpiredSave {
+        path: PathBuf,
+        ago: humantime::Duration,
+    },
+    #[error("can't use the saved brightness: {_0}")]
+    SavedState(#[source] StateReadError),
     #[error("a general error occured")]
     Other(
         #[source]
@@ -88,15 +166,164 @@ pub enum ParseTokensError {
     },
     #[error("Unclosed delimiter")]
     UnclosedDelimiter,
+    #[error("functions are nested deeper than {max} levels")]
+    TooDeep { max: usize },
+}
+
+/// Values that were coerced during an evaluation instead of being used as is
+#[derive(Debug, Clone, PartialEq, Display)]
+pub enum EvalWarning {
+    #[display("{current} + {value} is saturated at the maximum of {max}")]
+    SaturatedMax { current: u32, value: u32, max: u32 },
+    #[display("{current} - {value} is saturated at 0")]
+    SaturatedZero { current: u32, value: u32 },
+    #[display("{requested}% is clamped to {clamped}%")]
+    ClampedPercent { requested: f64, clamped: f64 },
+    #[display("the restored brightness was saved {age} ago")]
+    StaleRestore { age: humantime::Duration },
+}
+
+/// A single evaluated expression, see [`EvalContext::traced`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceStep {
+    /// How deeply it's nested in function calls
+    pub depth: usize,
+    pub expression: String,
+    /// `None` if it failed
+    pub value: Option<u32>,
+    /// What happened while evaluating it, like warnings or the brightness that was read
+    pub notes: Vec<String>,
+}
+
+#[derive(Default)]
+struct Trace {
+    steps: Vec<TraceStep>,
+    /// The steps being evaluated, the innermost last
+    open: Vec<usize>,
+}
+
+/// The state of a single evaluation, device reads are memoized so the brightness is read at most
+/// once no matter how often it is needed
+pub struct EvalContext<'a> {
+    pub device: &'a dyn BrightnessRead,
+    pub easing: &'a dyn Easing,
+    current: OnceCell<u32>,
+    warnings: RefCell<Vec<EvalWarning>>,
+    trace: Option<RefCell<Trace>>,
+}
+
+impl<'a> EvalContext<'a> {
+    pub fn new(device: &'a dyn BrightnessRead, easing: &'a dyn Easing) -> Self {
+        Self {
+            device,
+            easing,
+            current: OnceCell::new(),
+            warnings: RefCell::default(),
+            trace: None,
+        }
+    }
+
+    /// Records every evaluated expression, its value and notes about it, see [`Self::trace`]
+    pub fn traced(self) -> Self {
+        Self {
+            trace: Some(RefCell::default()),
+            ..self
+        }
+    }
+
+    /// The evaluated expressions in the order they were started, empty unless [`Self::traced`]
+    pub fn trace(&self) -> Vec<TraceStep> {
+        self.trace
+            .as_ref()
+            .map_or_else(Vec::new, |trace| trace.borrow().steps.clone())
+    }
+
+    /// Attaches a note to the expression being evaluated if it's traced
+    pub fn note(&self, note: impl FnOnce() -> String) {
+        if let Some(trace) = &self.trace {
+            let mut trace = trace.borrow_mut();
+            if let Some(&index) = trace.open.last() {
+                trace.steps[index].notes.push(note());
+            }
+        }
+    }
+
+    /// Evaluates relative brightnesses based on `current` instead of the device's brightness
+    pub fn with_current(self, current: u32) -> Self {
+        Self {
+            current: OnceCell::from(current),
+            ..self
+        }
+    }
+
+    pub fn warn(&self, warning: EvalWarning) {
+        self.note(|| warning.to_string());
+        self.warnings.borrow_mut().push(warning);
+    }
+
+    pub fn into_warnings(self) -> Vec<EvalWarning> {
+        self.warnings.into_inner()
+    }
+
+    pub fn current(&self) -> Result<u32, DeviceReadError> {
+        if let Some(current) = self.current.get() {
+            return Ok(*current);
+        }
+
+        let current = self.device.current()?;
+        self.note(|| format!("the device's brightness is {current}"));
+        Ok(*self.current.get_or_init(|| current))
+    }
 }
 
 impl Ast {
     pub fn evaluate(
         &self,
-        device: &dyn Device,
+        device: &dyn BrightnessRead,
+        easing: &dyn Easing,
+    ) -> Result<u32, BrightnessEvaluationError> {
+        self.evaluate_in(&EvalContext::new(device, easing))
+    }
+
+    /// Evaluates the brightness and reports all values that had to be coerced
+    pub fn evaluate_with_warnings(
+        &self,
+        device: &dyn BrightnessRead,
         easing: &dyn Easing,
-    ) -> Result<u16, BrightnessEvaluationError> {
-        let current = device.current()?;
+    ) -> Result<(u32, Vec<EvalWarning>), BrightnessEvaluationError> {
+        let ctx = EvalContext::new(device, easing);
+        let value = self.evaluate_in(&ctx)?;
+        Ok((value, ctx.into_warnings()))
+    }
+
+    pub fn evaluate_in(&self, ctx: &EvalContext) -> Result<u32, BrightnessEvaluationError> {
+        let Some(trace) = &ctx.trace else {
+            return self.evaluate_untraced(ctx);
+        };
+
+        let index = {
+            let mut trace = trace.borrow_mut();
+            let depth = trace.open.len();
+            trace.steps.push(TraceStep {
+                depth,
+                expression: self.to_string(),
+                value: None,
+                notes: Vec::new(),
+            });
+            let index = trace.steps.len() - 1;
+            trace.open.push(index);
+            index
+        };
+        let result = self.evaluate_untraced(ctx);
+
+        let mut trace = trace.borrow_mut();
+        trace.open.pop();
+        trace.steps[index].value = result.as_ref().ok().copied();
+        result
+    }
+
+    fn evaluate_untraced(&self, ctx: &EvalContext) -> Result<u32, BrightnessEvaluationError> {
+        let EvalContext { device, easing, .. } = *ctx;
 
         match self {
             Self::Literal {
@@ -104,18 +331,24 @@ impl Ast {
                 value,
                 percent: true,
             } => {
-                let max = f64::from(device.max());
+                let mapper = PerceptualMapper::new(easing, device.max());
 
-                let current = easing.from_actual(f64::from(current) / max);
                 let value = f64::from(*value) / 100.0;
 
-                let new_perceived = match direction {
-                    ChangeDirection::Inc => (current + value).clamp(0.0, 1.0),
-                    ChangeDirection::Dec => (current - value).clamp(0.0, 1.0),
+                let requested = match direction {
+                    ChangeDirection::Inc => mapper.perceived(ctx.current()?) + value,
+                    ChangeDirection::Dec => mapper.perceived(ctx.current()?) - value,
                     ChangeDirection::Abs => value,
                 };
-                let new_actual = easing.to_actual(new_perceived);
-                Ok((new_actual * max) as u16)
+                let new_perceived = requested.clamp(0.0, 1.0);
+                if new_perceived != requested {
+                    ctx.warn(EvalWarning::ClampedPercent {
+                        requested: requested * 100.0,
+                        clamped: new_perceived * 100.0,
+                    });
+                }
+
+                Ok(mapper.level(new_perceived))
             }
             Self::Literal {
                 direction,
@@ -126,11 +359,54 @@ impl Ast {
                 let value = *value;
 
                 Ok(match direction {
-                    ChangeDirection::Inc => current.saturating_add(value).min(max),
-                    ChangeDirection::Dec => current.saturating_sub(value),
+                    ChangeDirection::Inc => {
+                        let current = ctx.current()?;
+                        let new = current.saturating_add(value).min(max);
+                        if u64::from(current) + u64::from(value) > u64::from(max) {
+                            ctx.warn(EvalWarning::SaturatedMax {
+                                current,
+                                value,
+                                max,
+                            });
+                        }
+                        new
+                    }
+                    ChangeDirection::Dec => {
+                        let current = ctx.current()?;
+                        if value > current {
+                            ctx.warn(EvalWarning::SaturatedZero { current, value });
+                        }
+                        current.saturating_sub(value)
+                    }
                     ChangeDirection::Abs => value,
                 })
             }
+            Self::Level { direction, value } => {
+                if *direction == ChangeDirection::Abs && *value > device.max() {
+                    return Err(BrightnessEvaluationError::LevelOutOfRange {
+                        level: *value,
+                        max: device.max(),
+                    });
+                }
+
+                Self::Literal {
+                    direction: *direction,
+                    value: *value,
+                    percent: false,
+                }
+                .evaluate_untraced(ctx)
+            }
+            Self::RawPercent { direction, value } => {
+                let max = u64::from(device.max());
+                let value = (max * u64::from(*value) + 50) / 100;
+
+                Self::Literal {
+                    direction: *direction,
+                    value: u32::try_from(value).unwrap_or(u32::MAX),
+                    percent: false,
+                }
+                .evaluate_untraced(ctx)
+            }
             Self::Function { name, arguments } => {
                 let Some(f) = functions::get_function(name.as_str()) else {
                     return Err(BrightnessEvaluationError::UnsupportedFunction(
@@ -148,12 +424,56 @@ impl Ast {
                     });
                 }
 
-                f.call(arguments, device, easing)
+                f.call(arguments, ctx)
             }
+            Self::Text(text) => Err(BrightnessEvaluationError::TextAsBrightness(text.clone())),
+        }
+    }
+
+    /// Checks every function call against the policy before anything is evaluated
+    pub fn check_functions(
+        &self,
+        policy: &functions::FunctionPolicy,
+    ) -> Result<(), BrightnessEvaluationError> {
+        let Self::Function { name, arguments } = self else {
+            return Ok(());
+        };
+
+        if let Some(f) = functions::get_function(name)
+            && !policy.allows(&*f)
+        {
+            return Err(BrightnessEvaluationError::DisabledFunction(name.clone()));
         }
+
+        arguments
+            .iter()
+            .try_for_each(|argument| argument.check_functions(policy))
     }
 
     pub fn parse_tokens<I>(tokens: &mut Peekable<I>) -> Result<Self, ParseTokensError>
+    where
+        I: Iterator<Item = Token>,
+    {
+        Self::parse_tokens_limited(tokens, DEFAULT_MAX_DEPTH)
+    }
+
+    /// Parses the tokens with at most `max_depth` nested function calls, which keeps untrusted
+    /// input from overflowing the stack
+    pub fn parse_tokens_limited<I>(
+        tokens: &mut Peekable<I>,
+        max_depth: usize,
+    ) -> Result<Self, ParseTokensError>
+    where
+        I: Iterator<Item = Token>,
+    {
+        Self::parse_at_depth(tokens, 0, max_depth)
+    }
+
+    fn parse_at_depth<I>(
+        tokens: &mut Peekable<I>,
+        depth: usize,
+        max_depth: usize,
+    ) -> Result<Self, ParseTokensError>
     where
         I: Iterator<Item = Token>,
     {
@@ -163,19 +483,35 @@ impl Ast {
                 if percent {
                     tokens.next();
                 }
-
-                let direction = match tokens.peek() {
-                    Some(Token::Plus) => ChangeDirection::Inc,
-                    Some(Token::Minus) => ChangeDirection::Dec,
-                    _ => ChangeDirection::default(),
-                };
+                if percent && tokens.next_if_eq(&Token::Percent).is_some() {
+                    return Ok(Self::RawPercent {
+                        direction: Self::parse_direction(tokens),
+                        value,
+                    });
+                }
 
                 Ok(Self::Literal {
-                    direction,
+                    direction: Self::parse_direction(tokens),
                     value,
                     percent,
                 })
             }
+            Token::Str(text) => Ok(Self::Text(text)),
+            Token::Identifier(name) if name == "level" && tokens.peek() == Some(&Token::Colon) => {
+                tokens.next();
+                match tokens.next() {
+                    Some(Token::Number(value)) => Ok(Self::Level {
+                        direction: Self::parse_direction(tokens),
+                        value,
+                    }),
+                    Some(encountered) => Err(ParseTokensError::IllegalToken {
+                        expected: Some((TokenCategory::Standalone, Some(Token::Number(0)))),
+                        encountered,
+                        reason: Some("Levels are written as `level:N`".to_string()),
+                    }),
+                    None => Err(ParseTokensError::NoTokens),
+                }
+            }
             Token::Identifier(name) => {
                 match tokens.peek() {
                     None => {
@@ -200,6 +536,9 @@ impl Ast {
                     }
                 }
 
+                if depth >= max_depth {
+                    return Err(ParseTokensError::TooDeep { max: max_depth });
+                }
                 let mut arguments = Vec::new();
 
                 let mut indent_level = 1;
@@ -218,8 +557,12 @@ impl Ast {
                         break;
                     }
 
-                    if tok == Token::Comma {
-                        arguments.push(Self::parse_tokens(&mut arg_tokens.into_iter().peekable())?);
+                    if tok == Token::Comma && indent_level == 1 {
+                        arguments.push(Self::parse_at_depth(
+                            &mut arg_tokens.into_iter().peekable(),
+                            depth + 1,
+                            max_depth,
+                        )?);
                         arg_tokens = Vec::new();
                     } else {
                         arg_tokens.push(tok);
@@ -228,7 +571,11 @@ impl Ast {
 
                 if !(arguments.is_empty() && arg_tokens.is_empty()) {
                     // for zero argument functions
-                    arguments.push(Self::parse_tokens(&mut arg_tokens.into_iter().peekable())?);
+                    arguments.push(Self::parse_at_depth(
+                        &mut arg_tokens.into_iter().peekable(),
+                        depth + 1,
+                        max_depth,
+                    )?);
                 }
 
                 Ok(Self::Function { name, arguments })
@@ -242,13 +589,26 @@ impl Ast {
     }
 }
 
+impl Ast {
+    fn parse_direction<I>(tokens: &mut Peekable<I>) -> ChangeDirection
+    where
+        I: Iterator<Item = Token>,
+    {
+        match tokens.peek() {
+            Some(Token::Plus) => ChangeDirection::Inc,
+            Some(Token::Minus) => ChangeDirection::Dec,
+            _ => ChangeDirection::default(),
+        }
+    }
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum AstFromStrError {
     #[error("{_0}")]
     LexerError(
         #[from]
         #[source]
-        UnsupportedCharError,
+        LexerError,
     ),
     #[error("{_0}")]
     TokenParseError(
@@ -262,8 +622,15 @@ impl FromStr for Ast {
     type Err = AstFromStrError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_limited(s, DEFAULT_MAX_DEPTH)
+    }
+}
+
+impl Ast {
+    /// Parses an expression with at most `max_depth` nested function calls
+    pub fn parse_limited(s: &str, max_depth: usize) -> Result<Self, AstFromStrError> {
         let tokens = lexer(s)?;
-        let ast = Ast::parse_tokens(&mut tokens.into_iter().peekable())?;
+        let ast = Ast::parse_tokens_limited(&mut tokens.into_iter().peekable(), max_depth)?;
         Ok(ast)
     }
 }
@@ -280,34 +647,75 @@ impl Default for Ast {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{animation::easing::EasingKind, meta::Meta};
+    use crate::{
+        animation::easing::EasingKind,
+        brightness::{ast::functions::FunctionPolicy, lexer::UnsupportedCharError},
+    };
 
     struct TestDevice {
-        max: u16,
-        current: u16,
+        max: u32,
+        current: u32,
     }
 
-    impl Meta for TestDevice {
-        fn meta(&self, _: &crate::config::Easings) -> Vec<crate::meta::Information> {
-            vec![]
+    struct CountingDevice {
+        reads: std::cell::Cell<usize>,
+    }
+
+    impl BrightnessRead for CountingDevice {
+        fn name(&self) -> Option<&str> {
+            None
         }
+
+        fn class(&self) -> crate::device::DeviceClass {
+            crate::device::DeviceClass::Backlight
+        }
+
+        fn max(&self) -> u32 {
+            100
+        }
+
+        fn current(&self) -> Result<u32, crate::device::errors::DeviceReadError> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(50)
+        }
+    }
+
+    #[test]
+    fn test_current_is_read_once() {
+        let dev = CountingDevice {
+            reads: std::cell::Cell::new(0),
+        };
+        let value = "max(current, 10+, clamp(5%-, current(), 20%+))"
+            .parse::<Ast>()
+            .unwrap()
+            .evaluate(&dev, &EasingKind::Linear)
+            .unwrap();
+
+        assert_eq!(value, 60);
+        assert_eq!(dev.reads.get(), 1);
+
+        "50".parse::<Ast>()
+            .unwrap()
+            .evaluate(&dev, &EasingKind::Linear)
+            .unwrap();
+        assert_eq!(dev.reads.get(), 1);
     }
 
-    impl Device for TestDevice {
+    impl BrightnessRead for TestDevice {
         fn name(&self) -> Option<&str> {
             Some("TestDevice")
         }
 
-        fn max(&self) -> u16 {
-            self.max
+        fn class(&self) -> crate::device::DeviceClass {
+            crate::device::DeviceClass::Backlight
         }
 
-        fn current(&self) -> Result<u16, crate::device::errors::DeviceReadError> {
-            Ok(self.current)
+        fn max(&self) -> u32 {
+            self.max
         }
 
-        fn set(&self, value: u16) -> Result<u16, crate::device::errors::DeviceWriteError<u16>> {
-            Ok(value.min(self.max))
+        fn current(&self) -> Result<u32, crate::device::errors::DeviceReadError> {
+            Ok(self.current)
         }
     }
 
@@ -389,6 +797,221 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_large_max() {
+        // intel_backlight often has a maximum above 65535
+        let dev = TestDevice {
+            max: 120_000,
+            current: 100_000,
+        };
+        let eval = |s: &str| {
+            s.parse::<Ast>()
+                .unwrap()
+                .evaluate(&dev, &EasingKind::Linear)
+                .unwrap()
+        };
+
+        assert_eq!(eval("50%"), 60_000);
+        assert_eq!(eval("70000"), 70_000);
+        assert_eq!(eval("30000+"), 120_000);
+        assert_eq!(eval("10%-"), 88_000);
+    }
+
+    #[test]
+    fn test_raw_percent() {
+        let dev = TestDevice {
+            max: 1000,
+            current: 500,
+        };
+        let easing: EasingKind = "x^2".parse().unwrap();
+        let eval = |s: &str| s.parse::<Ast>().unwrap().evaluate(&dev, &easing).unwrap();
+
+        assert_eq!(
+            "25%%+".parse::<Ast>().unwrap(),
+            Ast::RawPercent {
+                direction: ChangeDirection::Inc,
+                value: 25
+            }
+        );
+        assert_eq!(eval("50%%"), 500);
+        assert_eq!(eval("50%"), 250);
+        assert_eq!(eval("10%%-"), 400);
+        assert_eq!(eval("max(20%%, 1)"), 200);
+    }
+
+    #[test]
+    fn test_levels() {
+        let dev = TestDevice { max: 2, current: 1 };
+        let eval = |s: &str| {
+            s.parse::<Ast>()
+                .unwrap()
+                .evaluate(&dev, &EasingKind::Linear)
+        };
+
+        assert_eq!(
+            "level:2".parse::<Ast>().unwrap(),
+            Ast::Level {
+                direction: ChangeDirection::Abs,
+                value: 2
+            }
+        );
+        assert_eq!(eval("level:2").unwrap(), 2);
+        assert_eq!(eval("level:1-").unwrap(), 0);
+        assert_eq!(eval("max(level:0, 50%)").unwrap(), 1);
+        assert!(matches!(
+            eval("level:3"),
+            Err(BrightnessEvaluationError::LevelOutOfRange { level: 3, max: 2 })
+        ));
+        assert!("level:".parse::<Ast>().is_err());
+    }
+
+    #[test]
+    fn test_with_current() {
+        let dev = CountingDevice {
+            reads: std::cell::Cell::new(0),
+        };
+        let ctx = EvalContext::new(&dev, &EasingKind::Linear).with_current(70);
+        assert_eq!(
+            "10%+".parse::<Ast>().unwrap().evaluate_in(&ctx).unwrap(),
+            80
+        );
+        assert_eq!(dev.reads.get(), 0);
+    }
+
+    #[test]
+    fn test_warnings() {
+        let dev = TestDevice {
+            max: 1_000,
+            current: 900,
+        };
+
+        assert_eq!(
+            "200+"
+                .parse::<Ast>()
+                .unwrap()
+                .evaluate_with_warnings(&dev, &EasingKind::Linear)
+                .unwrap(),
+            (
+                1_000,
+                vec![EvalWarning::SaturatedMax {
+                    current: 900,
+                    value: 200,
+                    max: 1_000
+                }]
+            )
+        );
+
+        assert_eq!(
+            "max(1000-, 20%+)"
+                .parse::<Ast>()
+                .unwrap()
+                .evaluate_with_warnings(&dev, &EasingKind::Linear)
+                .unwrap(),
+            (
+                1_000,
+                vec![
+                    EvalWarning::SaturatedZero {
+                        current: 900,
+                        value: 1_000
+                    },
+                    EvalWarning::ClampedPercent {
+                        requested: 110.00000000000001,
+                        clamped: 100.0
+                    }
+                ]
+            )
+        );
+
+        assert_eq!(
+            "50%"
+                .parse::<Ast>()
+                .unwrap()
+                .evaluate_with_warnings(&dev, &EasingKind::Linear)
+                .unwrap(),
+            (500, vec![])
+        );
+    }
+
+    #[test]
+    fn test_blend() {
+        let dev = TestDevice {
+            max: 1_000,
+            current: 500,
+        };
+
+        assert_eq!(
+            "blend(0, 1000, 50%)"
+                .parse::<Ast>()
+                .unwrap()
+                .evaluate(&dev, &EasingKind::Linear)
+                .unwrap(),
+            500
+        );
+
+        assert_eq!(
+            "blend(current, 100%, 0%)"
+                .parse::<Ast>()
+                .unwrap()
+                .evaluate(&dev, &EasingKind::Linear)
+                .unwrap(),
+            500
+        );
+
+        // halfway in perceptual space is a quarter of the actual brightness for `x^2`
+        assert_eq!(
+            "blend(0, 100%, 50%)"
+                .parse::<Ast>()
+                .unwrap()
+                .evaluate(&dev, &"x^2".parse::<EasingKind>().unwrap())
+                .unwrap(),
+            250
+        );
+
+        // as a level of 4 the factor 70% would be rounded to 75%, which blends to 1.5 instead of 1.4
+        let coarse = TestDevice { max: 4, current: 2 };
+        for factor in ["70%", "clamp(70%, 0, 100%)", "max(70%, 10%)", "20%+"] {
+            assert_eq!(
+                format!("blend(0, 2, {factor})")
+                    .parse::<Ast>()
+                    .unwrap()
+                    .evaluate(&coarse, &EasingKind::Linear)
+                    .unwrap(),
+                1,
+                "{factor}"
+            );
+        }
+        // the current brightness is a factor of 50%
+        assert_eq!(
+            "blend(0, 4, current)"
+                .parse::<Ast>()
+                .unwrap()
+                .evaluate(&coarse, &EasingKind::Linear)
+                .unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_check_functions() {
+        let ast = "max(clamp(1, restore, 100), 5)".parse::<Ast>().unwrap();
+
+        assert!(ast.check_functions(&FunctionPolicy::default()).is_ok());
+        assert!(matches!(
+            ast.check_functions(&FunctionPolicy {
+                disabled: vec![],
+                sandbox: true
+            }),
+            Err(BrightnessEvaluationError::DisabledFunction(name)) if name == "restore"
+        ));
+        assert!(matches!(
+            ast.check_functions(&FunctionPolicy {
+                disabled: vec!["clamp".to_string()],
+                sandbox: false
+            }),
+            Err(BrightnessEvaluationError::DisabledFunction(name)) if name == "clamp"
+        ));
+    }
+
     #[test]
     fn test_ast_fail() {
         let dev = TestDevice {
@@ -487,10 +1110,10 @@ mod tests {
 
         assert_eq!(
             "cl@mp()".parse::<Ast>().unwrap_err(),
-            AstFromStrError::LexerError(UnsupportedCharError {
+            AstFromStrError::LexerError(LexerError::UnsupportedChar(UnsupportedCharError {
                 char: '@',
                 index: 2
-            })
+            }))
         );
 
         assert_eq!(
@@ -498,6 +1121,16 @@ mod tests {
             AstFromStrError::TokenParseError(ParseTokensError::UnclosedDelimiter)
         );
 
+        let nested = format!("{}1{}", "max(".repeat(10_000), ")".repeat(10_000));
+        assert_eq!(
+            nested.parse::<Ast>().unwrap_err(),
+            AstFromStrError::TokenParseError(ParseTokensError::TooDeep {
+                max: DEFAULT_MAX_DEPTH
+            })
+        );
+        assert!(Ast::parse_limited("max(min(1))", 2).is_ok());
+        assert!(Ast::parse_limited("max(min(1))", 1).is_err());
+
         assert!(matches!(
             "max 2".parse::<Ast>().unwrap_err(),
             AstFromStrError::TokenParseError(ParseTokensError::IllegalToken {
@@ -512,4 +1145,82 @@ mod tests {
             AstFromStrError::TokenParseError(ParseTokensError::NoTokens)
         ));
     }
+
+    #[test]
+    fn test_display() {
+        for expression in [
+            "10%+",
+            "50",
+            "level:3-",
+            "40%%",
+            "max(5%, clamp(1, current(), 20%+), restore(\"tpacpi::kbd light\"))",
+        ] {
+            let ast: Ast = expression.parse().unwrap();
+            assert_eq!(ast.to_string(), expression);
+            assert_eq!(ast.to_string().parse::<Ast>().unwrap(), ast);
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let dev = TestDevice {
+            max: 100,
+            current: 50,
+        };
+        let ctx = EvalContext::new(&dev, &EasingKind::Linear).traced();
+        let value = "clamp(10, 30%+, 70)"
+            .parse::<Ast>()
+            .unwrap()
+            .evaluate_in(&ctx)
+            .unwrap();
+        assert_eq!(value, 70);
+
+        let steps: Vec<_> = ctx
+            .trace()
+            .into_iter()
+            .map(|step| (step.depth, step.expression, step.value, step.notes))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                (
+                    0,
+                    "clamp(10, 30%+, 70)".to_string(),
+                    Some(70),
+                    vec!["80 is clamped to 70".to_string()]
+                ),
+                (1, "10".to_string(), Some(10), vec![]),
+                (
+                    1,
+                    "30%+".to_string(),
+                    Some(80),
+                    vec!["the device's brightness is 50".to_string()]
+                ),
+                (1, "70".to_string(), Some(70), vec![]),
+            ]
+        );
+
+        let untraced = EvalContext::new(&dev, &EasingKind::Linear);
+        "current"
+            .parse::<Ast>()
+            .unwrap()
+            .evaluate_in(&untraced)
+            .unwrap();
+        assert!(untraced.trace().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let ast: Ast = "max(5%, restore(\"intel_backlight\"))".parse().unwrap();
+        let json = serde_json::to_value(&ast).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"function": {"name": "max", "arguments": [
+                {"literal": {"direction": "abs", "value": 5, "percent": true}},
+                {"function": {"name": "restore", "arguments": [{"text": "intel_backlight"}]}},
+            ]}})
+        );
+        assert_eq!(serde_json::from_value::<Ast>(json).unwrap(), ast);
+    }
 }
@@ -7,12 +7,15 @@ pub enum Token {
     RightParentheses,
     Percent,
     Comma,
+    Colon,
     Plus,
     Minus,
 
-    Number(u16),
+    Number(u32),
 
     Identifier(String),
+    /// Text in double quotes, like a device name
+    Str(String),
 }
 
 impl Token {
@@ -22,6 +25,7 @@ impl Token {
             ')' => Some(Self::RightParentheses),
             '%' => Some(Self::Percent),
             ',' => Some(Self::Comma),
+            ':' => Some(Self::Colon),
             '+' => Some(Self::Plus),
             '-' => Some(Self::Minus),
             _ => None,
@@ -34,17 +38,19 @@ impl Token {
             Self::RightParentheses => "`)`",
             Self::Percent => "`%`",
             Self::Comma => "`,`",
+            Self::Colon => "`:`",
             Self::Plus => "`+`",
             Self::Minus => "`-`",
             Self::Number(_) => "number",
             Self::Identifier(_) => "identifier",
+            Self::Str(_) => "string",
         }
     }
 }
 
 #[derive(Debug, Display, Clone, Copy, PartialEq)]
 pub enum TokenCategory {
-    /// Tokens that can work on their own, numbers and identifiers
+    /// Tokens that can work on their own, numbers, identifiers and strings
     #[display("Standalone")]
     Standalone,
     /// Tokens that can modify the exact meaning of a Standalone Token, for example `%`
@@ -58,9 +64,11 @@ pub enum TokenCategory {
 impl From<Token> for TokenCategory {
     fn from(value: Token) -> Self {
         match value {
-            Token::Number(_) | Token::Identifier(_) => Self::Standalone,
+            Token::Number(_) | Token::Identifier(_) | Token::Str(_) => Self::Standalone,
             Token::Percent | Token::Plus | Token::Minus => Self::Supportive,
-            Token::Comma | Token::LeftParentheses | Token::RightParentheses => Self::Grammar,
+            Token::Comma | Token::Colon | Token::LeftParentheses | Token::RightParentheses => {
+                Self::Grammar
+            }
         }
     }
 }
@@ -72,22 +80,50 @@ pub struct UnsupportedCharError {
     pub index: usize,
 }
 
-pub fn lexer<S>(str: S) -> Result<Vec<Token>, UnsupportedCharError>
+#[derive(Error, Debug, PartialEq)]
+pub enum LexerError {
+    #[error("{_0}")]
+    UnsupportedChar(
+        #[from]
+        #[source]
+        UnsupportedCharError,
+    ),
+    #[error("the string starting at {index} isn't closed")]
+    UnclosedString { index: usize },
+    #[error("the number at {index} is larger than {}", u32::MAX)]
+    NumberTooLarge { index: usize },
+}
+
+pub fn lexer<S>(str: S) -> Result<Vec<Token>, LexerError>
 where
     S: AsRef<str>,
 {
     let mut tokens = Vec::new();
     let mut new_token_starts = true;
 
-    for (i, c) in str.as_ref().chars().enumerate() {
-        if let Some(atomic) = Token::new_atomic(c) {
+    let mut chars = str.as_ref().chars().enumerate();
+    while let Some((i, c)) = chars.next() {
+        if c == '"' {
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, c)) => string.push(c),
+                    None => return Err(LexerError::UnclosedString { index: i }),
+                }
+            }
+            tokens.push(Token::Str(string));
+        } else if let Some(atomic) = Token::new_atomic(c) {
             tokens.push(atomic);
         } else if let Some(digit) = c.to_digit(10) {
             match tokens.last_mut() {
                 Some(Token::Number(last)) if !new_token_starts => {
-                    *last = *last * 10 + digit as u16;
+                    *last = last
+                        .checked_mul(10)
+                        .and_then(|last| last.checked_add(digit))
+                        .ok_or(LexerError::NumberTooLarge { index: i })?;
                 }
-                _ => tokens.push(Token::Number(digit as u16)),
+                _ => tokens.push(Token::Number(digit)),
             }
         } else if (c.is_ascii() && c.is_alphabetic()) || c == '_' {
             match tokens.last_mut() {
@@ -97,7 +133,7 @@ where
                 _ => tokens.push(Token::Identifier(c.to_string())),
             }
         } else if !c.is_whitespace() {
-            return Err(UnsupportedCharError { char: c, index: i });
+            return Err(UnsupportedCharError { char: c, index: i }.into());
         }
 
         new_token_starts = c.is_whitespace();
@@ -144,5 +180,31 @@ mod tests {
                 To::RightParentheses,
             ]
         );
+        assert_eq!(
+            lexer("level:2+").unwrap(),
+            vec![
+                To::Identifier(String::from("level")),
+                To::Colon,
+                To::Number(2),
+                To::Plus,
+            ]
+        );
+        assert_eq!(
+            lexer(r#"restore("tpacpi::kbd light")"#).unwrap(),
+            vec![
+                To::Identifier(String::from("restore")),
+                To::LeftParentheses,
+                To::Str(String::from("tpacpi::kbd light")),
+                To::RightParentheses,
+            ]
+        );
+        assert_eq!(
+            lexer("4294967295 4294967296"),
+            Err(LexerError::NumberTooLarge { index: 20 })
+        );
+        assert_eq!(
+            lexer(r#"restore("foo"#),
+            Err(LexerError::UnclosedString { index: 8 })
+        );
     }
 }
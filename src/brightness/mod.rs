@@ -1,2 +1,3 @@
 pub mod ast;
 pub mod lexer;
+pub mod perceptual;
@@ -0,0 +1,100 @@
+//! The conversion between hardware levels and the perceived brightness. Everything which reports
+//! or accepts percentages goes through [`PerceptualMapper`], so setting a reported percentage
+//! always results in the level it was reported for
+
+use crate::animation::easing::Easing;
+
+#[derive(Clone, Copy)]
+pub struct PerceptualMapper<'a> {
+    easing: &'a dyn Easing,
+    max: u32,
+}
+
+impl<'a> PerceptualMapper<'a> {
+    pub fn new(easing: &'a dyn Easing, max: u32) -> Self {
+        Self { easing, max }
+    }
+
+    /// The perceived brightness of a hardware level in `0.0..=1.0`
+    pub fn perceived(&self, level: u32) -> f64 {
+        if self.max == 0 {
+            return 0.0;
+        }
+        self.easing
+            .from_actual(f64::from(level) / f64::from(self.max))
+    }
+
+    pub fn percent(&self, level: u32) -> f64 {
+        self.perceived(level) * 100.0
+    }
+
+    /// The nearest hardware level of a perceived brightness, which is clamped to `0.0..=1.0`
+    pub fn level(&self, perceived: f64) -> u32 {
+        let actual = self.easing.to_actual(perceived.clamp(0.0, 1.0));
+        (actual * f64::from(self.max))
+            .round()
+            .clamp(0.0, f64::from(self.max)) as u32
+    }
+
+    pub fn level_of_percent(&self, percent: f64) -> u32 {
+        self.level(percent / 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::easing::EasingKind;
+
+    fn easings() -> Vec<EasingKind> {
+        ["x", "x^2.2", "3^x", "0.9*x + 0.1", "range(0.05, 0.9, x^2)"]
+            .into_iter()
+            .map(|easing| easing.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_level_roundtrip() {
+        for easing in easings() {
+            for max in [1, 7, 100, 255, 1000, 19393, 120_000] {
+                let mapper = PerceptualMapper::new(&easing, max);
+                // easings like `range` don't reach all levels
+                let reachable = mapper.level(0.0)..=mapper.level(1.0);
+                for level in reachable.step_by((max / 100).max(1) as usize) {
+                    assert_eq!(
+                        mapper.level(mapper.perceived(level)),
+                        level,
+                        "{easing} with max {max}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_percent_roundtrip() {
+        for easing in easings() {
+            for max in [1, 7, 100, 1000] {
+                let mapper = PerceptualMapper::new(&easing, max);
+                for percent in 0..=100 {
+                    let level = mapper.level_of_percent(f64::from(percent));
+                    let reported = mapper.percent(level);
+                    assert_eq!(
+                        mapper.level_of_percent(reported),
+                        level,
+                        "{easing} with max {max} at {percent}%"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_clamped() {
+        let easing = EasingKind::default();
+        let mapper = PerceptualMapper::new(&easing, 100);
+        assert_eq!(mapper.level(1.5), 100);
+        assert_eq!(mapper.level(-0.5), 0);
+        assert_eq!(PerceptualMapper::new(&easing, 0).perceived(0), 0.0);
+    }
+}
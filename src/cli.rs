@@ -1,6 +1,21 @@
-use crate::{animation::easing::EasingKind, brightness::ast};
-use clap::{Parser, Subcommand, value_parser};
-use std::{num::NonZero, time::Duration};
+use bright::{
+    animation::easing::EasingKind,
+    brightness::ast,
+    config::{
+        Easings,
+        settings::{DeviceSettings, parse_duration_in},
+    },
+    device::DeviceClass,
+    output::OutputFormat,
+};
+use clap::{Parser, Subcommand, ValueEnum, value_parser};
+use std::{num::NonZero, path::PathBuf, sync::OnceLock, time::Duration};
+
+const DEFAULT_FPS: u16 = 30;
+const MIN_SPEED: f64 = 0.01;
+const MAX_SPEED: f64 = 1000.0;
+/// The long help of every `--device`, some commands add to it
+const DEVICE_HELP: &str = "Choose a device by name\nThis takes precedence over the BRIGHT_DEVICE environment variable\nIf several classes have a device of that name, qualify it with the class like `leds:foo`\nA path like `/sys/class/backlight/foo` uses that directory directly";
 
 #[derive(Parser)]
 pub struct Args {
@@ -9,28 +24,252 @@ pub struct Args {
     /// The easing to map perceived brightness → actual device brightness
     #[arg(
         long,
-        long_help = "The easing to use\nIt maps perceived brightness to the actual brightness, both input and output should be in the interval 0.0..=1.0\nValid inputs look like: `x^2.5` (polynomial), `3.141^x` (exponential) or simply `x` (linear)"
+        value_parser = Easings::from_assignments,
+        long_help = "The easing to use\nIt maps perceived brightness to the actual brightness, both input and output should be in the interval 0.0..=1.0\nValid inputs look like: `x^2.5` (polynomial), `3.141^x` (exponential) or simply `x` (linear)\nA bare easing replaces the configured ones, `intel_backlight=x^2,default=x` only overrides the given devices like lines of the easings file"
+    )]
+    pub easing: Option<Easings>,
+    /// The output format: text, json, csv or waybar
+    #[arg(long, global = true, default_value = "text")]
+    pub format: OutputFormat,
+    /// Disable all functions that access anything besides the device, like `restore`
+    #[arg(long, global = true, default_value_t = false)]
+    pub sandbox: bool,
+    /// Replace an active LED trigger, like `none`, while setting the brightness
+    #[arg(
+        long,
+        global = true,
+        long_help = "Replace an active LED trigger, like `none`, while setting the brightness\nWithout it a trigger like `timer` immediately overrides the written brightness, the previous trigger is activated again afterwards"
     )]
-    pub easing: Option<EasingKind>,
+    pub force_trigger: Option<String>,
+    /// Power a backlight's display off when its brightness reaches 0 and on when it rises again
+    #[arg(
+        long,
+        global = true,
+        long_help = "Power a backlight's display off when its brightness reaches 0 and on when it rises again\nThis writes `bl_power` once the last frame is written and before the first one, the `power_off_at_zero` setting enables it permanently"
+    )]
+    pub power_off_at_zero: bool,
+    /// Go below the `min` setting of a device
+    #[arg(long, global = true)]
+    pub force: bool,
 }
 
 #[derive(Subcommand, Clone)]
 pub enum Command {
     /// List all found devices and show basic information about them
     #[command(alias = "ls", alias = "l")]
-    List,
+    List {
+        /// Show how many discrete hardware levels each device has
+        #[arg(long)]
+        levels: bool,
+        /// List the ambient light sensors instead of the devices
+        #[arg(long, conflicts_with = "levels")]
+        sensors: bool,
+        /// Also show the sysfs entries which were skipped and why
+        #[arg(long, conflicts_with = "sensors")]
+        verbose: bool,
+        /// Only list the devices of a class, like `backlight`
+        #[arg(
+            long,
+            conflicts_with = "sensors",
+            long_help = "Only list the devices of a class, like `backlight`\nWithout it the `default_class` setting is used if it's set"
+        )]
+        class: Option<DeviceClass>,
+        /// List the devices of every class, even if the `default_class` setting is set
+        #[arg(long, conflicts_with_all = ["sensors", "class"])]
+        all_classes: bool,
+        /// Keep running and print backlights and LEDs as they are added or removed
+        #[arg(
+            long,
+            conflicts_with = "sensors",
+            long_help = "Keep running and print backlights and LEDs as they are added or removed\nThis follows the kernel's hotplug events, like a USB keyboard being plugged in"
+        )]
+        follow: bool,
+    },
     /// Show detailed metadata about a selected device
     #[command(aliases = ["metadata", "info"])]
     Meta {
         /// Choose a device by name
         #[arg(
             long,
-            long_help = "Choose a device by name\nThis takes presidence over the BRIGHT_DEVICE environment variable"
+            long_help = DEVICE_HELP
+        )]
+        device: Option<String>,
+        /// Show how many discrete hardware levels the device has
+        #[arg(long)]
+        levels: bool,
+        /// Show an ambient light sensor, like `iio:device0`, instead of a device
+        #[arg(long, conflicts_with_all = ["device", "levels"])]
+        sensor: Option<String>,
+    },
+    /// Show the brightness of a selected device
+    Get {
+        /// Choose a device by name
+        #[arg(
+            long,
+            long_help = DEVICE_HELP
+        )]
+        device: Option<String>,
+        /// Always read the device, even if `cache_ttl` is set
+        #[arg(long)]
+        no_cache: bool,
+    },
+    /// Keep running and print the brightness of a selected device whenever it changes
+    #[command(
+        long_about = "Keep running and print the brightness of a selected device whenever it changes\nThis is meant for custom status bar modules, for example using `--format waybar`"
+    )]
+    Module {
+        /// Choose a device by name
+        #[arg(
+            long,
+            long_help = DEVICE_HELP
+        )]
+        device: Option<String>,
+        /// How often to check for changes if the driver doesn't announce them
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        interval: humantime::Duration,
+    },
+    /// Show which brightness an expression evaluates to without changing the device
+    Eval {
+        /// The expression, like `max(30%, 10%+)`
+        expression: ast::Ast,
+        /// Choose a device by name
+        #[arg(
+            long,
+            long_help = DEVICE_HELP
+        )]
+        device: Option<String>,
+        /// Show every step of the evaluation
+        #[arg(
+            long,
+            long_help = "Show every step of the evaluation\nEvery function call and its arguments are listed with their values, nested ones indented, together with the brightness that was read and values which were clamped"
+        )]
+        explain: bool,
+    },
+    /// Power the display of a backlight on or off without changing its brightness
+    #[command(
+        long_about = "Power the display of a backlight on or off without changing its brightness\nThis writes the backlight's `bl_power`, LEDs and virtual devices don't have one"
+    )]
+    Power {
+        state: PowerState,
+        /// Choose a device by name
+        #[arg(
+            long,
+            long_help = DEVICE_HELP
         )]
         device: Option<String>,
     },
     /// Change the brightness of a selected device
     Set(SetArgs),
+    /// Read one brightness per line from stdin and apply them to a selected device
+    Pipe(PipeArgs),
+    /// Fade in to the saved brightness, meant to be run on login
+    #[command(
+        long_about = "Fade in to the saved brightness, meant to be run on login\nThe target is the `startup_brightness` setting, which defaults to `restore()`, and the duration defaults to the `startup_duration` setting or one second"
+    )]
+    Startup(StartupArgs),
+    /// Print `bright set` commands which recreate the current brightness of all devices
+    #[command(
+        long_about = "Print `bright set` commands which recreate the current brightness of all devices\nThe output is a shell script, or a list of devices using `--format json`, which can be replayed using `bright import`"
+    )]
+    Export,
+    /// Apply the brightnesses written by `bright export`
+    Import(ImportArgs),
+    /// Change all devices listed in a TOML file which aren't at their brightness yet
+    #[command(
+        long_about = "Change all devices listed in a TOML file which aren't at their brightness yet\nEvery entry maps a device to a brightness, like `intel_backlight = \"40%\"`, or to a table with a `brightness` and an optional `duration`\nDevices which already have their brightness aren't touched"
+    )]
+    Apply(ApplyArgs),
+    /// Change the easings file
+    #[command(subcommand)]
+    Easing(EasingCommand),
+    /// Change the settings file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Animate a device back and forth many times and fail if files or memory leak
+    #[command(
+        long_about = "Animate a device back and forth many times and fail if files or memory leak\nEvery cycle fades a quarter of the maximum away from the current brightness and back, the same way `bright set` does, and the brightness is restored at the end\nRun it against a fake tree using BRIGHT_SYSFS_ROOT, or on real hardware to check its drivers"
+    )]
+    Soak(SoakArgs),
+    /// Fade a keyboard backlight out while nothing is typed and back in on the next key
+    #[command(
+        long_about = "Fade a keyboard backlight out while nothing is typed and back in on the next key\nThe brightness before fading out is saved and restored, like `bright set --save` and `restore()` do\nKey presses are read from the keyboards in /dev/input, which requires being in the `input` group"
+    )]
+    Idle(IdleArgs),
+    /// Write every change of the devices' brightness with its time into a JSON file
+    #[command(
+        long_about = "Write every change of the devices' brightness with its time into a JSON file\nThe brightness is polled, the first events are the brightness each device had when the recording started\nIt runs until `--duration` passed or it's interrupted, the file is updated with every change so nothing is lost"
+    )]
+    Record(RecordArgs),
+    /// Apply the changes written by `bright record` at the time they happened
+    Replay(ReplayArgs),
+    /// Show what this build supports, so scripts can adapt to different versions
+    #[command(
+        long_about = "Show what this build supports, so scripts can adapt to different versions
+This lists the subcommands, backends, functions, output formats and enabled cargo features"
+    )]
+    Capabilities {
+        /// Print JSON, the same as `--format json`
+        #[arg(long)]
+        json: bool,
+    },
+    /// Update this binary from the GitHub releases
+    #[cfg(feature = "self-update")]
+    #[command(name = "self", subcommand)]
+    SelfUpdate(SelfCommand),
+}
+
+#[cfg(feature = "self-update")]
+#[derive(Subcommand, Clone)]
+pub enum SelfCommand {
+    /// Show whether a newer version was released, without downloading it
+    Check,
+    /// Download the latest release, verify its signature and replace this binary with it
+    #[command(
+        long_about = "Download the latest release, verify its signature and replace this binary with it\nThe binary has to be writable, a binary installed by a package manager should be updated using it instead"
+    )]
+    Update,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum EasingCommand {
+    /// Set the easing of a device
+    Set {
+        /// A device name, `class:backlight`, `class:leds` or `default`
+        device: String,
+        easing: EasingKind,
+    },
+    /// Find a polynomial easing interactively by adjusting it while watching the device
+    #[command(
+        long_about = "Find a polynomial easing interactively by adjusting it while watching the device\nThe arrow keys up and down change the exponent, left and right change the percentage which is shown on the device\nEnter saves the easing for the device, q cancels"
+    )]
+    Tune {
+        /// Choose a device by name
+        #[arg(
+            long,
+            long_help = DEVICE_HELP
+        )]
+        device: Option<String>,
+    },
+    /// Remove the easing of a device, so it falls back to its class' or the default easing
+    Unset {
+        /// A device name, `class:backlight`, `class:leds` or `default`
+        device: String,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ConfigCommand {
+    /// Change a setting, keeping comments and the order of the other entries
+    Set {
+        /// A setting like `verify_writes`, or one of a device like `intel_backlight.min`
+        key: String,
+        value: String,
+    },
+    /// Remove a setting, so the global setting or the default applies again
+    Unset {
+        /// A setting like `verify_writes`, or one of a device like `intel_backlight.min`
+        key: String,
+    },
 }
 
 #[derive(Parser, Clone)]
@@ -43,28 +282,225 @@ pub struct SetArgs {
     /// Choose a device by name
     #[arg(
         long,
-        long_help = "Choose a device by name\nThis takes presidence over the BRIGHT_DEVICE environment variable"
+        long_help = format!("{DEVICE_HELP}\nA glob like `tpacpi::*` or a regex like `re:^input\\d+::.*lock$` changes every matching device\nRepeat it to change several devices using the same brightness")
+    )]
+    pub device: Vec<String>,
+    /// Change all devices besides virtual ones like `all`
+    #[arg(long, conflicts_with_all = ["device", "class"])]
+    pub all: bool,
+    /// Change all devices of a class, like `leds`
+    #[arg(long, conflicts_with = "device")]
+    pub class: Option<DeviceClass>,
+    #[command(flatten)]
+    pub animation: AnimationArgs,
+    /// Whether to save the new value to a temporary file so it can be restored later on
+    #[arg(long, default_value_t = false)]
+    pub save: bool,
+    /// Don't restore the saved value after this long, e.g. `1h`
+    #[arg(
+        long,
+        requires = "save",
+        value_parser = parse_duration,
+        long_help = "Don't restore the saved value after this long, e.g. `1h`\n`restore` fails with an error instead of silently going back to a brightness which is no longer relevant"
+    )]
+    pub expires: Option<humantime::Duration>,
+}
+
+#[derive(Parser, Clone)]
+pub struct StartupArgs {
+    /// Choose a device by name
+    #[arg(
+        long,
+        long_help = DEVICE_HELP
+    )]
+    pub device: Option<String>,
+    /// Fade from the current brightness instead of from 0
+    #[arg(long)]
+    pub from_current: bool,
+    #[command(flatten)]
+    pub animation: AnimationArgs,
+}
+
+#[derive(Parser, Clone)]
+pub struct PipeArgs {
+    /// Choose a device by name
+    #[arg(
+        long,
+        long_help = DEVICE_HELP
+    )]
+    pub device: Option<String>,
+    #[command(flatten)]
+    pub animation: AnimationArgs,
+}
+
+#[derive(Parser, Clone)]
+pub struct SoakArgs {
+    /// Choose a device by name
+    #[arg(
+        long,
+        long_help = DEVICE_HELP
     )]
     pub device: Option<String>,
-    /// The duration of the animation, if omitted the change isn't animated
-    #[arg(short, long, group = "time")]
+    /// How many times to animate back and forth
+    #[arg(long, default_value_t = 1000, value_parser = value_parser!(u32).range(1..))]
+    pub cycles: u32,
+    /// How many more files may be open after the last cycle than after the first
+    #[arg(long, default_value_t = 0)]
+    pub max_fd_growth: usize,
+    /// How many KiB the resident memory may grow between the first and the last cycle
+    #[arg(long, default_value_t = 1024)]
+    pub max_memory_growth: u64,
+    #[command(flatten)]
+    pub animation: AnimationArgs,
+}
+
+#[derive(Parser, Clone)]
+pub struct IdleArgs {
+    /// Choose a device by name
+    #[arg(
+        long,
+        long_help = format!("{DEVICE_HELP}\nWithout either the first LED named like `*::kbd_backlight` is used")
+    )]
+    pub device: Option<String>,
+    /// How long no key has to be pressed before the backlight fades out
+    #[arg(long, default_value = "10s", value_parser = parse_duration)]
+    pub timeout: humantime::Duration,
+    #[command(flatten)]
+    pub animation: AnimationArgs,
+}
+
+#[derive(Parser, Clone)]
+pub struct RecordArgs {
+    /// The file to write the changes to as JSON lines, it's replaced if it exists
+    pub file: PathBuf,
+    /// Only record a device, repeat it to record several ones
+    #[arg(
+        long,
+        long_help = "Only record a device, repeat it to record several ones\nWithout it every device besides virtual ones like `all` is recorded\nA glob like `tpacpi::*` or a regex like `re:^input\\d+::.*lock$` records every matching device"
+    )]
+    pub device: Vec<String>,
+    /// How often to read the brightness
+    #[arg(long, default_value = "50ms", value_parser = parse_duration)]
+    pub interval: humantime::Duration,
+    /// Stop recording after this long
+    #[arg(long, value_parser = parse_duration)]
+    pub duration: Option<humantime::Duration>,
+}
+
+#[derive(Parser, Clone)]
+pub struct ReplayArgs {
+    /// The file written by `bright record`
+    pub file: PathBuf,
+    /// How much faster than recorded to replay, like `2x` or `0.5x`
+    #[arg(long, default_value = "1x", value_parser = parse_speed)]
+    pub speed: f64,
+}
+
+#[derive(Parser, Clone)]
+pub struct ImportArgs {
+    /// The script or JSON written by `bright export`, stdin if omitted or `-`
+    pub file: Option<PathBuf>,
+    #[command(flatten)]
+    pub animation: AnimationArgs,
+}
+
+#[derive(Parser, Clone)]
+pub struct ApplyArgs {
+    /// The file with the desired brightness of each device
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub animation: AnimationArgs,
+}
+
+#[derive(clap::Args, Clone)]
+pub struct AnimationArgs {
+    /// The duration of the animation, if omitted the configured one is used
+    #[arg(
+        short,
+        long,
+        group = "time",
+        value_parser = parse_duration,
+        long_help = "The duration of the animation, if omitted the `duration` setting is used and without it the change isn't animated\nBare numbers like `300` are milliseconds unless the `duration_unit` setting says otherwise"
+    )]
     pub duration: Option<humantime::Duration>,
-    /// The fps (frames-per-second) the animation will have
+    /// The fps (frames-per-second) of the animation, 30 unless configured otherwise. Requires
+    /// `--duration` or a configured duration
+    #[arg(long, value_parser = value_parser!(u16).range(1..=1000))]
+    pub fps: Option<u16>,
+    /// Change the brightness instantly, even if a duration is configured
+    #[arg(long, conflicts_with_all = ["duration", "fps"])]
+    pub no_animate: bool,
+    /// A shell command to run after every applied frame
     #[arg(
         long,
-        requires = "time",
-        default_value_t = 30,
-        value_parser = value_parser!(u16).range(1..=1000)
+        long_help = "A shell command to run after every applied frame\nThe applied value, the device's maximum and name are passed via the BRIGHT_VALUE, BRIGHT_MAX and BRIGHT_DEVICE environment variables, BRIGHT_LAST_FRAME is 1 for the final frame"
     )]
-    pub fps: u16,
-    /// Whether to save the new value to a temporary file so it can be restored later on
-    #[arg(long, default_value_t = false)]
-    pub save: bool,
+    pub frame_cmd: Option<String>,
+    /// Re-read the brightness before every frame and continue from there
+    #[arg(
+        long,
+        conflicts_with = "no_animate",
+        long_help = "Re-read the brightness before every frame and continue from there\nRequires `--duration` or a configured duration\nIf the brightness is changed by something else during the animation, e.g. a hardware hotkey, the remaining frames converge smoothly from the new value instead of jumping back"
+    )]
+    pub follow_actual: bool,
+    /// Merge relative changes arriving within this window, e.g. from a held brightness key
+    #[arg(
+        long,
+        value_parser = parse_duration,
+        long_help = "Merge relative changes arriving within this window, e.g. from a held brightness key\nInstead of the current brightness, relative values are based on the target of the previous invocation if it was within the window, so three quick `5%+` add up to 15%"
+    )]
+    pub coalesce: Option<humantime::Duration>,
+    /// What to do if writing a frame fails
+    #[arg(long, value_enum, default_value_t = WriteErrorPolicy::Retry)]
+    pub on_write_error: WriteErrorPolicy,
 }
 
-impl SetArgs {
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerState {
+    On,
+    Off,
+    /// Power it on if it's off and off if it's on
+    Toggle,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteErrorPolicy {
+    /// Stop the animation at the first failed frame
+    Abort,
+    /// Continue with the next frame, giving up after several consecutive failures
+    Skip,
+    /// Retry the frame a few times before stopping the animation
+    Retry,
+}
+
+impl AnimationArgs {
+    /// Fills in the duration and fps from the settings unless they were given or `--no-animate`
+    /// was used. `--fps` and `--follow-actual` require a duration, either `--duration` or the
+    /// `duration` setting
+    pub fn or_settings(&self, settings: &DeviceSettings) -> Result<Self, &'static str> {
+        if self.no_animate {
+            return Ok(Self {
+                duration: None,
+                ..self.clone()
+            });
+        }
+
+        let duration = self.duration.or(settings.duration);
+        if self.fps.is_some() && duration.is_none() {
+            return Err("`--fps` requires `--duration` or a `duration` setting");
+        }
+        if self.follow_actual && duration.is_none() {
+            return Err("`--follow-actual` requires `--duration` or a `duration` setting");
+        }
+        Ok(Self {
+            duration,
+            fps: self.fps.or(settings.fps),
+            ..self.clone()
+        })
+    }
+
     pub fn frame_duration(&self) -> Duration {
-        Duration::from_millis(1000 / u64::from(self.fps))
+        Duration::from_millis(1000 / u64::from(self.fps.unwrap_or(DEFAULT_FPS)))
     }
 
     pub fn frame_count(&self) -> NonZero<usize> {
@@ -73,4 +509,73 @@ impl SetArgs {
             .and_then(NonZero::new)
             .unwrap_or(NonZero::new(1).unwrap())
     }
+
+    /// The frame count and duration for a change of `distance` hardware levels. There are never
+    /// more frames than levels in between, the frames are stretched instead so the animation
+    /// takes as long but doesn't write the same level repeatedly
+    pub fn capped_frames(&self, distance: u32) -> (NonZero<usize>, Duration) {
+        let frames = self.frame_count();
+        let Some(levels) = usize::try_from(distance)
+            .ok()
+            .and_then(NonZero::new)
+            .filter(|levels| *levels < frames)
+        else {
+            return (frames, self.frame_duration());
+        };
+
+        let total = self.frame_duration() * frames.get() as u32;
+        (levels, total / levels.get() as u32)
+    }
+}
+
+/// The `duration_unit` setting, which is set before the arguments are parsed
+static DURATION_UNIT: OnceLock<Duration> = OnceLock::new();
+
+/// Sets the unit of bare numbers for [`parse_duration`], it can only be set once
+pub fn set_duration_unit(unit: Duration) {
+    let _ = DURATION_UNIT.set(unit);
+}
+
+/// Parses durations like `1s 500ms`, bare numbers use the `duration_unit` setting so keybindings can
+/// simply say `-d 300`
+fn parse_duration(s: &str) -> Result<humantime::Duration, humantime::DurationError> {
+    parse_duration_in(s, || {
+        DURATION_UNIT
+            .get()
+            .copied()
+            .unwrap_or(Duration::from_millis(1))
+    })
+}
+
+/// Parses a factor like `2x`, the `x` is optional. It's bounded, so the scaled times of a session
+/// stay representable
+fn parse_speed(s: &str) -> Result<f64, String> {
+    let speed: f64 = s
+        .strip_suffix('x')
+        .unwrap_or(s)
+        .parse()
+        .map_err(|_| format!("`{s}` isn't a speed like `2x`"))?;
+    if (MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        Ok(speed)
+    } else {
+        Err(format!(
+            "the speed must be between {MIN_SPEED}x and {MAX_SPEED}x"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_speed() {
+        assert_eq!(parse_speed("2x"), Ok(2.0));
+        assert_eq!(parse_speed("0.5"), Ok(0.5));
+        assert!(parse_speed("0x").is_err());
+        assert!(parse_speed("1e-300x").is_err());
+        assert!(parse_speed("NaN").is_err());
+        assert_eq!(parse_speed("1000x"), Ok(1000.0));
+        assert!(parse_speed("fast").is_err());
+    }
 }
@@ -0,0 +1,393 @@
+pub mod settings;
+pub mod write;
+
+use crate::{
+    animation::easing::{EasingKind, EasingParseError, split_top_level},
+    device::{DeviceClass, UnknownClassError},
+};
+use derive_more::Display;
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    env, fs, io,
+    path::PathBuf,
+    str::FromStr,
+};
+use thiserror::Error;
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Display)]
+pub enum EasingDevice {
+    #[display("default")]
+    Default,
+    #[display("{_0}")]
+    Name(String),
+    /// All devices of a class, written as `class:backlight`
+    #[display("class:{}", _0.sysfs_name())]
+    Class(DeviceClass),
+}
+
+impl From<&str> for EasingDevice {
+    fn from(value: &str) -> Self {
+        Self::Name(value.to_string())
+    }
+}
+
+impl FromStr for EasingDevice {
+    type Err = UnknownClassError;
+
+    /// Parses the left side of a line in the easings file
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("class:") {
+            Some(class) => class.parse().map(Self::Class),
+            None => Ok(Self::from(s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Easings(HashMap<EasingDevice, EasingKind>);
+
+impl Easings {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    pub fn from_config() -> Result<Option<Self>, EasingFromFileError> {
+        let Some(path) = path() else {
+            return Err(EasingFromFileError::NoPath);
+        };
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path)?;
+
+        match Self::from_str(&content) {
+            Ok(easings) => Ok(Some(easings)),
+            Err(error) => Err(EasingFromFileError::ParseError { path, error }),
+        }
+    }
+
+    /// Parses comma separated lines of the easings file, like `intel_backlight=x^2,default=x`
+    pub fn from_assignments(s: &str) -> Result<Self, EasingAssignmentsError> {
+        // nothing would replace the configured easings
+        if s.trim().is_empty() {
+            return Err(EasingAssignmentsError::Empty);
+        }
+        let entries: Vec<_> = split_top_level(s, ',').map(str::trim).collect();
+        // the entries are parsed as lines, which these would split or comment out
+        if let Some(entry) = entries
+            .iter()
+            .find(|entry| entry.contains(['\n', '\r', '#']))
+        {
+            return Err(EasingAssignmentsError::InvalidCharacter(entry.to_string()));
+        }
+
+        entries.join("\n").parse().map_err(|error| {
+            let line_number = match error {
+                MultilineEasingsParseError::ParseError { line_number, .. }
+                | MultilineEasingsParseError::UnknownClass { line_number, .. }
+                | MultilineEasingsParseError::DuplicateDevice { line_number, .. } => line_number,
+            };
+            EasingAssignmentsError::Invalid {
+                entry: entries.get(line_number).unwrap_or(&s).to_string(),
+                error,
+            }
+        })
+    }
+
+    /// All devices, classes and the default which have an easing
+    pub fn devices(&self) -> impl Iterator<Item = &EasingDevice> {
+        self.0.keys()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&EasingDevice, &EasingKind)> {
+        self.0.iter()
+    }
+
+    /// The easing configured for exactly this entry, without any fallbacks
+    pub fn get_exact(&self, device: &EasingDevice) -> Option<&EasingKind> {
+        self.0.get(device)
+    }
+
+    /// Sets the easing of an entry, returning the previous one
+    pub fn insert(&mut self, device: EasingDevice, easing: EasingKind) -> Option<EasingKind> {
+        self.0.insert(device, easing)
+    }
+
+    pub fn remove(&mut self, device: &EasingDevice) -> Option<EasingKind> {
+        self.0.remove(device)
+    }
+
+    /// The easing of a device, falling back to the one of its class and then to the default.
+    /// Virtual devices are already perceptual, so they don't use the default
+    pub fn get_or_default<S: ToString>(&self, name: Option<S>, class: DeviceClass) -> EasingKind {
+        name.and_then(|name| self.0.get(&EasingDevice::Name(name.to_string())))
+            .or_else(|| self.0.get(&EasingDevice::Class(class)))
+            .or_else(|| {
+                (class != DeviceClass::Virtual)
+                    .then(|| self.0.get(&EasingDevice::Default))
+                    .flatten()
+            })
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Easings {
+    fn default() -> Self {
+        let mut hm = HashMap::new();
+        hm.insert(EasingDevice::Default, EasingKind::default());
+        Self(hm)
+    }
+}
+
+impl From<EasingKind> for Easings {
+    fn from(value: EasingKind) -> Self {
+        let mut hm = HashMap::new();
+        hm.insert(EasingDevice::Default, value);
+        Self(hm)
+    }
+}
+
+impl IntoIterator for Easings {
+    type Item = (EasingDevice, EasingKind);
+    type IntoIter = std::collections::hash_map::IntoIter<EasingDevice, EasingKind>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Easings {
+    type Item = (&'a EasingDevice, &'a EasingKind);
+    type IntoIter = std::collections::hash_map::Iter<'a, EasingDevice, EasingKind>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EasingFromFileError {
+    #[error("no config file path could be found")]
+    NoPath,
+    #[error("could not read file")]
+    ReadFile(
+        #[from]
+        #[source]
+        io::Error,
+    ),
+    #[error("could not parse file")]
+    ParseError {
+        path: PathBuf,
+        #[source]
+        error: MultilineEasingsParseError,
+    },
+}
+
+fn path() -> Option<PathBuf> {
+    config_file("BRIGHT_CONFIG", "easings")
+}
+
+/// The path of a file in bright's config directory, which can be overwritten by an environment
+/// variable
+fn config_file(env_var: &str, name: &str) -> Option<PathBuf> {
+    if let Some(path) = env::var_os(env_var) {
+        return Some(PathBuf::from(path));
+    }
+
+    let config_dir = dirs::home_dir()
+        .map(|home| home.join(".config"))
+        .or_else(dirs::config_dir)?;
+    Some(config_dir.join("bright").join(name))
+}
+
+#[derive(Debug, Error)]
+pub enum MultilineEasingsParseError {
+    #[error("can't parse line {l}", l = line_number + 1)]
+    ParseError {
+        line_number: usize,
+        #[source]
+        error: EasingParseError,
+    },
+    #[error("line {l}: {error}", l = line_number + 1)]
+    UnknownClass {
+        line_number: usize,
+        #[source]
+        error: UnknownClassError,
+    },
+    #[error("device {device} is duplicated in line {l}", l = line_number + 1)]
+    DuplicateDevice {
+        line_number: usize,
+        device: EasingDevice,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum EasingAssignmentsError {
+    #[error("no easing given")]
+    Empty,
+    #[error("invalid easing `{_0}`, it can't contain line breaks or `#`")]
+    InvalidCharacter(String),
+    #[error("invalid easing `{entry}`{}", match error {
+        MultilineEasingsParseError::ParseError { error, .. } => format!(": {error}"),
+        MultilineEasingsParseError::UnknownClass { error, .. } => format!(": {error}"),
+        MultilineEasingsParseError::DuplicateDevice { device, .. } => format!(", {device} is assigned twice"),
+    })]
+    Invalid {
+        entry: String,
+        #[source]
+        error: MultilineEasingsParseError,
+    },
+}
+
+impl FromStr for Easings {
+    type Err = MultilineEasingsParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut easings = Self::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (dev, easing) = match line.split_once('=') {
+                // only trim the middle as the line itself is already trimmed
+                Some((dev_name, easing)) => {
+                    let dev = dev_name.trim_end().parse().map_err(|error| {
+                        MultilineEasingsParseError::UnknownClass {
+                            line_number: i,
+                            error,
+                        }
+                    })?;
+                    (dev, easing.trim_start())
+                }
+                None => (EasingDevice::Default, line),
+            };
+
+            let easing = EasingKind::from_str(easing).map_err(|error| {
+                MultilineEasingsParseError::ParseError {
+                    line_number: i,
+                    error,
+                }
+            })?;
+
+            match easings.0.entry(dev) {
+                Entry::Vacant(entry) => {
+                    entry.insert(easing);
+                }
+                Entry::Occupied(entry) => {
+                    return Err(MultilineEasingsParseError::DuplicateDevice {
+                        line_number: i,
+                        device: entry.key().clone(), // only clone in case of error
+                    });
+                }
+            }
+        }
+
+        Ok(easings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_class_fallback() {
+        let easings: Easings = "
+            x^2
+            class:backlight = x^2.2
+            intel_backlight = 3^x
+        "
+        .trim()
+        .parse()
+        .unwrap();
+
+        let easing = |name: Option<&str>, class| easings.get_or_default(name, class).to_string();
+        assert_eq!(
+            easing(Some("intel_backlight"), DeviceClass::Backlight),
+            "3^x"
+        );
+        assert_eq!(easing(Some("acpi_video0"), DeviceClass::Backlight), "x^2.2");
+        assert_eq!(easing(None, DeviceClass::Backlight), "x^2.2");
+        assert_eq!(easing(Some("input0::capslock"), DeviceClass::Leds), "x^2");
+        assert_eq!(easing(Some("all"), DeviceClass::Virtual), "x");
+
+        assert!(matches!(
+            "class:screens = x".parse::<Easings>(),
+            Err(MultilineEasingsParseError::UnknownClass { line_number: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_assignments() {
+        let easings = Easings::from_assignments("intel_backlight=range(0.1, 1, x^2), x^3").unwrap();
+        let easing = |name| {
+            easings
+                .get_or_default(Some(name), DeviceClass::Backlight)
+                .to_string()
+        };
+        assert_eq!(easing("intel_backlight"), "range(0.1, 1, x^2)");
+        assert_eq!(easing("acpi_video0"), "x^3");
+
+        let err = Easings::from_assignments("x, foo=y^2").unwrap_err();
+        assert!(matches!(
+            err,
+            EasingAssignmentsError::Invalid {
+                entry,
+                error: MultilineEasingsParseError::ParseError { line_number: 1, .. },
+            } if entry == "foo=y^2"
+        ));
+
+        assert!(matches!(
+            Easings::from_assignments("x^2\nbogus"),
+            Err(EasingAssignmentsError::InvalidCharacter(_))
+        ));
+        assert!(matches!(
+            Easings::from_assignments("x, # foo=x^2"),
+            Err(EasingAssignmentsError::InvalidCharacter(_))
+        ));
+        assert!(matches!(
+            Easings::from_assignments(" "),
+            Err(EasingAssignmentsError::Empty)
+        ));
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut easings = Easings::default();
+        let dev = EasingDevice::from("intel_backlight");
+        assert!(easings.get_exact(&dev).is_none());
+
+        assert!(
+            easings
+                .insert(dev.clone(), "x^2".parse().unwrap())
+                .is_none()
+        );
+        assert_eq!(easings.get_exact(&dev).unwrap().to_string(), "x^2");
+        assert_eq!(
+            easings
+                .insert(dev.clone(), "x^3".parse().unwrap())
+                .unwrap()
+                .to_string(),
+            "x^2"
+        );
+
+        let mut entries: Vec<_> = (&easings)
+            .into_iter()
+            .map(|(dev, easing)| format!("{dev} = {easing}"))
+            .collect();
+        entries.sort();
+        assert_eq!(entries, ["default = x", "intel_backlight = x^3"]);
+
+        assert!(easings.remove(&dev).is_some());
+        assert_eq!(
+            easings.devices().collect::<Vec<_>>(),
+            [&EasingDevice::Default]
+        );
+    }
+}
@@ -0,0 +1,570 @@
+use super::config_file;
+use crate::{
+    brightness::ast::{Ast, functions::FunctionPolicy},
+    device::{
+        DeviceClass,
+        backlight::bl_type::{self, BlType},
+    },
+    hooks::HookFailurePolicy,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io, iter,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use thiserror::Error;
+
+/// Settings which can be set globally and be overwritten for a single device using
+/// `<device>.<key> = <value>`
+#[derive(Default, Clone, Debug)]
+pub struct DeviceSettings {
+    /// Read the brightness back after every write, as some drivers quantize the written value
+    pub verify_writes: Option<bool>,
+    /// Read `brightness` if a backlight's `actual_brightness` can't be read or reports 0
+    pub read_fallback: Option<bool>,
+    /// Pause animations while the display is off instead of writing to a dark panel
+    pub pause_when_off: Option<bool>,
+    /// What happens to an animation which would start while the display is off
+    pub when_off: Option<DisplayOffPolicy>,
+    /// Power the display off using `bl_power` at brightness 0 and on again above it
+    pub power_off_at_zero: Option<bool>,
+    /// Use at most one frame per hardware level, stretching the frames to keep the duration
+    pub cap_frames: Option<bool>,
+    /// How long changes are animated if `--duration` isn't given
+    pub duration: Option<humantime::Duration>,
+    /// The fps of animations if `--fps` isn't given
+    pub fps: Option<u16>,
+    /// A shell command run before a change, see [`crate::hooks`]
+    pub pre_set: Option<String>,
+    /// A shell command run after a change, see [`crate::hooks`]
+    pub post_set: Option<String>,
+    /// Whether a failed hook stops the change
+    pub hook_failure: Option<HookFailurePolicy>,
+    /// The brightness `bright startup` fades to, `restore()` by default
+    pub startup_brightness: Option<Ast>,
+    /// How long `bright startup` fades
+    pub startup_duration: Option<humantime::Duration>,
+    /// The fastest `bright pipe` may change the brightness, in perceived percent per second
+    pub max_rate: Option<f64>,
+    /// The lowest brightness changes may go to without `--force`, like `5%`
+    pub min: Option<Ast>,
+}
+
+impl DeviceSettings {
+    /// Uses the values of `fallback` for all values that aren't set
+    fn or(self, fallback: &Self) -> Self {
+        Self {
+            verify_writes: self.verify_writes.or(fallback.verify_writes),
+            read_fallback: self.read_fallback.or(fallback.read_fallback),
+            pause_when_off: self.pause_when_off.or(fallback.pause_when_off),
+            when_off: self.when_off.or(fallback.when_off),
+            power_off_at_zero: self.power_off_at_zero.or(fallback.power_off_at_zero),
+            cap_frames: self.cap_frames.or(fallback.cap_frames),
+            duration: self.duration.or(fallback.duration),
+            fps: self.fps.or(fallback.fps),
+            pre_set: self.pre_set.or_else(|| fallback.pre_set.clone()),
+            post_set: self.post_set.or_else(|| fallback.post_set.clone()),
+            hook_failure: self.hook_failure.or(fallback.hook_failure),
+            startup_brightness: self
+                .startup_brightness
+                .or_else(|| fallback.startup_brightness.clone()),
+            startup_duration: self.startup_duration.or(fallback.startup_duration),
+            max_rate: self.max_rate.or(fallback.max_rate),
+            min: self.min.or_else(|| fallback.min.clone()),
+        }
+    }
+
+    /// Sets a key, bare numbers in durations are multiples of `duration_unit`
+    fn set(&mut self, key: &str, value: &str, duration_unit: Duration) -> Result<(), SettingError> {
+        match key {
+            "verify_writes" => self.verify_writes = Some(parse_value(key, value)?),
+            "read_fallback" => self.read_fallback = Some(parse_value(key, value)?),
+            "pause_when_off" => self.pause_when_off = Some(parse_value(key, value)?),
+            "when_off" => self.when_off = Some(parse_value(key, value)?),
+            "power_off_at_zero" => self.power_off_at_zero = Some(parse_value(key, value)?),
+            "cap_frames" => self.cap_frames = Some(parse_value(key, value)?),
+            "duration" => self.duration = Some(parse_duration(key, value, duration_unit)?),
+            "pre_set" => self.pre_set = Some(value.to_string()),
+            "post_set" => self.post_set = Some(value.to_string()),
+            "hook_failure" => self.hook_failure = Some(parse_value(key, value)?),
+            "fps" => {
+                self.fps = Some(
+                    parse_value(key, value)
+                        .ok()
+                        .filter(|fps| (1..=1000).contains(fps))
+                        .ok_or_else(|| SettingError::InvalidValue {
+                            key: key.to_string(),
+                            value: value.to_string(),
+                        })?,
+                );
+            }
+            "startup_brightness" => self.startup_brightness = Some(parse_value(key, value)?),
+            "startup_duration" => {
+                self.startup_duration = Some(parse_duration(key, value, duration_unit)?);
+            }
+            "min" => self.min = Some(parse_value(key, value)?),
+            "max_rate" => {
+                self.max_rate = Some(
+                    parse_value(key, value)
+                        .ok()
+                        .filter(|rate: &f64| rate.is_finite() && *rate > 0.0)
+                        .ok_or_else(|| SettingError::InvalidValue {
+                            key: key.to_string(),
+                            value: value.to_string(),
+                        })?,
+                );
+            }
+            _ => return Err(SettingError::UnknownKey(key.to_string())),
+        }
+
+        Ok(())
+    }
+}
+
+/// What to do if the display is already off when an animation would start
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DisplayOffPolicy {
+    /// Wait until it's on again, unless `pause_when_off` is disabled
+    #[default]
+    Pause,
+    /// Apply the brightness at once, nobody sees the animation anyway
+    Instant,
+    /// Don't change the brightness and fail
+    Refuse,
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("unknown policy `{_0}`, expected pause, instant or refuse")]
+pub struct UnknownDisplayOffPolicy(String);
+
+impl FromStr for DisplayOffPolicy {
+    type Err = UnknownDisplayOffPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pause" => Ok(Self::Pause),
+            "instant" => Ok(Self::Instant),
+            "refuse" => Ok(Self::Refuse),
+            _ => Err(UnknownDisplayOffPolicy(s.to_string())),
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Settings {
+    defaults: DeviceSettings,
+    devices: HashMap<String, DeviceSettings>,
+    /// Functions which can't be used in brightness expressions
+    disabled_functions: Vec<String>,
+    /// Disable all functions which access anything besides the device
+    sandbox: bool,
+    /// Warn about configured devices which don't exist, most likely typos
+    warn_unknown_devices: Option<bool>,
+    /// How long `get` may reuse a brightness it read before
+    cache_ttl: Option<humantime::Duration>,
+    /// The unit of durations given as a bare number on the command line
+    duration_unit: Option<humantime::Duration>,
+    /// Where sysfs is mounted, e.g. in a chroot
+    sysfs_root: Option<PathBuf>,
+    /// Devices changed together, defined like `@displays = intel_backlight, ddc-DP-1`
+    groups: BTreeMap<String, Vec<String>>,
+    /// Which backlight types are used if several control the same panel, the first is preferred
+    backlight_preference: Option<Vec<BlType>>,
+    /// Add UPower's keyboard backlight as a device
+    upower: bool,
+    /// The class `list` shows and the default device is chosen from
+    default_class: Option<DeviceClass>,
+}
+
+impl Settings {
+    pub fn from_config() -> Result<Self, SettingsFromFileError> {
+        let Some(path) = path() else {
+            return Ok(Self::default());
+        };
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        Self::from_str(&content).map_err(|error| SettingsFromFileError::ParseError { path, error })
+    }
+
+    pub fn function_policy(&self) -> FunctionPolicy {
+        FunctionPolicy {
+            disabled: self.disabled_functions.clone(),
+            sandbox: self.sandbox,
+        }
+    }
+
+    pub fn warn_unknown_devices(&self) -> bool {
+        self.warn_unknown_devices.unwrap_or(false)
+    }
+
+    pub fn cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl.map(Into::into)
+    }
+
+    pub fn duration_unit(&self) -> Duration {
+        self.duration_unit
+            .map_or(Duration::from_millis(1), Into::into)
+    }
+
+    pub fn sysfs_root(&self) -> Option<&Path> {
+        self.sysfs_root.as_deref()
+    }
+
+    /// The groups by name without the `@`, and the names of their members
+    pub fn groups(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.groups
+            .iter()
+            .map(|(name, members)| (name.as_str(), members.as_slice()))
+    }
+
+    pub fn default_class(&self) -> Option<DeviceClass> {
+        self.default_class
+    }
+
+    pub fn upower(&self) -> bool {
+        self.upower
+    }
+
+    pub fn backlight_preference(&self) -> &[BlType] {
+        self.backlight_preference
+            .as_deref()
+            .unwrap_or(&bl_type::DEFAULT_PREFERENCE)
+    }
+
+    /// All devices which have settings of their own
+    pub fn devices(&self) -> impl Iterator<Item = &str> {
+        self.devices.keys().map(String::as_str)
+    }
+
+    /// Drops every `min` setting, for `--force`
+    pub fn ignore_min(&mut self) {
+        self.defaults.min = None;
+        for device in self.devices.values_mut() {
+            device.min = None;
+        }
+    }
+
+    /// Drops every `pre_set` and `post_set` hook, for `bright soak`
+    pub fn ignore_hooks(&mut self) {
+        for device in iter::once(&mut self.defaults).chain(self.devices.values_mut()) {
+            device.pre_set = None;
+            device.post_set = None;
+        }
+    }
+
+    /// The settings for a device, unset values fall back to the global ones
+    pub fn device(&self, name: Option<&str>) -> DeviceSettings {
+        name.and_then(|name| self.devices.get(name))
+            .cloned()
+            .unwrap_or_default()
+            .or(&self.defaults)
+    }
+}
+
+impl Settings {
+    /// Sets a key without a device prefix, either a global-only setting or a default
+    fn set(&mut self, key: &str, value: &str) -> Result<(), SettingError> {
+        match key {
+            "disabled_functions" => self.disabled_functions = parse_list(value),
+            "sandbox" => self.sandbox = parse_value(key, value)?,
+            "upower" => self.upower = parse_value(key, value)?,
+            "default_class" => self.default_class = Some(parse_value(key, value)?),
+            "warn_unknown_devices" => self.warn_unknown_devices = Some(parse_value(key, value)?),
+            "cache_ttl" => self.cache_ttl = Some(parse_value(key, value)?),
+            "sysfs_root" => self.sysfs_root = Some(PathBuf::from(value)),
+            "backlight_preference" => {
+                self.backlight_preference = Some(
+                    parse_list(value)
+                        .iter()
+                        .map(|bl_type| parse_value(key, bl_type))
+                        .collect::<Result<_, _>>()?,
+                );
+            }
+            // both `s` and `1s` are accepted
+            "duration_unit" => {
+                let unit: humantime::Duration =
+                    parse_value(key, value).or_else(|_| parse_value(key, &format!("1{value}")))?;
+                if unit.is_zero() {
+                    return Err(SettingError::InvalidValue {
+                        key: key.to_string(),
+                        value: value.to_string(),
+                    });
+                }
+                self.duration_unit = Some(unit);
+            }
+            _ => return self.defaults.set(key, value, self.duration_unit()),
+        }
+
+        Ok(())
+    }
+}
+
+pub(super) fn path() -> Option<PathBuf> {
+    config_file("BRIGHT_SETTINGS", "settings")
+}
+
+/// Parses a duration like `1s 500ms`, a bare number is a multiple of `unit`. The command line
+/// parses its durations the same way
+pub fn parse_duration_in(
+    s: &str,
+    unit: impl FnOnce() -> Duration,
+) -> Result<humantime::Duration, humantime::DurationError> {
+    match s.parse::<u32>() {
+        Ok(count) => unit()
+            .checked_mul(count)
+            .map(Into::into)
+            .ok_or(humantime::DurationError::NumberOverflow),
+        Err(_) => s.parse(),
+    }
+}
+
+fn parse_duration(
+    key: &str,
+    value: &str,
+    unit: Duration,
+) -> Result<humantime::Duration, SettingError> {
+    parse_duration_in(value, || unit).map_err(|_| SettingError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, SettingError> {
+    value.parse().map_err(|_| SettingError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Parses a comma separated list
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SettingError {
+    #[error("unknown setting `{_0}`")]
+    UnknownKey(String),
+    #[error("`{value}` isn't a valid value for `{key}`")]
+    InvalidValue { key: String, value: String },
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SettingsParseError {
+    #[error("line {l} isn't of the form `key = value`", l = line_number + 1)]
+    MissingValue { line_number: usize },
+    #[error("line {l}: {error}", l = line_number + 1)]
+    Setting {
+        line_number: usize,
+        #[source]
+        error: SettingError,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum SettingsFromFileError {
+    #[error("can't read the settings file: {_0}")]
+    ReadFile(
+        #[from]
+        #[source]
+        io::Error,
+    ),
+    #[error("can't parse {}, {error}", path.display())]
+    ParseError {
+        path: PathBuf,
+        #[source]
+        error: SettingsParseError,
+    },
+}
+
+impl FromStr for Settings {
+    type Err = SettingsParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut settings = Self::default();
+
+        let mut entries = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(SettingsParseError::MissingValue { line_number: i })?;
+            entries.push((i, key.trim_end(), value.trim_start()));
+        }
+        // durations depend on the unit, wherever it's set
+        entries.sort_by_key(|(_, key, _)| *key != "duration_unit");
+
+        for (i, key, value) in entries {
+            if let Some(group) = key.strip_prefix('@') {
+                settings.groups.insert(group.to_string(), parse_list(value));
+                continue;
+            }
+
+            // device names may contain dots, keys never do
+            let duration_unit = settings.duration_unit();
+            let result = match key.rsplit_once('.') {
+                Some((device, key)) => settings.devices.entry(device.to_string()).or_default().set(
+                    key,
+                    value,
+                    duration_unit,
+                ),
+                None => settings.set(key, value),
+            };
+            result.map_err(|error| SettingsParseError::Setting {
+                line_number: i,
+                error,
+            })?;
+        }
+
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_fallback() {
+        let settings: Settings = "
+            # comments and empty lines are ignored
+
+            verify_writes = true
+            intel_backlight.verify_writes = false
+        "
+        .parse()
+        .unwrap();
+
+        assert_eq!(settings.device(None).verify_writes, Some(true));
+        assert_eq!(
+            settings.device(Some("acpi_video0")).verify_writes,
+            Some(true)
+        );
+        assert_eq!(
+            settings.device(Some("intel_backlight")).verify_writes,
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_groups() {
+        let settings: Settings = "@displays = intel_backlight, ddc-DP-1".parse().unwrap();
+        let groups: Vec<_> = settings.groups().collect();
+        assert_eq!(
+            groups,
+            [(
+                "displays",
+                ["intel_backlight".to_string(), "ddc-DP-1".to_string()].as_slice()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_backlight_preference() {
+        let preference = |s: &str| {
+            s.parse::<Settings>()
+                .map(|s| s.backlight_preference().to_vec())
+        };
+        assert_eq!(preference("").unwrap(), bl_type::DEFAULT_PREFERENCE);
+        assert_eq!(
+            preference("backlight_preference = raw, firmware").unwrap(),
+            [BlType::Raw, BlType::Firmware]
+        );
+        assert!(preference("backlight_preference = raw, acpi").is_err());
+    }
+
+    #[test]
+    fn test_function_policy() {
+        let settings: Settings = "disabled_functions = restore, blend".parse().unwrap();
+        let policy = settings.function_policy();
+        assert_eq!(policy.disabled, vec!["restore", "blend"]);
+        assert!(!policy.sandbox);
+    }
+
+    #[test]
+    fn test_duration_unit() {
+        let unit = |s: &str| s.parse::<Settings>().unwrap().duration_unit();
+        assert_eq!(unit(""), Duration::from_millis(1));
+        assert_eq!(unit("duration_unit = s"), Duration::from_secs(1));
+        assert_eq!(unit("duration_unit = 10ms"), Duration::from_millis(10));
+        assert!("duration_unit = 0ms".parse::<Settings>().is_err());
+
+        // bare numbers in durations use the unit, even if it's set further down
+        let settings: Settings = "duration = 3\nfoo.startup_duration = 2\nduration_unit = s"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            settings.device(None).duration.map(Into::into),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            settings
+                .device(Some("foo"))
+                .startup_duration
+                .map(Into::into),
+            Some(Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let ms = || Duration::from_millis(1);
+        assert_eq!(
+            *parse_duration_in("300", ms).unwrap(),
+            Duration::from_millis(300)
+        );
+        assert_eq!(
+            *parse_duration_in("2", || Duration::from_secs(1)).unwrap(),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            *parse_duration_in("1s 500ms", ms).unwrap(),
+            Duration::from_millis(1500)
+        );
+        assert!(parse_duration_in("-3", ms).is_err());
+        assert_eq!(
+            parse_duration_in("4294967295", || Duration::from_secs(u64::MAX / 1000)),
+            Err(humantime::DurationError::NumberOverflow)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            "verify_writes".parse::<Settings>().unwrap_err(),
+            SettingsParseError::MissingValue { line_number: 0 }
+        );
+        assert_eq!(
+            "\nverify_writes = maybe".parse::<Settings>().unwrap_err(),
+            SettingsParseError::Setting {
+                line_number: 1,
+                error: SettingError::InvalidValue {
+                    key: "verify_writes".to_string(),
+                    value: "maybe".to_string()
+                }
+            }
+        );
+        assert_eq!(
+            "foo.bar = 1".parse::<Settings>().unwrap_err(),
+            SettingsParseError::Setting {
+                line_number: 0,
+                error: SettingError::UnknownKey("bar".to_string())
+            }
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use super::{
+    EasingDevice, Easings,
+    settings::{self, Settings, SettingsParseError},
+};
+use crate::state::write_atomic;
+use std::{
+    collections::HashSet,
+    fs, io, iter,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConfigWriteError {
+    #[error("no config file path could be found")]
+    NoPath,
+    #[error("can't access the config file: {_0}")]
+    Io(
+        #[from]
+        #[source]
+        io::Error,
+    ),
+    #[error("`{_0}` can't be written as a single line of the settings")]
+    InvalidEntry(String),
+    #[error("the settings would become invalid, {_0}")]
+    InvalidSettings(
+        #[from]
+        #[source]
+        SettingsParseError,
+    ),
+}
+
+/// Writes the easings to the easings file, keeping comments and the order of existing entries
+pub fn write_easings(easings: &Easings) -> Result<PathBuf, ConfigWriteError> {
+    let path = super::path().ok_or(ConfigWriteError::NoPath)?;
+    let content = read_or_empty(&path)?;
+    write(&path, &update_easings(&content, easings))?;
+    Ok(path)
+}
+
+/// Sets or, if `value` is `None`, removes a line of the settings file, e.g.
+/// `intel_backlight.verify_writes`
+pub fn write_setting(key: &str, value: Option<&str>) -> Result<PathBuf, ConfigWriteError> {
+    if key.contains(['=', '#']) {
+        return Err(ConfigWriteError::InvalidEntry(key.to_string()));
+    }
+    if let Some(entry) = iter::once(key)
+        .chain(value)
+        .find(|entry| entry.trim().is_empty() || entry.contains(['\n', '\r']))
+    {
+        return Err(ConfigWriteError::InvalidEntry(entry.to_string()));
+    }
+
+    let path = settings::path().ok_or(ConfigWriteError::NoPath)?;
+    let content = update_entry(&read_or_empty(&path)?, key, value);
+    content.parse::<Settings>()?;
+    write(&path, &content)?;
+    Ok(path)
+}
+
+/// Replaces all entries in `content` with the ones of `easings`, entries which aren't part of
+/// `easings` anymore are removed and new ones are appended
+pub fn update_easings(content: &str, easings: &Easings) -> String {
+    let mut written = HashSet::new();
+    let mut lines = Vec::new();
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let device = match trimmed.split_once('=') {
+            Some((device, _)) => device.trim_end().parse(),
+            None => Ok(EasingDevice::Default),
+        };
+        let Ok(device) = device else {
+            // keep lines we don't understand, they will fail loudly when being read
+            lines.push(line.to_string());
+            continue;
+        };
+
+        if written.contains(&device) {
+            continue;
+        }
+        if let Some(line) = easing_line(easings, &device) {
+            lines.push(line);
+            written.insert(device);
+        }
+    }
+
+    let mut missing: Vec<_> = easings
+        .devices()
+        .filter(|device| !written.contains(*device))
+        .collect();
+    // the default is the only line without a device, so it goes first
+    missing.sort_by_key(|device| (**device != EasingDevice::Default, device.to_string()));
+    lines.extend(
+        missing
+            .into_iter()
+            .filter_map(|device| easing_line(easings, device)),
+    );
+
+    finish(lines)
+}
+
+/// Sets `key = value` in a line based config file, removing the entry if `value` is `None`
+pub fn update_entry(content: &str, key: &str, value: Option<&str>) -> String {
+    let mut found = false;
+    let mut lines = Vec::new();
+
+    for line in content.lines() {
+        let is_entry = line
+            .split_once('=')
+            .is_some_and(|(line_key, _)| line_key.trim() == key)
+            && !line.trim_start().starts_with('#');
+        if !is_entry {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        if let Some(value) = value
+            && !found
+        {
+            lines.push(format!("{key} = {value}"));
+        }
+        found = true;
+    }
+
+    if let Some(value) = value
+        && !found
+    {
+        lines.push(format!("{key} = {value}"));
+    }
+
+    finish(lines)
+}
+
+fn easing_line(easings: &Easings, device: &EasingDevice) -> Option<String> {
+    let easing = easings.get_exact(device)?;
+    Some(match device {
+        EasingDevice::Default => easing.to_string(),
+        device => format!("{device} = {easing}"),
+    })
+}
+
+fn finish(lines: Vec<String>) -> String {
+    let mut content = lines.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    content
+}
+
+fn read_or_empty(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        result => result,
+    }
+}
+
+fn write(path: &Path, content: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_easings() {
+        let content = "# my screen\nintel_backlight = x^2\n\nx\ninput0::capslock = 3^x\n";
+        let mut easings: Easings = content.parse().unwrap();
+        easings.insert("intel_backlight".into(), "x^2.2".parse().unwrap());
+        easings.remove(&"input0::capslock".into());
+        easings.insert(
+            "class:leds".parse().unwrap(),
+            "x^3 | 0.9*x + 0.1".parse().unwrap(),
+        );
+
+        assert_eq!(
+            update_easings(content, &easings),
+            "# my screen\nintel_backlight = x^2.2\n\nx\nclass:leds = x^3 | 0.9*x + 0.1\n"
+        );
+        assert_eq!(update_easings("", &Easings::default()), "x\n");
+    }
+
+    #[test]
+    fn test_update_entry() {
+        let content = "# verify_writes = false\nverify_writes = true\nsandbox = true\n";
+        assert_eq!(
+            update_entry(content, "verify_writes", Some("false")),
+            "# verify_writes = false\nverify_writes = false\nsandbox = true\n"
+        );
+        assert_eq!(
+            update_entry(content, "sandbox", None),
+            "# verify_writes = false\nverify_writes = true\n"
+        );
+        assert_eq!(
+            update_entry("", "intel_backlight.read_fallback", Some("false")),
+            "intel_backlight.read_fallback = false\n"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! A declarative file of the brightness devices should have, applied by `bright apply`:
+//!
+//! ```toml
+//! intel_backlight = "40%"
+//!
+//! ["leds:tpacpi::kbd_backlight"]
+//! brightness = 0
+//! duration = "300ms"
+//! ```
+
+use crate::brightness::ast::{Ast, AstFromStrError};
+use std::str::FromStr;
+use thiserror::Error;
+use toml::{Table, Value};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DesiredState {
+    /// In the order of the file
+    pub devices: Vec<DesiredDevice>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DesiredDevice {
+    /// The device as given to `--device`
+    pub device: String,
+    pub brightness: Ast,
+    /// How long to animate the change, the settings apply if it isn't given
+    pub duration: Option<humantime::Duration>,
+}
+
+impl DesiredDevice {
+    fn from_value(device: String, value: &Value) -> Result<Self, DesiredStateParseError> {
+        let invalid = |reason: &str| DesiredStateParseError::InvalidDevice {
+            device: device.clone(),
+            reason: reason.to_string(),
+        };
+
+        let (brightness, duration) = match value {
+            Value::Table(table) => {
+                if let Some(key) = table
+                    .keys()
+                    .find(|key| !["brightness", "duration"].contains(&key.as_str()))
+                {
+                    return Err(invalid(&format!("unknown key `{key}`")));
+                }
+                let brightness = table
+                    .get("brightness")
+                    .ok_or_else(|| invalid("`brightness` is missing"))?;
+                let duration = match table.get("duration") {
+                    Some(Value::String(duration)) => Some(
+                        duration
+                            .parse()
+                            .map_err(|err| invalid(&format!("invalid duration: {err}")))?,
+                    ),
+                    Some(_) => return Err(invalid("the duration must be a string like `300ms`")),
+                    None => None,
+                };
+                (brightness, duration)
+            }
+            value => (value, None),
+        };
+
+        let brightness = match brightness {
+            Value::String(brightness) => brightness.parse(),
+            Value::Integer(brightness) => brightness.to_string().parse(),
+            _ => return Err(invalid("the brightness must be a string or a number")),
+        }
+        .map_err(|error| DesiredStateParseError::InvalidBrightness {
+            device: device.clone(),
+            error,
+        })?;
+
+        Ok(Self {
+            device,
+            brightness,
+            duration,
+        })
+    }
+}
+
+impl FromStr for DesiredState {
+    type Err = DesiredStateParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let table: Table = s.parse()?;
+        let devices = table
+            .into_iter()
+            .map(|(device, value)| DesiredDevice::from_value(device, &value))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { devices })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DesiredStateParseError {
+    #[error("invalid TOML: {_0}")]
+    Toml(
+        #[from]
+        #[source]
+        toml::de::Error,
+    ),
+    #[error("invalid entry for {device}: {reason}")]
+    InvalidDevice { device: String, reason: String },
+    #[error("invalid brightness for {device}: {error}")]
+    InvalidBrightness {
+        device: String,
+        #[source]
+        error: AstFromStrError,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse() {
+        let state: DesiredState = r#"
+            kbd = 2
+            intel_backlight = "40%"
+
+            ["leds:tpacpi::kbd_backlight"]
+            brightness = "max(1, 10%)"
+            duration = "300ms"
+        "#
+        .parse()
+        .unwrap();
+
+        let devices: Vec<_> = state
+            .devices
+            .iter()
+            .map(|device| (device.device.as_str(), device.duration.map(Duration::from)))
+            .collect();
+        // in the order of the file, not sorted
+        assert_eq!(
+            devices,
+            vec![
+                ("kbd", None),
+                ("intel_backlight", None),
+                (
+                    "leds:tpacpi::kbd_backlight",
+                    Some(Duration::from_millis(300))
+                )
+            ]
+        );
+        assert_eq!(state.devices[0].brightness, "2".parse().unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            "foo = true".parse::<DesiredState>(),
+            Err(DesiredStateParseError::InvalidDevice { .. })
+        ));
+        assert!(matches!(
+            "[foo]\nduration = \"1s\"".parse::<DesiredState>(),
+            Err(DesiredStateParseError::InvalidDevice { .. })
+        ));
+        assert!(matches!(
+            "foo = \"max(1\"".parse::<DesiredState>(),
+            Err(DesiredStateParseError::InvalidBrightness { .. })
+        ));
+    }
+}
@@ -0,0 +1,196 @@
+//! The panels of FreeBSD's `acpi_video(4)`, controlled using `sysctl hw.acpi.video.lcd0.brightness`.
+//! The firmware only accepts the percentages in `hw.acpi.video.lcd0.levels`, so the hardware
+//! levels of these devices are the positions in that list
+
+use super::{
+    BrightnessRead, BrightnessWrite, Device, DeviceClass, DeviceProvider,
+    errors::{DeviceReadError, DeviceWriteError},
+};
+use crate::{
+    brightness::perceptual::PerceptualMapper,
+    config::{Easings, settings::Settings},
+    meta::{Entries, Entry, Meta},
+};
+use std::{
+    io,
+    process::{Command, Stdio},
+};
+
+const PREFIX: &str = "hw.acpi.video.";
+
+pub struct AcpiVideo {
+    /// The output like `lcd0`
+    name: String,
+    /// The accepted percentages, sorted and without duplicates
+    levels: Vec<u32>,
+}
+
+/// Every output which supports changing its brightness
+pub fn find_outputs() -> Vec<AcpiVideo> {
+    let Ok(names) = sysctl(&["-N", "hw.acpi.video"]) else {
+        return Vec::new();
+    };
+
+    parse_outputs(&names)
+        .into_iter()
+        .filter_map(|name| {
+            let levels = sysctl(&["-n", &format!("{PREFIX}{name}.levels")]).ok()?;
+            let levels = parse_levels(&levels);
+            (levels.len() > 1).then(|| AcpiVideo {
+                name: name.to_string(),
+                levels,
+            })
+        })
+        .collect()
+}
+
+pub struct Provider;
+
+impl DeviceProvider for Provider {
+    fn class(&self) -> DeviceClass {
+        AcpiVideo::CLASS
+    }
+
+    fn discover(&self, _settings: &Settings, _easings: &Easings) -> Vec<Box<dyn Device>> {
+        find_outputs()
+            .into_iter()
+            .map(|output| Box::new(output) as Box<dyn Device>)
+            .collect()
+    }
+}
+
+impl AcpiVideo {
+    pub const CLASS: DeviceClass = DeviceClass::Backlight;
+
+    fn brightness_oid(&self) -> String {
+        format!("{PREFIX}{}.brightness", self.name)
+    }
+}
+
+impl BrightnessRead for AcpiVideo {
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn class(&self) -> DeviceClass {
+        Self::CLASS
+    }
+
+    fn max(&self) -> u32 {
+        self.levels.len() as u32 - 1
+    }
+
+    fn current(&self) -> Result<u32, DeviceReadError> {
+        let percent = sysctl(&["-n", &self.brightness_oid()])?.trim().parse()?;
+        Ok(nearest_level(&self.levels, percent))
+    }
+}
+
+impl BrightnessWrite for AcpiVideo {
+    fn set(&self, value: u32) -> Result<u32, DeviceWriteError<u32>> {
+        let Some(percent) = self.levels.get(value as usize) else {
+            return Err(DeviceWriteError::Overflow {
+                max: self.max(),
+                provided: value,
+            });
+        };
+
+        sysctl(&[&format!("{}={percent}", self.brightness_oid())])
+            .map_err(DeviceWriteError::Write)?;
+        Ok(value)
+    }
+}
+
+impl Meta for AcpiVideo {
+    fn meta<'a>(&'a self, easings: &'a Easings) -> Entries<'a> {
+        let entries = [
+            Entry::new("Device", || {
+                Some((self.name.clone(), Some(format!("{PREFIX}{}", self.name))))
+            }),
+            Entry::new("Current brightness", || {
+                let easing = easings.get_or_default(self.name(), Self::CLASS);
+                let cur = self.current().ok();
+                let perc = cur.map(|cur| PerceptualMapper::new(&easing, self.max()).percent(cur));
+                Some((
+                    cur.map_or_else(|| String::from('?'), |n| n.to_string()),
+                    perc.map(|p| format!("{p}%")),
+                ))
+            }),
+            Entry::new("Max brightness", || Some((self.max().to_string(), None))),
+            Entry::new("Levels", || {
+                let levels: Vec<_> = self.levels.iter().map(|l| format!("{l}%")).collect();
+                Some((
+                    levels.join(", "),
+                    Some("accepted by the firmware".to_string()),
+                ))
+            }),
+            Entry::new("Writes", || Some(("sysctl".to_string(), None))),
+        ];
+        Box::new(entries.into_iter())
+    }
+}
+
+fn sysctl(arguments: &[&str]) -> io::Result<String> {
+    let output = Command::new("sysctl")
+        .args(arguments)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "sysctl failed: {}",
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The outputs in a list of OIDs like `hw.acpi.video.lcd0.brightness`
+fn parse_outputs(names: &str) -> Vec<&str> {
+    names
+        .lines()
+        .filter_map(|line| {
+            line.trim()
+                .strip_prefix(PREFIX)?
+                .strip_suffix(".brightness")
+        })
+        .filter(|name| !name.contains('.'))
+        .collect()
+}
+
+/// The firmware's list starts with the defaults on AC and battery, which are repeated later on
+fn parse_levels(levels: &str) -> Vec<u32> {
+    let mut levels: Vec<_> = levels
+        .split_whitespace()
+        .filter_map(|level| level.parse().ok())
+        .collect();
+    levels.sort_unstable();
+    levels.dedup();
+    levels
+}
+
+fn nearest_level(levels: &[u32], percent: u32) -> u32 {
+    levels
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, level)| level.abs_diff(percent))
+        .map_or(0, |(i, _)| i as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let names = "hw.acpi.video.crt0.active\nhw.acpi.video.lcd0.active\n\
+                     hw.acpi.video.lcd0.brightness\nhw.acpi.video.lcd0.levels\n";
+        assert_eq!(parse_outputs(names), ["lcd0"]);
+
+        let levels = parse_levels("100 40 0 10 20 30 40 50 60 70 80 90 100\n");
+        assert_eq!(levels, [0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100]);
+        assert_eq!(nearest_level(&levels, 40), 4);
+        assert_eq!(nearest_level(&levels, 42), 4);
+        assert_eq!(nearest_level(&levels, 100), 10);
+    }
+}
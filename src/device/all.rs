@@ -0,0 +1,93 @@
+use super::{
+    BrightnessRead, BrightnessWrite, Device, DeviceClass, DeviceProvider,
+    backlight::find_deduped_backlights,
+    errors::{DeviceReadError, DeviceWriteError},
+    floor::with_floor,
+    group::DeviceGroup,
+};
+use crate::{
+    config::{Easings, settings::Settings},
+    meta::{Entries, Meta},
+};
+
+pub const NAME: &str = "all";
+
+/// A virtual device controlling all backlights at once.
+/// Its brightness is the average perceived brightness of all backlights, setting it moves all of
+/// them alike, so differences between them are kept, see [`DeviceGroup`]
+pub struct AllBacklights {
+    group: DeviceGroup,
+}
+
+impl AllBacklights {
+    pub const CLASS: DeviceClass = DeviceClass::Virtual;
+
+    /// Returns `None` if there are no backlights to control. Shadowed backlights are left out, as
+    /// they control the same panel as another one
+    pub fn find(settings: &Settings, easings: &Easings) -> Option<Self> {
+        let backlights = find_deduped_backlights(settings)?
+            .0
+            .into_iter()
+            .map(|bl| with_floor(Box::new(bl), settings, easings))
+            .collect();
+
+        DeviceGroup::new(
+            NAME.to_string(),
+            "all backlights".to_string(),
+            backlights,
+            easings,
+        )
+        .map(|group| Self { group })
+    }
+}
+
+pub struct Provider;
+
+impl DeviceProvider for Provider {
+    fn class(&self) -> DeviceClass {
+        AllBacklights::CLASS
+    }
+
+    fn discover(&self, settings: &Settings, easings: &Easings) -> Vec<Box<dyn Device>> {
+        self.by_name(NAME, settings, easings).into_iter().collect()
+    }
+
+    fn by_name(
+        &self,
+        name: &str,
+        settings: &Settings,
+        easings: &Easings,
+    ) -> Option<Box<dyn Device>> {
+        if name != NAME {
+            return None;
+        }
+        AllBacklights::find(settings, easings).map(|all| Box::new(all) as Box<dyn Device>)
+    }
+}
+
+impl BrightnessRead for AllBacklights {
+    delegate::delegate! {
+        to self.group {
+            fn name(&self) -> Option<&str>;
+            fn class(&self) -> DeviceClass;
+            fn max(&self) -> u32;
+            fn current(&self) -> Result<u32, DeviceReadError>;
+        }
+    }
+}
+
+impl BrightnessWrite for AllBacklights {
+    fn set(&self, value: u32) -> Result<u32, DeviceWriteError<u32>> {
+        self.group.set(value)
+    }
+
+    fn slow_writes(&self) -> bool {
+        self.group.slow_writes()
+    }
+}
+
+impl Meta for AllBacklights {
+    fn meta<'a>(&'a self, easings: &'a Easings) -> Entries<'a> {
+        self.group.meta(easings)
+    }
+}
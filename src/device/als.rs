@@ -0,0 +1,182 @@
+//! Ambient light sensors exposed by the industrial I/O subsystem. They can't be changed, so they
+//! aren't a [`Device`](super::Device), but their lux readings are useful next to the backlights
+
+use super::{UNNAMED, errors::DeviceNotFound, sysfs_root};
+use crate::{
+    config::Easings,
+    meta::{Entries, Entry, Meta},
+};
+use std::{
+    fs, io,
+    num::ParseFloatError,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// The already scaled illuminance, which not every driver provides
+const PROCESSED: &str = "in_illuminance_input";
+/// The raw illuminance, which is turned into lux using the scale and offset
+const RAW: &str = "in_illuminance_raw";
+
+/// All illuminance sensors below `/sys/bus/iio/devices`
+pub fn find_sensors() -> Vec<AmbientLightSensor> {
+    let Ok(entries) = sysfs_root().join("bus/iio/devices").read_dir() else {
+        return Vec::new();
+    };
+
+    let mut sensors: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| AmbientLightSensor::try_new(entry.path()))
+        .collect();
+    sensors.sort_by(|a, b| a.path.cmp(&b.path));
+    sensors
+}
+
+/// Finds a sensor by its name, like `iio:device0`, or the name the driver reports
+pub fn get_sensor(name: &str) -> Result<AmbientLightSensor, DeviceNotFound> {
+    let mut sensors = find_sensors();
+    let candidates = sensors
+        .iter()
+        .filter_map(|sensor| sensor.name().map(ToString::to_string))
+        .collect();
+
+    // names are preferred over labels, which are only read if no name matches
+    let index = sensors
+        .iter()
+        .position(|sensor| sensor.name() == Some(name))
+        .or_else(|| {
+            sensors
+                .iter()
+                .position(|sensor| sensor.label().as_deref() == Some(name))
+        });
+    index
+        .map(|index| sensors.swap_remove(index))
+        .ok_or(DeviceNotFound::NoNamed {
+            name: name.to_string(),
+            candidates,
+        })
+}
+
+#[derive(Debug)]
+pub struct AmbientLightSensor {
+    pub path: PathBuf,
+    processed: bool,
+}
+
+impl AmbientLightSensor {
+    /// Returns `None` if the iio device doesn't measure illuminance
+    pub fn try_new(path: PathBuf) -> Option<Self> {
+        let processed = if path.join(PROCESSED).is_file() {
+            true
+        } else if path.join(RAW).is_file() {
+            false
+        } else {
+            return None;
+        };
+
+        Some(Self { path, processed })
+    }
+
+    /// The name of the iio device, like `iio:device0`
+    pub fn name(&self) -> Option<&str> {
+        self.path.file_name()?.to_str()
+    }
+
+    /// The name the driver reports, like `acpi-als`
+    pub fn label(&self) -> Option<String> {
+        fs::read_to_string(self.path.join("name"))
+            .ok()
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+    }
+
+    /// The current illuminance in lux
+    pub fn lux(&self) -> Result<f64, SensorReadError> {
+        if self.processed {
+            return read_number(&self.path.join(PROCESSED));
+        }
+
+        let raw = read_number(&self.path.join(RAW))?;
+        let scale = optional_number(&self.path.join("in_illuminance_scale"))?.unwrap_or(1.0);
+        let offset = optional_number(&self.path.join("in_illuminance_offset"))?.unwrap_or(0.0);
+        Ok((raw + offset) * scale)
+    }
+}
+
+impl Meta for AmbientLightSensor {
+    fn meta<'a>(&'a self, _: &'a Easings) -> Entries<'a> {
+        let entries = [
+            Entry::new("Sensor", || {
+                Some((
+                    self.name().unwrap_or(UNNAMED).to_string(),
+                    Some(self.path.display().to_string()),
+                ))
+            }),
+            Entry::new("Label", || Some((self.label()?, None))),
+            Entry::new("Illuminance", || {
+                let lux = self.lux().ok();
+                Some((
+                    lux.map_or_else(|| String::from('?'), |lux| lux.to_string()),
+                    Some("lux".to_string()),
+                ))
+            }),
+            Entry::new("Reading", || {
+                let reading = if self.processed { "processed" } else { "raw" };
+                Some((reading.to_string(), None))
+            }),
+        ];
+        Box::new(entries.into_iter())
+    }
+}
+
+fn read_number(path: &Path) -> Result<f64, SensorReadError> {
+    Ok(fs::read_to_string(path)?.trim().parse()?)
+}
+
+fn optional_number(path: &Path) -> Result<Option<f64>, SensorReadError> {
+    match read_number(path) {
+        Err(SensorReadError::Read(err)) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SensorReadError {
+    #[error("can't read the illuminance: {_0}")]
+    Read(
+        #[from]
+        #[source]
+        io::Error,
+    ),
+    #[error("can't parse the illuminance: {_0}")]
+    Parse(
+        #[from]
+        #[source]
+        ParseFloatError,
+    ),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lux() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("iio:device0");
+        fs::create_dir(&path).unwrap();
+        assert!(AmbientLightSensor::try_new(path.clone()).is_none());
+
+        fs::write(path.join(RAW), "120\n").unwrap();
+        let sensor = AmbientLightSensor::try_new(path.clone()).unwrap();
+        assert_eq!(sensor.lux().unwrap(), 120.0);
+
+        fs::write(path.join("in_illuminance_scale"), "0.5\n").unwrap();
+        fs::write(path.join("in_illuminance_offset"), "10\n").unwrap();
+        assert_eq!(sensor.lux().unwrap(), 65.0);
+
+        fs::write(path.join(PROCESSED), "42.5\n").unwrap();
+        let sensor = AmbientLightSensor::try_new(path).unwrap();
+        assert_eq!(sensor.lux().unwrap(), 42.5);
+    }
+}
@@ -2,8 +2,9 @@ use derive_more::Display;
 use std::{io, num::ParseIntError};
 use thiserror::Error;
 
+/// The power of a backlight's display, written to `bl_power` using the framebuffer's blank levels
 #[repr(u8)]
-#[derive(Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
 pub enum BlPower {
     #[display("on")]
     On = 0,
@@ -25,6 +26,13 @@ impl BlPower {
             _ => None,
         }
     }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::On => Self::Off,
+            Self::Off => Self::On,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -1,8 +1,12 @@
 use derive_more::Display;
-use std::io;
+use std::{io, str::FromStr};
 use thiserror::Error;
 
-#[derive(Display)]
+/// Prefer the firmware and platform interfaces over the graphics driver when they control the
+/// same panel, like systemd does, as the firmware may keep its own idea of the brightness
+pub const DEFAULT_PREFERENCE: [BlType; 3] = [BlType::Firmware, BlType::Platform, BlType::Raw];
+
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlType {
     #[display("raw")]
     Raw,
@@ -24,6 +28,13 @@ impl TryFrom<&str> for BlType {
     }
 }
 
+impl FromStr for BlType {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s)
+    }
+}
+
 impl From<BlType> for &str {
     fn from(value: BlType) -> Self {
         match value {
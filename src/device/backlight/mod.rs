@@ -1,16 +1,21 @@
-mod bl_power;
-mod bl_type;
+pub mod bl_power;
+pub mod bl_type;
+pub mod shadow;
 
 use super::{
-    BRIGHTNESS_FILES, Device, DeviceClass,
+    BRIGHTNESS_FILES, BrightnessRead, BrightnessWrite, Device, DeviceClass, DeviceProvider,
+    discovery::{DiscoveryReport, sysfs_entry},
     errors::{DeviceReadError, DeviceWriteError},
-    led::Led,
+    label,
+    led::{BuildDeviceError, Led, LedBuilder},
+    quirks::Quirk,
 };
 use crate::{
-    config::Easings,
-    meta::{Information, Meta},
+    config::{Easings, settings::Settings},
+    meta::{Entries, Entry, Meta},
 };
 use std::{
+    cell::Cell,
     fs::{self, OpenOptions},
     io::{self, Write},
     num::ParseIntError,
@@ -39,52 +44,166 @@ const ALL_FILES: [&str; BRIGHTNESS_FILES.len() + BACKLIGHT_FILES.len()] = {
     res
 };
 
-pub fn find_backlights() -> Option<Vec<Backlight>> {
-    let lights = Path::new(CLASS.path())
-        .read_dir()
-        .ok()?
-        .filter_map(Result::ok)
-        .filter_map(|entry| Backlight::try_new(entry.path()).ok())
-        .collect();
-    Some(lights)
+/// All backlights, the ones shadowed by a preferred backlight for the same panel come last
+pub fn find_backlights(settings: &Settings) -> Option<Vec<Backlight>> {
+    let (mut backlights, shadowed) = find_deduped_backlights(settings)?;
+    backlights.extend(shadowed.into_iter().map(|shadowed| shadowed.backlight));
+    Some(backlights)
+}
+
+/// The backlights to use, ordered by the preferred type, and the shadowed ones, see [`shadow`]
+pub fn find_deduped_backlights(
+    settings: &Settings,
+) -> Option<(Vec<Backlight>, Vec<shadow::Shadowed>)> {
+    let backlights = discover_backlights(settings)?.devices;
+    Some(shadow::dedupe(backlights, settings.backlight_preference()))
+}
+
+/// Like [`find_backlights`] but also reports the entries which aren't usable backlights
+pub fn discover_backlights(settings: &Settings) -> Option<DiscoveryReport<Backlight>> {
+    let report = DiscoveryReport::scan(&CLASS.path(), Backlight::try_new)?;
+    Some(report.map(|bl| bl.configured(settings)))
+}
+
+/// The backlights in sysfs, including shadowed ones
+pub struct Provider;
+
+impl DeviceProvider for Provider {
+    fn class(&self) -> DeviceClass {
+        CLASS
+    }
+
+    fn discover(&self, settings: &Settings, _easings: &Easings) -> Vec<Box<dyn Device>> {
+        find_backlights(settings)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|bl| Box::new(bl) as Box<dyn Device>)
+            .collect()
+    }
+
+    fn by_name(
+        &self,
+        name: &str,
+        settings: &Settings,
+        _easings: &Easings,
+    ) -> Option<Box<dyn Device>> {
+        let bl = Backlight::try_new(CLASS.path().join(sysfs_entry(name)?)).ok()?;
+        Some(Box::new(bl.configured(settings)))
+    }
 }
 
 #[derive(Debug)]
 pub struct Backlight {
     led: Led,
+    /// Whether to fall back to `brightness` if `actual_brightness` can't be read or reports 0
+    read_fallback: bool,
+    /// Whether the last read fell back to `brightness`
+    fell_back: Cell<bool>,
 }
 
-impl Device for Backlight {
+impl BrightnessRead for Backlight {
     delegate::delegate! {
         to self.led {
             fn name(&self) -> Option<&str>;
-            fn max(&self) -> u16;
-            fn set(&self, value: u16) -> Result<u16, DeviceWriteError<u16>>;
+            fn max(&self) -> u32;
             fn path(&self) -> Option<PathBuf>;
+            fn min_step(&self) -> u32;
         }
     }
 
-    fn current(&self) -> Result<u16, DeviceReadError> {
-        self.parse("actual_brightness")
+    fn class(&self) -> DeviceClass {
+        CLASS
     }
-}
 
-impl Meta for Backlight {
-    fn meta(&self, easings: &Easings) -> Vec<Information> {
-        let mut list = self.led.meta(easings);
-        if let Ok(mode) = self.power_mode() {
-            list.push(Information::new(
-                "Power mode".to_string(),
-                mode.to_string(),
-                None,
-            ));
+    fn label(&self) -> Option<String> {
+        // the DRM connector, if the driver links it, has the panel's EDID. Otherwise only the
+        // machine is known, whose built-in display backlights are for
+        label::connector_label(&self.led.dev_path.join("device")).or_else(|| {
+            let bl_type = self.bl_type().ok()?;
+            Some(format!(
+                "Built-in display of {} ({bl_type})",
+                label::dmi_product()?
+            ))
+        })
+    }
+
+    fn backlight_type(&self) -> Option<bl_type::BlType> {
+        self.bl_type().ok()
+    }
+
+    fn power(&self) -> Option<bl_power::BlPower> {
+        self.power_mode().ok()
+    }
+
+    fn display_off(&self) -> bool {
+        // the DRM connector, if the driver links it, knows about DPMS
+        let dpms = fs::read_to_string(self.led.dev_path.join("device").join("dpms"));
+        matches!(self.power_mode(), Ok(bl_power::BlPower::Off))
+            || dpms.is_ok_and(|dpms| dpms.trim_end() == "Off")
+    }
+
+    fn current(&self) -> Result<u32, DeviceReadError> {
+        self.led.wait_for_settle();
+        if self.led.has_quirk(Quirk::StaleActual) {
+            return self.wanted_brightness();
+        }
+
+        let actual = self.parse("actual_brightness");
+        self.fell_back.set(false);
+        if !self.read_fallback {
+            return actual;
         }
 
-        if let Ok(bl_t) = self.bl_type() {
-            list.push(Information::new("Type".to_string(), bl_t.to_string(), None));
+        match actual {
+            Ok(0) | Err(_) => match self.wanted_brightness() {
+                Ok(wanted) if wanted != 0 || actual.is_err() => {
+                    self.fell_back.set(true);
+                    Ok(wanted)
+                }
+                _ => actual,
+            },
+            actual => actual,
         }
+    }
+
+    fn fell_back(&self) -> bool {
+        self.fell_back.get()
+    }
+}
+
+impl BrightnessWrite for Backlight {
+    delegate::delegate! {
+        to self.led {
+            fn set(&self, value: u32) -> Result<u32, DeviceWriteError<u32>>;
+            fn slow_writes(&self) -> bool;
+        }
+    }
+
+    fn set_power(&self, power: bl_power::BlPower) -> io::Result<()> {
+        let path = self.led.dev_path.join("bl_power");
+        let mut file = OpenOptions::new()
+            .read(false)
+            .create(false)
+            .write(true)
+            .open(path)?;
+
+        write!(file, "{}", u8::from(power))
+    }
+}
 
-        list
+impl Meta for Backlight {
+    fn meta<'a>(&'a self, easings: &'a Easings) -> Entries<'a> {
+        let entries = [
+            Entry::new("Power mode", || {
+                Some((self.power_mode().ok()?.to_string(), None))
+            }),
+            Entry::new("Type", || Some((self.bl_type().ok()?.to_string(), None))),
+        ];
+        Box::new(
+            self.led
+                .meta_with_label(easings, || self.label())
+                .chain(entries),
+        )
     }
 }
 
@@ -105,7 +224,9 @@ impl Backlight {
 
         if relevant_files_count == ALL_FILES.len() {
             Ok(Self {
-                led: unsafe { Led::new_unchecked(path)? },
+                led: Led::read_max(path)?,
+                read_fallback: false,
+                fell_back: Cell::new(false),
             })
         } else {
             Err(NewBacklightError::NotEnoughFiles {
@@ -114,23 +235,31 @@ impl Backlight {
         }
     }
 
+    /// Creates a device from a known path without checking which files it contains, e.g.
+    /// `Backlight::builder().path(path).max(1000).build()`
+    pub fn builder() -> BacklightBuilder {
+        BacklightBuilder::default()
+    }
+
+    pub fn with_read_fallback(self, read_fallback: bool) -> Self {
+        Self {
+            read_fallback,
+            ..self
+        }
+    }
+
+    /// Applies the settings of this backlight
+    pub fn configured(self, settings: &Settings) -> Self {
+        let read_fallback = settings.device(self.name()).read_fallback;
+        self.with_read_fallback(read_fallback.unwrap_or(false))
+    }
+
     pub fn power_mode(&self) -> Result<bl_power::BlPower, bl_power::BlPowerReadError> {
         let path = self.led.dev_path.join("bl_power");
         let num = fs::read_to_string(path)?.trim_end().parse()?;
         bl_power::BlPower::try_new(num).ok_or(bl_power::BlPowerReadError::InvalidNumber(num))
     }
 
-    pub fn set_power(&self, power: bl_power::BlPower) -> io::Result<()> {
-        let path = self.led.dev_path.join("bl_power");
-        let mut file = OpenOptions::new()
-            .read(false)
-            .create(false)
-            .write(true)
-            .open(path)?;
-
-        write!(file, "{}", u8::from(power))
-    }
-
     pub fn bl_type(&self) -> Result<bl_type::BlType, bl_type::BlTypeReadError> {
         let path = self.led.dev_path.join("type");
         let content = fs::read_to_string(path)?;
@@ -150,11 +279,51 @@ impl Backlight {
             .map_err(DeviceReadError::from)
     }
 
-    pub fn wanted_brightness(&self) -> Result<u16, DeviceReadError> {
+    pub fn wanted_brightness(&self) -> Result<u32, DeviceReadError> {
         self.parse("brightness")
     }
 }
 
+#[derive(Debug, Default, Clone)]
+pub struct BacklightBuilder {
+    led: LedBuilder,
+    read_fallback: bool,
+}
+
+impl BacklightBuilder {
+    /// The device's directory, like `/sys/class/backlight/intel_backlight`
+    pub fn path(self, path: impl Into<PathBuf>) -> Self {
+        Self {
+            led: self.led.path(path),
+            ..self
+        }
+    }
+
+    /// The maximal brightness, read from `max_brightness` if it isn't set
+    pub fn max(self, max: u32) -> Self {
+        Self {
+            led: self.led.max(max),
+            ..self
+        }
+    }
+
+    /// See [`Backlight::with_read_fallback`]
+    pub fn read_fallback(self, read_fallback: bool) -> Self {
+        Self {
+            read_fallback,
+            ..self
+        }
+    }
+
+    pub fn build(self) -> Result<Backlight, BuildDeviceError> {
+        Ok(Backlight {
+            led: self.led.build()?,
+            read_fallback: self.read_fallback,
+            fell_back: Cell::new(false),
+        })
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum NewBacklightError {
     #[error("can't list content of directory")]
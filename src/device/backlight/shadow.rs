@@ -0,0 +1,224 @@
+//! Laptops often expose the same panel several times, e.g. as `acpi_video0` of type `firmware`
+//! and as `intel_backlight` of type `raw`. Only the preferred one of them should be used, the
+//! others are shadowed
+
+use super::{Backlight, bl_type::BlType};
+use crate::device::{BrightnessRead, UNNAMED};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A backlight controlling the same panel as a preferred one
+#[derive(Debug)]
+pub struct Shadowed {
+    pub backlight: Backlight,
+    /// The name of the preferred backlight
+    pub by: String,
+}
+
+/// Splits the backlights into the ones to use, ordered by their type's position in `preference`,
+/// and the shadowed ones. Types which aren't in `preference` come last
+pub fn dedupe(
+    backlights: Vec<Backlight>,
+    preference: &[BlType],
+) -> (Vec<Backlight>, Vec<Shadowed>) {
+    let mut ranked: Vec<_> = backlights
+        .into_iter()
+        .map(|bl| {
+            let bl_type = bl.bl_type().ok();
+            let rank = bl_type.map_or(preference.len() + 1, |ty| {
+                preference
+                    .iter()
+                    .position(|preferred| *preferred == ty)
+                    .unwrap_or(preference.len())
+            });
+            (rank, bl_type, bl.panel(), bl)
+        })
+        .collect();
+    ranked.sort_by_key(|(rank, ..)| *rank);
+
+    // without a PCI device, like for a platform driver, it's the internal panel
+    let internal = internal_gpu(
+        ranked
+            .iter()
+            .filter_map(|(_, _, panel, _)| panel.as_deref()),
+    );
+    for (_, _, panel, _) in &mut ranked {
+        if panel.is_none() {
+            panel.clone_from(&internal);
+        }
+    }
+
+    let mut kept: Vec<(Option<BlType>, Option<PathBuf>, Backlight)> = Vec::new();
+    let mut shadowed = Vec::new();
+    for (_, bl_type, panel, bl) in ranked {
+        let by = kept.iter().find(|(kept_type, kept_panel, _)| {
+            // backlights of the same type are distinct panels, like two raw ones of two GPUs
+            let distinct_types = bl_type.is_some() && kept_type.is_some() && bl_type != *kept_type;
+            // two backlights without a known GPU are both the internal panel's
+            distinct_types && panel == *kept_panel
+        });
+        match by {
+            Some((_, _, by)) => shadowed.push(Shadowed {
+                by: by.name().unwrap_or(UNNAMED).to_string(),
+                backlight: bl,
+            }),
+            None => kept.push((bl_type, panel, bl)),
+        }
+    }
+
+    (kept.into_iter().map(|(_, _, bl)| bl).collect(), shadowed)
+}
+
+/// The GPU with a built-in connector like `card0-eDP-1`, `None` if none or several have one
+fn internal_gpu<'a>(gpus: impl Iterator<Item = &'a Path>) -> Option<PathBuf> {
+    let mut internal: Vec<_> = gpus.filter(|gpu| has_internal_connector(gpu)).collect();
+    internal.sort();
+    internal.dedup();
+    match internal[..] {
+        [gpu] => Some(gpu.to_path_buf()),
+        _ => None,
+    }
+}
+
+fn has_internal_connector(gpu: &Path) -> bool {
+    let Ok(cards) = fs::read_dir(gpu.join("drm")) else {
+        return false;
+    };
+    cards
+        .filter_map(Result::ok)
+        .filter_map(|card| fs::read_dir(card.path()).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .any(|connector| {
+            let name = connector.file_name();
+            let Some((_, connector)) = name.to_str().and_then(|name| name.split_once('-')) else {
+                return false;
+            };
+            ["eDP-", "LVDS-", "DSI-"]
+                .iter()
+                .any(|internal| connector.starts_with(internal))
+        })
+}
+
+impl Backlight {
+    /// The PCI device, usually the GPU, the backlight belongs to
+    fn panel(&self) -> Option<PathBuf> {
+        let device = fs::canonicalize(self.led.dev_path.join("device")).ok()?;
+        device
+            .ancestors()
+            .find(|dir| {
+                dir.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(is_pci_address)
+            })
+            .map(PathBuf::from)
+    }
+}
+
+/// Like `0000:00:02.0`
+fn is_pci_address(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() == 12
+        && bytes.iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b':',
+            10 => *b == b'.',
+            _ => b.is_ascii_hexdigit(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, os::unix, path::Path};
+
+    fn backlight(class: &Path, name: &str, bl_type: &str, device: Option<&Path>) -> Backlight {
+        let dir = class.join(name);
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("type"), format!("{bl_type}\n")).unwrap();
+        if let Some(device) = device {
+            unix::fs::symlink(device, dir.join("device")).unwrap();
+        }
+        Backlight::builder().path(dir).max(100).build().unwrap()
+    }
+
+    fn names(backlights: &[Backlight]) -> Vec<&str> {
+        backlights.iter().filter_map(|bl| bl.name()).collect()
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let root = tempfile::tempdir().unwrap();
+        let gpu = root.path().join("devices/pci0000:00/0000:00:02.0");
+        let connector = gpu.join("drm/card0/card0-eDP-1");
+        let other_gpu = root.path().join("devices/pci0000:00/0000:01:00.0");
+        fs::create_dir_all(&connector).unwrap();
+        fs::create_dir_all(&other_gpu).unwrap();
+        let class = root.path().join("class/backlight");
+        fs::create_dir_all(&class).unwrap();
+
+        let backlights = || {
+            vec![
+                backlight(&class, "intel_backlight", "raw", Some(&connector)),
+                backlight(&class, "acpi_video0", "firmware", Some(&gpu)),
+                backlight(&class, "nvidia_0", "raw", Some(&other_gpu)),
+            ]
+        };
+
+        let (kept, shadowed) = dedupe(backlights(), &[BlType::Firmware, BlType::Raw]);
+        assert_eq!(names(&kept), ["acpi_video0", "nvidia_0"]);
+        assert_eq!(shadowed.len(), 1);
+        assert_eq!(shadowed[0].backlight.name(), Some("intel_backlight"));
+        assert_eq!(shadowed[0].by, "acpi_video0");
+
+        fs::remove_dir_all(&class).unwrap();
+        fs::create_dir_all(&class).unwrap();
+        let (kept, shadowed) = dedupe(backlights(), &[BlType::Raw]);
+        assert_eq!(names(&kept), ["intel_backlight", "nvidia_0"]);
+        assert_eq!(shadowed[0].backlight.name(), Some("acpi_video0"));
+        assert_eq!(shadowed[0].by, "intel_backlight");
+    }
+
+    #[test]
+    fn test_dedupe_platform() {
+        let root = tempfile::tempdir().unwrap();
+        let gpu = root.path().join("devices/pci0000:00/0000:00:02.0");
+        let other_gpu = root.path().join("devices/pci0000:00/0000:01:00.0");
+        let connector = gpu.join("drm/card0/card0-eDP-1");
+        fs::create_dir_all(&other_gpu).unwrap();
+        fs::create_dir_all(&connector).unwrap();
+        let class = root.path().join("class/backlight");
+
+        let backlights = |gpu: &Path| {
+            fs::create_dir_all(&class).unwrap();
+            vec![
+                backlight(&class, "nvidia_0", "raw", Some(&other_gpu)),
+                backlight(&class, "intel_backlight", "raw", Some(gpu)),
+                backlight(&class, "dell_backlight", "platform", None),
+            ]
+        };
+
+        // a platform backlight is the panel of the GPU with the eDP connector
+        let (kept, shadowed) = dedupe(backlights(&gpu), &[BlType::Raw]);
+        assert_eq!(names(&kept), ["nvidia_0", "intel_backlight"]);
+        assert_eq!(shadowed[0].backlight.name(), Some("dell_backlight"));
+        assert_eq!(shadowed[0].by, "intel_backlight");
+
+        // if the GPU with the eDP connector has no backlight, it isn't any other GPU's
+        fs::remove_dir_all(&class).unwrap();
+        let (kept, shadowed) = dedupe(backlights(&other_gpu), &[BlType::Raw]);
+        assert_eq!(
+            names(&kept),
+            ["nvidia_0", "intel_backlight", "dell_backlight"]
+        );
+        assert!(shadowed.is_empty());
+    }
+
+    #[test]
+    fn test_pci_address() {
+        assert!(is_pci_address("0000:00:02.0"));
+        assert!(!is_pci_address("pci0000:00"));
+        assert!(!is_pci_address("card0-eDP-1"));
+    }
+}
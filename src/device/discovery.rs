@@ -0,0 +1,122 @@
+//! Scanning a class directory in sysfs, which keeps the entries that aren't usable devices
+//! together with the reason instead of silently dropping them
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub struct DiscoveryReport<T> {
+    pub devices: Vec<T>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+/// A directory entry which couldn't be turned into a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedEntry {
+    pub path: PathBuf,
+    /// The error including all of its sources
+    pub reason: String,
+}
+
+impl<T> DiscoveryReport<T> {
+    /// Tries to create a device from every entry of `dir`, returns `None` if it can't be listed
+    pub fn scan<E: Error>(dir: &Path, try_new: impl Fn(PathBuf) -> Result<T, E>) -> Option<Self> {
+        let mut report = Self {
+            devices: Vec::new(),
+            skipped: Vec::new(),
+        };
+
+        for entry in dir.read_dir().ok()? {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(err) => {
+                    report.skipped.push(SkippedEntry {
+                        path: dir.to_path_buf(),
+                        reason: chain(&err),
+                    });
+                    continue;
+                }
+            };
+            match try_new(path.clone()) {
+                Ok(device) => report.devices.push(device),
+                Err(err) => report.skipped.push(SkippedEntry {
+                    path,
+                    reason: chain(&err),
+                }),
+            }
+        }
+
+        Some(report)
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> DiscoveryReport<U> {
+        DiscoveryReport {
+            devices: self.devices.into_iter().map(f).collect(),
+            skipped: self.skipped,
+        }
+    }
+}
+
+/// The name of a device as a single entry of its class directory, `None` for names like `..` which
+/// would leave it
+pub(crate) fn sysfs_entry(name: &str) -> Option<&str> {
+    let separator = |c| c == '/' || c == '\0';
+    (!name.is_empty() && name != "." && name != ".." && !name.contains(separator)).then_some(name)
+}
+
+/// The error including all of its sources, separated by colons
+pub(crate) fn chain(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::led::Led;
+    use std::fs;
+
+    #[test]
+    fn test_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let complete = dir.path().join("input0::capslock");
+        fs::create_dir(&complete).unwrap();
+        fs::write(complete.join("brightness"), "0\n").unwrap();
+        fs::write(complete.join("max_brightness"), "1\n").unwrap();
+        let incomplete = dir.path().join("broken");
+        fs::create_dir(&incomplete).unwrap();
+        fs::write(incomplete.join("brightness"), "0\n").unwrap();
+
+        let report = DiscoveryReport::scan(dir.path(), Led::try_new).unwrap();
+        assert_eq!(report.devices.len(), 1);
+        assert_eq!(
+            report.skipped,
+            [SkippedEntry {
+                path: incomplete,
+                reason: "only 1 of 2 required files were found".to_string(),
+            }]
+        );
+
+        assert!(DiscoveryReport::scan(&dir.path().join("missing"), Led::try_new).is_none());
+    }
+
+    #[test]
+    fn test_sysfs_entry() {
+        assert_eq!(
+            sysfs_entry("tpacpi::kbd_backlight"),
+            Some("tpacpi::kbd_backlight")
+        );
+        assert_eq!(sysfs_entry(".."), None);
+        assert_eq!(sysfs_entry("../leds"), None);
+        assert_eq!(sysfs_entry(""), None);
+        assert_eq!(sysfs_entry("a\0b"), None);
+    }
+}
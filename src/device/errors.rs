@@ -1,4 +1,4 @@
-use std::{io, num::ParseIntError};
+use std::{io, num::ParseIntError, path::PathBuf};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -37,18 +37,64 @@ where
 {
     #[error("provided brightness {provided} is bigger than {max}")]
     Overflow { max: T, provided: T },
+    /// The device was removed, e.g. an unplugged keyboard
+    #[error("the device disappeared")]
+    DeviceGone(#[source] io::Error),
     #[error("writing the file failed {}", .0.kind())]
-    Write(
-        #[from]
-        #[source]
-        io::Error,
-    ),
+    Write(#[source] io::Error),
+}
+
+impl<T> From<io::Error> for DeviceWriteError<T>
+where
+    T: std::cmp::Ord + std::fmt::Display,
+{
+    fn from(err: io::Error) -> Self {
+        match err.raw_os_error() {
+            Some(libc::ENOENT | libc::ENODEV) => Self::DeviceGone(err),
+            _ => Self::Write(err),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum DeviceNotFound {
-    #[error("no device named '{name}' available")]
-    NoNamed { name: String },
+    #[error(
+        "no device named '{name}' available{}",
+        if candidates.is_empty() { String::new() } else { format!(", try one of {}", candidates.join(", ")) }
+    )]
+    NoNamed {
+        name: String,
+        /// All devices which are available
+        candidates: Vec<String>,
+    },
+    #[error("'{name}' matches several devices, choose one of {}", candidates.join(", "))]
+    Ambiguous {
+        name: String,
+        candidates: Vec<String>,
+    },
     #[error("no device available")]
     NoFound,
+    #[error("{} isn't a device: {reason}", path.display())]
+    InvalidPath { path: PathBuf, reason: String },
+    #[error("'{pattern}' isn't a valid pattern: {reason}")]
+    InvalidPattern { pattern: String, reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_gone() {
+        for errno in [libc::ENOENT, libc::ENODEV] {
+            assert!(matches!(
+                DeviceWriteError::<u32>::from(io::Error::from_raw_os_error(errno)),
+                DeviceWriteError::DeviceGone(_)
+            ));
+        }
+        assert!(matches!(
+            DeviceWriteError::<u32>::from(io::Error::from_raw_os_error(libc::EIO)),
+            DeviceWriteError::Write(_)
+        ));
+    }
 }
@@ -0,0 +1,91 @@
+//! The `min` setting, enforced for every write to a device no matter which command, group or
+//! library function does it
+
+use super::{
+    BrightnessRead, BrightnessWrite, Device, DeviceClass,
+    backlight::{bl_power::BlPower, bl_type::BlType},
+    errors::{DeviceReadError, DeviceWriteError},
+};
+use crate::{
+    config::{Easings, settings::Settings},
+    meta::{Entries, Meta},
+};
+use std::{io, path::PathBuf, time::Duration};
+
+/// A device which isn't set below its `min` setting. One which is already below it isn't raised,
+/// it just doesn't go any lower
+pub struct Floored {
+    device: Box<dyn Device>,
+    min: u32,
+}
+
+/// Wraps the device if it has a `min` setting. A setting which can't be evaluated is ignored here,
+/// the commands report it
+pub fn with_floor(
+    device: Box<dyn Device>,
+    settings: &Settings,
+    easings: &Easings,
+) -> Box<dyn Device> {
+    let Some(min) = settings.device(device.name()).min else {
+        return device;
+    };
+    let easing = easings.get_or_default(device.name(), device.class());
+    match min.evaluate(&*device, &easing) {
+        Ok(min) if min > 0 => Box::new(Floored { device, min }),
+        _ => device,
+    }
+}
+
+impl Floored {
+    /// The lowest value `value` may be lowered to
+    fn floor(&self, value: u32) -> u32 {
+        if value >= self.min {
+            return value;
+        }
+        let floor = self
+            .device
+            .current()
+            .map_or(self.min, |current| current.min(self.min));
+        value.max(floor)
+    }
+}
+
+impl BrightnessRead for Floored {
+    delegate::delegate! {
+        to self.device {
+            fn name(&self) -> Option<&str>;
+            fn class(&self) -> DeviceClass;
+            fn max(&self) -> u32;
+            fn current(&self) -> Result<u32, DeviceReadError>;
+            fn path(&self) -> Option<PathBuf>;
+            fn label(&self) -> Option<String>;
+            fn backlight_type(&self) -> Option<BlType>;
+            fn power(&self) -> Option<BlPower>;
+            fn display_off(&self) -> bool;
+            fn trigger(&self) -> Option<String>;
+            fn fell_back(&self) -> bool;
+            fn min_step(&self) -> u32;
+            fn wait_for_change(&self, timeout: Duration) -> io::Result<()>;
+        }
+    }
+}
+
+impl BrightnessWrite for Floored {
+    fn set(&self, value: u32) -> Result<u32, DeviceWriteError<u32>> {
+        self.device.set(self.floor(value))
+    }
+
+    delegate::delegate! {
+        to self.device {
+            fn slow_writes(&self) -> bool;
+            fn set_trigger(&self, trigger: &str) -> io::Result<()>;
+            fn set_power(&self, power: BlPower) -> io::Result<()>;
+        }
+    }
+}
+
+impl Meta for Floored {
+    fn meta<'a>(&'a self, easings: &'a Easings) -> Entries<'a> {
+        self.device.meta(easings)
+    }
+}
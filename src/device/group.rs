@@ -0,0 +1,247 @@
+//! Named groups of devices from the settings like `@displays = intel_backlight, ddc-DP-1`, which
+//! are changed together using `--device @displays`
+
+use super::{
+    BrightnessRead, BrightnessWrite, Device, DeviceClass, DeviceProvider, UNNAMED,
+    errors::{DeviceReadError, DeviceWriteError},
+    find_named, hardware_providers,
+};
+use crate::{
+    animation::easing::EasingKind,
+    brightness::perceptual::PerceptualMapper,
+    config::{Easings, settings::Settings},
+    meta::{Entries, Entry, Meta},
+};
+use std::cell::RefCell;
+
+/// The brightness is the perceived brightness in permille
+const MAX: u32 = 1000;
+
+/// Several devices acting as one.
+/// Its brightness is the average perceived brightness of the members, setting it moves all of
+/// them alike, so differences between them are kept, see [`scale`]
+pub struct DeviceGroup {
+    name: String,
+    /// What the group controls, like `all backlights`
+    description: String,
+    members: Vec<(Box<dyn Device>, EasingKind)>,
+    /// The perceived brightness of every member when the group was last read, see [`Self::start`]
+    start: RefCell<Option<Vec<f64>>>,
+}
+
+impl DeviceGroup {
+    pub const CLASS: DeviceClass = DeviceClass::Virtual;
+
+    /// Returns `None` if there are no members to control
+    pub fn new(
+        name: String,
+        description: String,
+        members: Vec<Box<dyn Device>>,
+        easings: &Easings,
+    ) -> Option<Self> {
+        let members: Vec<_> = members
+            .into_iter()
+            .map(|device| {
+                let easing = easings.get_or_default(device.name(), device.class());
+                (device, easing)
+            })
+            .collect();
+
+        (!members.is_empty()).then_some(Self {
+            name,
+            description,
+            members,
+            start: RefCell::new(None),
+        })
+    }
+
+    /// Looks up the members of a configured group, members which don't exist right now, like an
+    /// unplugged monitor, are left out
+    pub fn find(
+        name: &str,
+        members: &[String],
+        settings: &Settings,
+        easings: &Easings,
+    ) -> Option<Self> {
+        let providers = hardware_providers();
+        let devices = members
+            .iter()
+            .filter_map(|member| find_named(&providers, member, settings, easings).ok())
+            .collect();
+        Self::new(format!("@{name}"), "group".to_string(), devices, easings)
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = (&dyn Device, &EasingKind)> {
+        self.members
+            .iter()
+            .map(|(device, easing)| (&**device, easing))
+    }
+
+    fn perceived(device: &dyn Device, easing: &EasingKind) -> Result<f64, DeviceReadError> {
+        Ok(PerceptualMapper::new(easing, device.max()).perceived(device.current()?))
+    }
+
+    fn levels(&self) -> Result<Vec<f64>, DeviceReadError> {
+        self.members()
+            .map(|(device, easing)| Self::perceived(device, easing))
+            .collect()
+    }
+
+    /// The levels the members are scaled from. They are read along with the group's brightness,
+    /// so an animation, which reads it before the first frame, scales every frame from the same
+    /// levels instead of the rounded ones of the previous frame
+    fn start(&self) -> Vec<f64> {
+        self.start
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                // without a readable level the member is treated as off
+                self.members()
+                    .map(|(device, easing)| Self::perceived(device, easing).unwrap_or(0.0))
+                    .collect()
+            })
+            .clone()
+    }
+}
+
+/// Moves a member from `level` as the group's average moves from `from` to `to`. Lowering scales
+/// every member by the same factor, raising closes the same share of every member's distance to
+/// the maximum, so the average is `to` and no member is cut off at the top
+fn scale(level: f64, from: f64, to: f64) -> f64 {
+    if to <= from {
+        if from > 0.0 { level * to / from } else { to }
+    } else if from < 1.0 {
+        level + (1.0 - level) * (to - from) / (1.0 - from)
+    } else {
+        to
+    }
+}
+
+/// The groups configured in the settings
+pub struct Provider;
+
+impl DeviceProvider for Provider {
+    fn class(&self) -> DeviceClass {
+        DeviceGroup::CLASS
+    }
+
+    fn discover(&self, settings: &Settings, easings: &Easings) -> Vec<Box<dyn Device>> {
+        settings
+            .groups()
+            .filter_map(|(name, members)| DeviceGroup::find(name, members, settings, easings))
+            .map(|group| Box::new(group) as Box<dyn Device>)
+            .collect()
+    }
+
+    fn by_name(
+        &self,
+        name: &str,
+        settings: &Settings,
+        easings: &Easings,
+    ) -> Option<Box<dyn Device>> {
+        let name = name.strip_prefix('@')?;
+        let (name, members) = settings.groups().find(|(group, _)| *group == name)?;
+        DeviceGroup::find(name, members, settings, easings)
+            .map(|group| Box::new(group) as Box<dyn Device>)
+    }
+}
+
+impl BrightnessRead for DeviceGroup {
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn class(&self) -> DeviceClass {
+        Self::CLASS
+    }
+
+    fn max(&self) -> u32 {
+        MAX
+    }
+
+    fn current(&self) -> Result<u32, DeviceReadError> {
+        let levels = self.levels()?;
+        let average = levels.iter().sum::<f64>() / levels.len() as f64;
+        *self.start.borrow_mut() = Some(levels);
+        Ok((average * f64::from(MAX)).round() as u32)
+    }
+}
+
+impl BrightnessWrite for DeviceGroup {
+    fn set(&self, value: u32) -> Result<u32, DeviceWriteError<u32>> {
+        if value > MAX {
+            return Err(DeviceWriteError::Overflow {
+                max: MAX,
+                provided: value,
+            });
+        }
+
+        let start = self.start();
+        let from = start.iter().sum::<f64>() / start.len() as f64;
+        let to = f64::from(value) / f64::from(MAX);
+
+        let mut applied = 0.0;
+        for ((device, easing), level) in self.members().zip(start) {
+            let mapper = PerceptualMapper::new(easing, device.max());
+            let written = device.set(mapper.level(scale(level, from, to)))?;
+            applied += mapper.perceived(written);
+        }
+
+        Ok((applied / self.members.len() as f64 * f64::from(MAX)).round() as u32)
+    }
+
+    fn slow_writes(&self) -> bool {
+        self.members().any(|(device, _)| device.slow_writes())
+    }
+}
+
+impl Meta for DeviceGroup {
+    fn meta<'a>(&'a self, _: &'a Easings) -> Entries<'a> {
+        let entries = [
+            Entry::new("Device", || {
+                Some((self.name.clone(), Some(self.description.clone())))
+            }),
+            Entry::new("Current brightness", || {
+                let current = self.current().ok();
+                Some((
+                    current.map_or_else(|| String::from('?'), |n| n.to_string()),
+                    current.map(|n| format!("{}%", f64::from(n) / f64::from(MAX) * 100.0)),
+                ))
+            }),
+            Entry::new("Max brightness", || Some((MAX.to_string(), None))),
+        ];
+        let members = self.members().map(|(device, easing)| {
+            Entry::new("Member", move || {
+                Some((
+                    device.name().unwrap_or(UNNAMED).to_string(),
+                    Some(format!("easing {easing}")),
+                ))
+            })
+        });
+
+        Box::new(entries.into_iter().chain(members))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale() {
+        let cases = [
+            // lowering keeps the ratio between members
+            ((0.8, 0.5, 0.25), 0.4),
+            ((0.2, 0.5, 0.25), 0.1),
+            // raising keeps the ratio of their distances to the maximum
+            ((0.8, 0.5, 0.75), 0.9),
+            ((0.2, 0.5, 0.75), 0.6),
+            // so everything reaches both ends
+            ((0.8, 0.5, 1.0), 1.0),
+            ((0.2, 0.5, 0.0), 0.0),
+            ((0.0, 0.0, 0.3), 0.3),
+        ];
+        for ((level, from, to), expected) in cases {
+            assert!((scale(level, from, to) - expected).abs() < 1e-9);
+        }
+    }
+}
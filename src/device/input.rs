@@ -0,0 +1,152 @@
+//! Key presses of all keyboards, read from their evdev nodes like `/dev/input/event3`. Reading
+//! them requires being in the `input` group
+
+use super::sysfs_root;
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    mem,
+    os::fd::AsRawFd,
+    path::Path,
+    time::Duration,
+};
+
+const EV_KEY: u16 = 1;
+
+/// Every keyboard found when it was opened, ones plugged in later aren't noticed
+pub struct Keyboards {
+    files: Vec<File>,
+}
+
+impl Keyboards {
+    pub fn open() -> io::Result<Self> {
+        let entries = fs::read_dir(sysfs_root().join("class/input"))?;
+        let mut last_error = None;
+        let files: Vec<_> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("event"))
+            .filter(|entry| {
+                fs::read_to_string(entry.path().join("device/capabilities/key"))
+                    .is_ok_and(|keys| is_keyboard(&keys))
+            })
+            .filter_map(|entry| {
+                let path = Path::new("/dev/input").join(entry.file_name());
+                File::open(path).map_err(|err| last_error = Some(err)).ok()
+            })
+            .collect();
+
+        match (files.is_empty(), last_error) {
+            (false, _) => Ok(Self { files }),
+            (true, Some(err)) => Err(err),
+            (true, None) => Err(io::Error::new(io::ErrorKind::NotFound, "no keyboard found")),
+        }
+    }
+
+    /// Blocks until a key is pressed, `false` if the timeout passed first. Without a timeout it
+    /// waits forever
+    pub fn wait_for_key(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        let timeout = timeout.map_or(-1, |timeout| {
+            i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX)
+        });
+        let mut fds: Vec<_> = self
+            .files
+            .iter()
+            .map(|file| libc::pollfd {
+                fd: file.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+
+        // SAFETY: `fds` is a valid array of its length
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        if ready == -1 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::Interrupted => Ok(false),
+                _ => Err(err),
+            };
+        }
+
+        let mut pressed = false;
+        let mut buffer = [0; 64 * EVENT_SIZE];
+        let mut unplugged = Vec::new();
+        for (i, fd) in fds.iter().enumerate().filter(|(_, fd)| fd.revents != 0) {
+            if fd.revents & (libc::POLLERR | libc::POLLHUP) != 0 {
+                unplugged.push(i);
+                continue;
+            }
+            match self.files[i].read(&mut buffer) {
+                Ok(read) => pressed |= key_pressed(&buffer[..read]),
+                Err(_) => unplugged.push(i),
+            }
+        }
+        for i in unplugged.into_iter().rev() {
+            self.files.remove(i);
+        }
+
+        if self.files.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "all keyboards were unplugged",
+            ));
+        }
+        Ok(pressed)
+    }
+}
+
+/// An `input_event` is a `timeval` followed by the type, the code and the value
+const EVENT_SIZE: usize = mem::size_of::<libc::timeval>() + 8;
+
+/// Whether the events contain a key being pressed or held down
+fn key_pressed(events: &[u8]) -> bool {
+    events.chunks_exact(EVENT_SIZE).any(|event| {
+        let (kind, value) = event[EVENT_SIZE - 8..].split_at(2);
+        let kind = u16::from_ne_bytes([kind[0], kind[1]]);
+        let value = i32::from_ne_bytes([value[2], value[3], value[4], value[5]]);
+        kind == EV_KEY && value != 0
+    })
+}
+
+/// Checks the key bitmap like udev does, a keyboard has all keys from `KEY_ESC` to `KEY_D`.
+/// The bitmap is written as hexadecimal words with the lowest one last
+fn is_keyboard(keys: &str) -> bool {
+    keys.split_whitespace()
+        .next_back()
+        .and_then(|word| u64::from_str_radix(word, 16).ok())
+        .is_some_and(|word| word & 0xFFFF_FFFE == 0xFFFF_FFFE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut event = vec![0; EVENT_SIZE - 8];
+        event.extend(kind.to_ne_bytes());
+        event.extend(code.to_ne_bytes());
+        event.extend(value.to_ne_bytes());
+        event
+    }
+
+    #[test]
+    fn test_key_pressed() {
+        // EV_MSC, then the release of KEY_A and a SYN_REPORT
+        let released = [event(4, 4, 30), event(EV_KEY, 30, 0), event(0, 0, 0)].concat();
+        assert!(!key_pressed(&released));
+        let pressed = [event(4, 4, 30), event(EV_KEY, 30, 1), event(0, 0, 0)].concat();
+        assert!(key_pressed(&pressed));
+        let repeated = event(EV_KEY, 30, 2);
+        assert!(key_pressed(&repeated));
+    }
+
+    #[test]
+    fn test_is_keyboard() {
+        assert!(is_keyboard(
+            "402000000 3803078f800d001 feffffdfffefffff fffffffffffffffe\n"
+        ));
+        // a power button
+        assert!(!is_keyboard("10000000000000 0\n"));
+        assert!(!is_keyboard(""));
+    }
+}
@@ -0,0 +1,124 @@
+//! Human-friendly labels for devices, read from what the firmware reports about the hardware
+
+use super::sysfs_root;
+use std::{fs, path::Path};
+
+const EDID_HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const MONITOR_NAME: u8 = 0xfc;
+const UNSPECIFIED_TEXT: u8 = 0xfe;
+
+/// The panel model of a DRM connector, like `BOE NE135FBM-N41 (eDP-1)`
+pub fn connector_label(connector: &Path) -> Option<String> {
+    let edid = fs::read(connector.join("edid")).ok()?;
+    let model = edid_model(&edid)?;
+
+    // the connector is called like `card0-eDP-1`
+    let connector = connector.canonicalize().ok()?;
+    let connector = connector.file_name()?.to_str()?;
+    match connector.split_once('-') {
+        Some((_, name)) => Some(format!("{model} ({name})")),
+        None => Some(model),
+    }
+}
+
+/// The vendor and product of the machine, like `LENOVO ThinkPad X1 Carbon 6th`
+pub fn dmi_product() -> Option<String> {
+    let dmi = sysfs_root().join("class/dmi/id");
+    let read = |file: &str| {
+        fs::read_to_string(dmi.join(file))
+            .ok()
+            .map(|content| content.trim().to_string())
+            .filter(|content| !content.is_empty())
+    };
+
+    // some vendors put the model name into the version and a part number into the name
+    let product = read("product_version")
+        .filter(|version| version.chars().any(char::is_alphabetic))
+        .or_else(|| read("product_name"))?;
+    match read("sys_vendor") {
+        Some(vendor) => Some(format!("{vendor} {product}")),
+        None => Some(product),
+    }
+}
+
+/// The manufacturer and model stored in an EDID blob
+fn edid_model(edid: &[u8]) -> Option<String> {
+    if edid.len() < 128 || edid[..8] != EDID_HEADER {
+        return None;
+    }
+
+    // three letters with five bits each, `A` is 1
+    let id = u16::from_be_bytes([edid[8], edid[9]]);
+    let manufacturer: String = [10, 5, 0]
+        .into_iter()
+        .map(|shift| char::from(b'A' - 1 + ((id >> shift) & 0x1f) as u8))
+        .collect();
+
+    let mut name = None;
+    let mut text = None;
+    for descriptor in edid[54..126].chunks_exact(18) {
+        if descriptor[..3] != [0, 0, 0] {
+            continue;
+        }
+        let content = String::from_utf8_lossy(&descriptor[5..]);
+        let content = content.split('\n').next().unwrap_or_default().trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        match descriptor[3] {
+            MONITOR_NAME => name = Some(content.to_string()),
+            // laptop panels often store the vendor and model as two of these instead
+            UNSPECIFIED_TEXT => text = Some(content.to_string()),
+            _ => {}
+        }
+    }
+
+    let model = name.or(text)?;
+    if model.starts_with(&manufacturer) {
+        Some(model)
+    } else {
+        Some(format!("{manufacturer} {model}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edid(descriptors: &[(u8, &str)]) -> Vec<u8> {
+        let mut edid = vec![0; 128];
+        edid[..8].copy_from_slice(&EDID_HEADER);
+        // BOE
+        edid[8..10].copy_from_slice(&0x09e5u16.to_be_bytes());
+        for (i, (tag, text)) in descriptors.iter().enumerate() {
+            let offset = 54 + i * 18;
+            edid[offset + 3] = *tag;
+            let mut content = text.as_bytes().to_vec();
+            content.push(b'\n');
+            content.resize(13, b' ');
+            edid[offset + 5..offset + 18].copy_from_slice(&content);
+        }
+        edid
+    }
+
+    #[test]
+    fn test_edid_model() {
+        assert_eq!(
+            edid_model(&edid(&[
+                (UNSPECIFIED_TEXT, "BOE"),
+                (UNSPECIFIED_TEXT, "NE135FBM-N41")
+            ])),
+            Some("BOE NE135FBM-N41".to_string())
+        );
+        assert_eq!(
+            edid_model(&edid(&[
+                (UNSPECIFIED_TEXT, "foo"),
+                (MONITOR_NAME, "BOE Display")
+            ])),
+            Some("BOE Display".to_string())
+        );
+        assert_eq!(edid_model(&edid(&[])), None);
+        assert_eq!(edid_model(&[0; 128]), None);
+    }
+}
@@ -1,52 +1,96 @@
 use crate::{
-    animation::easing::Easing,
-    config::Easings,
+    brightness::perceptual::PerceptualMapper,
+    config::{Easings, settings::Settings},
     device::UNNAMED,
-    meta::{Information, Meta},
+    meta::{Entries, Entry, Meta},
 };
 
 use super::{
-    BRIGHTNESS_FILES, Device, DeviceClass,
+    BRIGHTNESS_FILES, BrightnessRead, BrightnessWrite, Device, DeviceClass, DeviceProvider,
+    discovery::{DiscoveryReport, sysfs_entry},
     errors::{DeviceReadError, DeviceWriteError},
+    logind,
+    quirks::{self, Quirk, quirks_for},
 };
 use std::{
+    cell::Cell,
+    ffi::CString,
     fs::{self, OpenOptions},
     io::{self, Write},
     marker::PhantomData,
-    path::{Path, PathBuf},
+    os::unix::ffi::OsStringExt,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
 const CLASS: DeviceClass = DeviceClass::Leds;
 
 pub fn find_leds() -> Option<Vec<Led>> {
-    let lights = Path::new(CLASS.path())
-        .read_dir()
-        .ok()?
-        .filter_map(Result::ok)
-        .filter_map(|entry| Led::try_new(entry.path()).ok())
-        .collect();
-    Some(lights)
+    discover_leds().map(|report| report.devices)
+}
+
+/// Like [`find_leds`] but also reports the entries which aren't usable LEDs
+pub fn discover_leds() -> Option<DiscoveryReport<Led>> {
+    DiscoveryReport::scan(&CLASS.path(), Led::try_new)
+}
+
+/// The LEDs in sysfs
+pub struct Provider;
+
+impl DeviceProvider for Provider {
+    fn class(&self) -> DeviceClass {
+        CLASS
+    }
+
+    fn discover(&self, _settings: &Settings, _easings: &Easings) -> Vec<Box<dyn Device>> {
+        find_leds()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|led| Box::new(led) as Box<dyn Device>)
+            .collect()
+    }
+
+    fn by_name(
+        &self,
+        name: &str,
+        _settings: &Settings,
+        _easings: &Easings,
+    ) -> Option<Box<dyn Device>> {
+        let led = Led::try_new(CLASS.path().join(sysfs_entry(name)?)).ok()?;
+        Some(Box::new(led))
+    }
 }
 
 #[derive(Debug)]
 pub struct Led {
     pub dev_path: PathBuf,
-    pub max: u16,
+    pub max: u32,
+    /// Workarounds applied automatically based on the device's name and driver
+    pub quirks: Vec<Quirk>,
+    last_write: Cell<Option<Instant>>,
+    /// Whether sysfs wasn't writable and logind is used instead
+    via_logind: Cell<bool>,
     /// This (private) field makes it unconstructable outside of this module
     _hidden: PhantomData<()>,
 }
 
-impl Device for Led {
+impl BrightnessRead for Led {
     fn name(&self) -> Option<&str> {
         self.dev_path.file_name()?.to_str()
     }
 
-    fn max(&self) -> u16 {
+    fn class(&self) -> DeviceClass {
+        CLASS
+    }
+
+    fn max(&self) -> u32 {
         self.max
     }
 
-    fn current(&self) -> Result<u16, DeviceReadError> {
+    fn current(&self) -> Result<u32, DeviceReadError> {
+        self.wait_for_settle();
         let path = self.dev_path.join("brightness");
         fs::read_to_string(path)?
             .trim_end()
@@ -54,7 +98,44 @@ impl Device for Led {
             .map_err(DeviceReadError::from)
     }
 
-    fn set(&self, value: u16) -> Result<u16, DeviceWriteError<u16>> {
+    fn path(&self) -> Option<PathBuf> {
+        Some(self.dev_path.clone())
+    }
+
+    fn min_step(&self) -> u32 {
+        self.quirks
+            .iter()
+            .find_map(|quirk| match quirk {
+                Quirk::MinStep(step) => Some((*step).max(1)),
+                _ => None,
+            })
+            .unwrap_or(1)
+    }
+
+    fn label(&self) -> Option<String> {
+        let device = fs::read_to_string(self.dev_path.join("device").join("name")).ok()?;
+        let device = device.trim();
+        if device.is_empty() {
+            return None;
+        }
+
+        // LEDs are named `device:color:function`, a keyboard has several with the same device
+        match self.name()?.rsplit_once(':') {
+            Some((_, function)) if !function.is_empty() => Some(format!("{device} ({function})")),
+            _ => Some(device.to_string()),
+        }
+    }
+
+    fn trigger(&self) -> Option<String> {
+        let triggers = fs::read_to_string(self.dev_path.join("trigger")).ok()?;
+        active_trigger(&triggers)
+            .filter(|trigger| *trigger != "none")
+            .map(ToString::to_string)
+    }
+}
+
+impl BrightnessWrite for Led {
+    fn set(&self, value: u32) -> Result<u32, DeviceWriteError<u32>> {
         if value > self.max {
             return Err(DeviceWriteError::Overflow {
                 max: self.max,
@@ -62,45 +143,106 @@ impl Device for Led {
             });
         }
 
+        let value = self.round_to_step(value);
+        self.wait_for_write_delay();
+
+        if self.via_logind.get() {
+            self.set_via_logind(value)?;
+            return Ok(value);
+        }
+
         let path = self.dev_path.join("brightness");
-        let mut file = OpenOptions::new()
+        // sysfs ignores the truncation, but regular files like the fake tree in tests need it
+        let file = OpenOptions::new()
             .read(false)
             .create(false)
             .write(true)
-            .open(path)?;
+            .truncate(true)
+            .open(path);
+        let mut file = match file {
+            // the user of the active session may still write through logind
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                if self.set_via_logind(value).is_err() {
+                    return Err(err.into());
+                }
+                self.via_logind.set(true);
+                return Ok(value);
+            }
+            file => file?,
+        };
 
         write!(file, "{value}")?;
+        self.last_write.set(Some(Instant::now()));
         Ok(value)
     }
 
-    fn path(&self) -> Option<PathBuf> {
-        Some(self.dev_path.clone())
+    fn slow_writes(&self) -> bool {
+        self.via_logind.get() || !self.sysfs_writable()
     }
+
+    fn set_trigger(&self, trigger: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(self.dev_path.join("trigger"))?;
+        write!(file, "{trigger}")
+    }
+}
+
+/// The `trigger` file lists all triggers and marks the active one like `none [timer] heartbeat`
+fn active_trigger(triggers: &str) -> Option<&str> {
+    triggers
+        .split_whitespace()
+        .find_map(|trigger| trigger.strip_prefix('[')?.strip_suffix(']'))
 }
 
 impl Meta for Led {
-    fn meta(&self, easings: &Easings) -> Vec<Information> {
-        let easing = easings.get_or_default(self.name());
-
-        let cur = self.current().ok();
-        let max = self.max;
-        let actual = cur.map(|cur| f64::from(cur) / f64::from(max));
-        let user_facing = actual.map(|ac| easing.from_actual(ac));
-        let perc = user_facing.map(|x| x * 100.0);
-
-        vec![
-            Information::new(
-                "Device".to_string(),
-                self.name().unwrap_or(UNNAMED).to_string(),
-                Some(self.dev_path.display().to_string()),
-            ),
-            Information::new(
-                "Current brightness".to_string(),
-                cur.map_or_else(|| String::from('?'), |n| n.to_string()),
-                perc.map(|p| format!("{p}%")),
-            ),
-            Information::new("Max brightness".to_string(), max.to_string(), None),
-        ]
+    fn meta<'a>(&'a self, easings: &'a Easings) -> Entries<'a> {
+        self.meta_with_label(easings, || self.label())
+    }
+}
+
+impl Led {
+    /// The entries shared with backlights, which find their label differently
+    pub(super) fn meta_with_label<'a>(
+        &'a self,
+        easings: &'a Easings,
+        label: impl FnOnce() -> Option<String> + 'a,
+    ) -> Entries<'a> {
+        let entries = [
+            Entry::new("Device", || {
+                Some((
+                    self.name().unwrap_or(UNNAMED).to_string(),
+                    Some(self.dev_path.display().to_string()),
+                ))
+            }),
+            Entry::new("Label", move || Some((label()?, None))),
+            Entry::new("Current brightness", || {
+                let easing = easings.get_or_default(self.name(), Self::CLASS);
+                let cur = self.current().ok();
+                let perc = cur.map(|cur| PerceptualMapper::new(&easing, self.max).percent(cur));
+                Some((
+                    cur.map_or_else(|| String::from('?'), |n| n.to_string()),
+                    perc.map(|p| format!("{p}%")),
+                ))
+            }),
+            Entry::new("Max brightness", || Some((self.max.to_string(), None))),
+            Entry::new("Writes", || {
+                if self.sysfs_writable() {
+                    Some(("sysfs".to_string(), None))
+                } else {
+                    Some((
+                        "logind".to_string(),
+                        Some("the brightness file isn't writable".to_string()),
+                    ))
+                }
+            }),
+            Entry::new("Quirks", || {
+                let quirks: Vec<_> = self.quirks.iter().map(ToString::to_string).collect();
+                (!quirks.is_empty()).then(|| (quirks.join(", "), None))
+            }),
+        ];
+        Box::new(entries.into_iter())
     }
 }
 
@@ -120,14 +262,7 @@ impl Led {
             .count();
 
         if relevant_files_count == BRIGHTNESS_FILES.len() {
-            let content =
-                fs::read_to_string(path.join("max_brightness")).map_err(DeviceReadError::from)?;
-            let max = content.trim_end().parse().map_err(DeviceReadError::from)?;
-            Ok(Self {
-                dev_path: path,
-                max,
-                _hidden: PhantomData,
-            })
+            Ok(Self::read_max(path)?)
         } else {
             Err(NewLedError::NotEnoughFiles {
                 relevant_files_count,
@@ -135,19 +270,149 @@ impl Led {
         }
     }
 
-    /// # Safety
-    /// Missing check that all required files for a LED-Device are present
-    pub unsafe fn new_unchecked(path: PathBuf) -> Result<Self, DeviceReadError> {
+    /// Creates a device from a known path without checking which files it contains, e.g.
+    /// `Led::builder().path(path).max(255).build()`
+    pub fn builder() -> LedBuilder {
+        LedBuilder::default()
+    }
+
+    /// Creates a device from a path whose files were already checked
+    pub(super) fn read_max(path: PathBuf) -> Result<Self, DeviceReadError> {
         let content = fs::read_to_string(path.join("max_brightness"))?;
         let max = content.trim_end().parse()?;
-        Ok(Self {
-            dev_path: path,
+        Ok(Self::with_max(path, max))
+    }
+
+    fn with_max(dev_path: PathBuf, max: u32) -> Self {
+        let driver = quirks::driver(&dev_path);
+        let quirks = dev_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| quirks_for(name, driver.as_deref()))
+            .unwrap_or_default();
+
+        Self {
+            dev_path,
             max,
+            quirks,
+            last_write: Cell::new(None),
+            via_logind: Cell::new(false),
             _hidden: PhantomData,
-        })
+        }
+    }
+
+    /// Whether the brightness file can be written directly, by permissions or as root
+    fn sysfs_writable(&self) -> bool {
+        let path = self.dev_path.join("brightness");
+        let Ok(path) = CString::new(path.into_os_string().into_vec()) else {
+            return false;
+        };
+        // SAFETY: the pointer comes from `path`, a NUL-terminated `CString` which lives until
+        // `access` returned
+        unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+    }
+
+    fn set_via_logind(&self, value: u32) -> io::Result<()> {
+        // the class' directory, which is the subsystem logind expects
+        let subsystem = self
+            .dev_path
+            .parent()
+            .and_then(|class| class.file_name()?.to_str())
+            .unwrap_or(CLASS.sysfs_name());
+        let name = self.name().ok_or(io::ErrorKind::InvalidInput)?;
+
+        logind::set_brightness(subsystem, name, value)?;
+        self.last_write.set(Some(Instant::now()));
+        Ok(())
+    }
+
+    pub fn has_quirk(&self, quirk: Quirk) -> bool {
+        self.quirks.contains(&quirk)
+    }
+
+    /// Sleeps until the write delay quirk allows the next write
+    fn wait_for_write_delay(&self) {
+        let delay = self.quirks.iter().find_map(|quirk| match quirk {
+            Quirk::WriteDelay(delay) => Some(*delay),
+            _ => None,
+        });
+        self.sleep_since_write(delay);
+    }
+
+    /// Sleeps until a read returns the brightness written last, as far as the settle delay quirk
+    /// knows
+    pub(super) fn wait_for_settle(&self) {
+        let delay = self.quirks.iter().find_map(|quirk| match quirk {
+            Quirk::SettleDelay(delay) => Some(*delay),
+            _ => None,
+        });
+        self.sleep_since_write(delay);
+    }
+
+    fn sleep_since_write(&self, delay: Option<Duration>) {
+        if let Some(delay) = delay
+            && let Some(last_write) = self.last_write.get()
+        {
+            thread::sleep(delay.saturating_sub(last_write.elapsed()));
+        }
+    }
+
+    /// The nearest level the minimum step quirk lets the hardware apply, the maximum stays
+    /// reachable. Anything but 0 is at least one step, so a dim brightness doesn't turn it off
+    fn round_to_step(&self, value: u32) -> u32 {
+        let step = self.min_step();
+        if step == 1 || value == 0 || value == self.max {
+            return value;
+        }
+        let rounded = value.saturating_add(step / 2) / step * step;
+        rounded.max(step).min(self.max)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct LedBuilder {
+    path: Option<PathBuf>,
+    max: Option<u32>,
+}
+
+impl LedBuilder {
+    /// The device's directory, like `/sys/class/leds/input0::capslock`
+    pub fn path(self, path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Some(path.into()),
+            ..self
+        }
+    }
+
+    /// The maximal brightness, read from `max_brightness` if it isn't set
+    pub fn max(self, max: u32) -> Self {
+        Self {
+            max: Some(max),
+            ..self
+        }
+    }
+
+    pub fn build(self) -> Result<Led, BuildDeviceError> {
+        let path = self.path.ok_or(BuildDeviceError::MissingPath)?;
+        match self.max {
+            Some(max) => Ok(Led::with_max(path, max)),
+            None => Ok(Led::read_max(path)?),
+        }
     }
 }
 
+#[derive(Debug, Error)]
+pub enum BuildDeviceError {
+    #[error("the device's path wasn't set")]
+    MissingPath,
+    #[error("can't read maximal brightness")]
+    ReadMax(
+        #[source]
+        #[from]
+        DeviceReadError,
+    ),
+}
+
 #[derive(Debug, Error)]
 pub enum NewLedError {
     #[error("can't list content of directory")]
@@ -165,3 +430,62 @@ pub enum NewLedError {
     #[error("only {relevant_files_count} of {} required files were found", BRIGHTNESS_FILES.len())]
     NotEnoughFiles { relevant_files_count: usize },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_active_trigger() {
+        assert_eq!(
+            active_trigger("none kbd-numlock [timer] heartbeat\n"),
+            Some("timer")
+        );
+        assert_eq!(active_trigger("[none] timer"), Some("none"));
+        assert_eq!(active_trigger("none timer"), None);
+    }
+
+    #[test]
+    fn test_builder() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("input0::capslock");
+        fs::create_dir(&path).unwrap();
+
+        let led = Led::builder().path(&path).max(1).build().unwrap();
+        assert_eq!(led.max(), 1);
+        assert_eq!(led.name(), Some("input0::capslock"));
+
+        assert!(matches!(
+            Led::builder().path(&path).build(),
+            Err(BuildDeviceError::ReadMax(_))
+        ));
+        fs::write(path.join("max_brightness"), "255\n").unwrap();
+        assert_eq!(Led::builder().path(&path).build().unwrap().max(), 255);
+
+        assert!(matches!(
+            Led::builder().max(1).build(),
+            Err(BuildDeviceError::MissingPath)
+        ));
+    }
+
+    #[test]
+    fn test_min_step() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("rgb:kbd_backlight");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("brightness"), "0\n").unwrap();
+
+        let mut led = Led::builder().path(&path).max(255).build().unwrap();
+        assert_eq!(led.min_step(), 1);
+        assert_eq!(led.set(7).unwrap(), 7);
+
+        led.quirks.push(Quirk::MinStep(16));
+        assert_eq!(led.min_step(), 16);
+        assert_eq!(led.set(7).unwrap(), 16);
+        assert_eq!(led.set(0).unwrap(), 0);
+        assert_eq!(led.set(8).unwrap(), 16);
+        assert_eq!(led.current().unwrap(), 16);
+        assert_eq!(led.set(250).unwrap(), 255);
+        assert_eq!(led.set(255).unwrap(), 255);
+    }
+}
@@ -0,0 +1,57 @@
+//! Writes through systemd-logind, which lets the user of the active session change backlights and
+//! LEDs without udev rules or root
+
+use std::{
+    io,
+    process::{Command, Stdio},
+};
+
+/// Sets the brightness using `org.freedesktop.login1.Session.SetBrightness`, the subsystem is
+/// `backlight` or `leds`
+pub fn set_brightness(subsystem: &str, name: &str, value: u32) -> io::Result<()> {
+    let output = command(subsystem, name, value).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("logind refused the brightness: {}", stderr.trim()),
+    ))
+}
+
+/// `busctl` ships with systemd, so this works wherever logind does
+fn command(subsystem: &str, name: &str, value: u32) -> Command {
+    let mut command = Command::new("busctl");
+    command
+        .args([
+            "call",
+            "--system",
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1/session/auto",
+            "org.freedesktop.login1.Session",
+            "SetBrightness",
+            "ssu",
+            subsystem,
+            name,
+        ])
+        .arg(value.to_string())
+        .stdin(Stdio::null());
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        let command = command("leds", "tpacpi::kbd_backlight", 2);
+        let args: Vec<_> = command.get_args().filter_map(|arg| arg.to_str()).collect();
+        assert_eq!(
+            args[args.len() - 4..],
+            ["ssu", "leds", "tpacpi::kbd_backlight", "2"]
+        );
+    }
+}
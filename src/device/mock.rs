@@ -0,0 +1,181 @@
+//! Devices backed by a fake sysfs tree in a temporary directory, so code using [`Device`] can be
+//! tested without real hardware, e.g.
+//! `MockDevice::backlight("intel_backlight", 1000, 500)?.set(250)?`
+//!
+//! Only available with the `mock` feature
+
+use super::{
+    BrightnessRead, BrightnessWrite, Device, DeviceClass,
+    backlight::{Backlight, bl_power::BlPower, bl_type::BlType},
+    errors::{DeviceReadError, DeviceWriteError},
+    led::Led,
+};
+use crate::{
+    config::Easings,
+    meta::{Entries, Meta},
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+
+pub struct MockDevice {
+    device: Box<dyn Device>,
+    path: PathBuf,
+    /// The fake sysfs root, which is removed together with the device
+    root: TempDir,
+}
+
+impl MockDevice {
+    /// A backlight in `class/backlight/<name>` with all files a real one has
+    pub fn backlight(name: &str, max: u32, current: u32) -> io::Result<Self> {
+        Self::create(DeviceClass::Backlight, name, max, current, |path| {
+            fs::write(path.join("actual_brightness"), format!("{current}\n"))?;
+            fs::write(path.join("bl_power"), "0\n")?;
+            fs::write(path.join("type"), "raw\n")?;
+            Backlight::builder()
+                .path(path)
+                .max(max)
+                .build()
+                .map(|backlight| Box::new(backlight) as Box<dyn Device>)
+                .map_err(io::Error::other)
+        })
+    }
+
+    /// A LED in `class/leds/<name>`
+    pub fn led(name: &str, max: u32, current: u32) -> io::Result<Self> {
+        Self::create(DeviceClass::Leds, name, max, current, |path| {
+            Led::builder()
+                .path(path)
+                .max(max)
+                .build()
+                .map(|led| Box::new(led) as Box<dyn Device>)
+                .map_err(io::Error::other)
+        })
+    }
+
+    fn create(
+        class: DeviceClass,
+        name: &str,
+        max: u32,
+        current: u32,
+        build: impl FnOnce(&Path) -> io::Result<Box<dyn Device>>,
+    ) -> io::Result<Self> {
+        let root = TempDir::new()?;
+        let path = root
+            .path()
+            .join("class")
+            .join(class.sysfs_name())
+            .join(name);
+        fs::create_dir_all(&path)?;
+        fs::write(path.join("max_brightness"), format!("{max}\n"))?;
+        fs::write(path.join("brightness"), format!("{current}\n"))?;
+
+        Ok(Self {
+            device: build(&path)?,
+            path,
+            root,
+        })
+    }
+
+    /// The root of the fake tree, which can be used as `BRIGHT_SYSFS_ROOT`
+    pub fn root(&self) -> &Path {
+        self.root.path()
+    }
+
+    /// Writes a file of the device, e.g. to simulate a driver changing `actual_brightness`
+    pub fn write_file(&self, file: &str, content: &str) -> io::Result<()> {
+        fs::write(self.path.join(file), content)
+    }
+
+    /// The brightness which was last written to the device
+    pub fn written(&self) -> io::Result<u32> {
+        fs::read_to_string(self.path.join("brightness"))?
+            .trim_end()
+            .parse()
+            .map_err(io::Error::other)
+    }
+}
+
+impl BrightnessRead for MockDevice {
+    delegate::delegate! {
+        to self.device {
+            fn name(&self) -> Option<&str>;
+            fn class(&self) -> DeviceClass;
+            fn max(&self) -> u32;
+            fn current(&self) -> Result<u32, DeviceReadError>;
+            fn path(&self) -> Option<PathBuf>;
+            fn label(&self) -> Option<String>;
+            fn backlight_type(&self) -> Option<BlType>;
+            fn power(&self) -> Option<BlPower>;
+            fn display_off(&self) -> bool;
+            fn trigger(&self) -> Option<String>;
+            fn fell_back(&self) -> bool;
+            fn min_step(&self) -> u32;
+        }
+    }
+}
+
+impl BrightnessWrite for MockDevice {
+    fn set(&self, value: u32) -> Result<u32, DeviceWriteError<u32>> {
+        let value = self.device.set(value)?;
+        // the kernel updates it for real backlights
+        if self.class() == DeviceClass::Backlight {
+            self.write_file("actual_brightness", &format!("{value}\n"))?;
+        }
+        Ok(value)
+    }
+
+    fn set_trigger(&self, trigger: &str) -> io::Result<()> {
+        self.device.set_trigger(trigger)
+    }
+
+    fn set_power(&self, power: BlPower) -> io::Result<()> {
+        self.device.set_power(power)
+    }
+}
+
+impl Meta for MockDevice {
+    fn meta<'a>(&'a self, easings: &'a Easings) -> Entries<'a> {
+        self.device.meta(easings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{AnimationIter, easing::EasingKind};
+    use std::num::NonZero;
+
+    #[test]
+    fn test_animation() {
+        let device = MockDevice::backlight("intel_backlight", 120_000, 60_000).unwrap();
+        assert_eq!(device.current().unwrap(), 60_000);
+
+        let frames = AnimationIter::new(
+            (device.current().unwrap(), 0),
+            device.max(),
+            NonZero::new(4).unwrap(),
+            EasingKind::default(),
+        );
+        for (frame, _) in frames {
+            device.set(frame).unwrap();
+            assert_eq!(device.current().unwrap(), frame);
+        }
+        assert_eq!(device.written().unwrap(), 0);
+
+        assert!(matches!(
+            device.set(120_001),
+            Err(DeviceWriteError::Overflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_meta() {
+        let device = MockDevice::led("input0::capslock", 1, 1).unwrap();
+        let max = device.field(&Easings::default(), "Max brightness").unwrap();
+        assert_eq!(max.data(), "1");
+        assert!(device.root().join("class/leds/input0::capslock").is_dir());
+    }
+}
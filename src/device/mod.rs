@@ -1,87 +1,481 @@
-use crate::meta::Meta;
+use crate::{
+    config::{Easings, settings::Settings},
+    meta::Meta,
+};
+use backlight::{bl_power::BlPower, bl_type::BlType};
 use derive_more::Display;
 use errors::DeviceNotFound;
-use std::{collections::BTreeMap, env, path::PathBuf};
+use pattern::DevicePattern;
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    env, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+    time::Duration,
+};
+use thiserror::Error;
 
+#[cfg(target_os = "freebsd")]
+pub mod acpi_video;
+pub mod all;
+pub mod als;
 pub mod backlight;
+pub mod discovery;
 pub mod errors;
+pub mod floor;
+pub mod group;
+pub mod input;
+pub mod label;
 pub mod led;
+pub mod logind;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod notify;
+pub mod pattern;
+pub mod quirks;
+pub mod upower;
+pub mod watch;
 
 pub const BRIGHTNESS_FILES: [&str; 2] = ["brightness", "max_brightness"];
 
 pub const UNNAMED: &str = "unnamed";
 
-pub trait Device: Meta {
+/// Observing a device, which is all a consumer like a status bar needs
+pub trait BrightnessRead {
     fn name(&self) -> Option<&str>;
+    fn class(&self) -> DeviceClass;
 
-    fn max(&self) -> u16;
-    fn current(&self) -> Result<u16, errors::DeviceReadError>;
-    fn set(&self, value: u16) -> Result<u16, errors::DeviceWriteError<u16>>;
+    fn max(&self) -> u32;
+    fn current(&self) -> Result<u32, errors::DeviceReadError>;
     fn path(&self) -> Option<PathBuf> {
         None
     }
+    /// A human-friendly name reported by the hardware, like the panel's model
+    fn label(&self) -> Option<String> {
+        None
+    }
+    /// The type of a backlight, like `raw` for the graphics driver's
+    fn backlight_type(&self) -> Option<BlType> {
+        None
+    }
+    /// The state of `bl_power`, `None` for devices which can't be powered off
+    fn power(&self) -> Option<BlPower> {
+        None
+    }
+    /// Whether the display lit by this device is powered off, e.g. by DPMS
+    fn display_off(&self) -> bool {
+        false
+    }
+    /// The active LED trigger like `timer`, which overrides written brightnesses
+    fn trigger(&self) -> Option<String> {
+        None
+    }
+    /// The smallest change of hardware levels which is visible, see [`quirks::Quirk::MinStep`]
+    fn min_step(&self) -> u32 {
+        1
+    }
+    /// Whether the last read of [`Self::current`] used a fallback, like a backlight reading
+    /// `brightness` because `actual_brightness` was unreliable
+    fn fell_back(&self) -> bool {
+        false
+    }
+    /// Blocks until the brightness changed or the timeout passed, for devices which announce
+    /// changes in another way than sysfs
+    fn wait_for_change(&self, _timeout: Duration) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Changing a device's brightness, so handing out a `&dyn BrightnessRead` guarantees that it isn't
+/// changed
+pub trait BrightnessWrite: BrightnessRead {
+    fn set(&self, value: u32) -> Result<u32, errors::DeviceWriteError<u32>>;
+    /// Activates an LED trigger, `none` stops triggers from changing the brightness
+    fn set_trigger(&self, _trigger: &str) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+    /// Whether every write is a D-Bus call, which is too slow to animate with
+    fn slow_writes(&self) -> bool {
+        false
+    }
+    /// Powers the display on or off without changing the brightness
+    fn set_power(&self, _power: BlPower) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// A device which can be read, written and described, implemented for everything which is all
+/// three
+pub trait Device: BrightnessWrite + Meta + Send {}
+
+impl<T: BrightnessWrite + Meta + Send> Device for T {}
+
+/// A source of devices, like sysfs' backlights or UPower, see [`providers`]
+pub trait DeviceProvider {
+    /// The class of every device of this provider
+    fn class(&self) -> DeviceClass;
+    /// Every device of this provider
+    fn discover(&self, settings: &Settings, easings: &Easings) -> Vec<Box<dyn Device>>;
+    /// The device of this name, providers which can look it up directly should do so instead of
+    /// discovering all of them
+    fn by_name(
+        &self,
+        name: &str,
+        settings: &Settings,
+        easings: &Easings,
+    ) -> Option<Box<dyn Device>> {
+        self.discover(settings, easings)
+            .into_iter()
+            .find(|device| device.name() == Some(name))
+    }
 }
 
-#[derive(PartialEq, Eq, Hash, Display, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Hash, Display, PartialOrd, Ord, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DeviceClass {
     #[display("Backlight")]
     Backlight,
     #[display("Leds")]
     Leds,
+    /// Devices which don't exist in sysfs but control others
+    #[display("Virtual")]
+    Virtual,
 }
 
 impl DeviceClass {
-    pub const fn path(&self) -> &str {
+    /// The name of the class' directory in `/sys/class`
+    pub fn sysfs_name(&self) -> &'static str {
         match self {
-            Self::Backlight => "/sys/class/backlight/",
-            Self::Leds => "/sys/class/leds/",
+            Self::Backlight => "backlight",
+            Self::Leds => "leds",
+            Self::Virtual => "virtual",
         }
     }
+
+    pub fn path(&self) -> PathBuf {
+        sysfs_root().join("class").join(self.sysfs_name())
+    }
 }
 
-pub fn all_devices() -> BTreeMap<DeviceClass, Vec<Box<dyn Device>>> {
-    let mut map = BTreeMap::new();
+#[derive(Debug, Error, PartialEq)]
+#[error("unknown device class `{_0}`, expected backlight, leds or virtual")]
+pub struct UnknownClassError(String);
 
-    if let Some(backlights) = backlight::find_backlights() {
-        let mapped = backlights
-            .into_iter()
-            .map(|bl| Box::new(bl) as Box<dyn Device>)
-            .collect();
+impl FromStr for DeviceClass {
+    type Err = UnknownClassError;
 
-        map.insert(backlight::Backlight::CLASS, mapped);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "backlight" => Ok(Self::Backlight),
+            "leds" => Ok(Self::Leds),
+            "virtual" => Ok(Self::Virtual),
+            _ => Err(UnknownClassError(s.to_string())),
+        }
     }
-    if let Some(leds) = led::find_leds() {
-        let mapped = leds
-            .into_iter()
-            .map(|bl| Box::new(bl) as Box<dyn Device>)
-            .collect();
+}
 
-        map.insert(led::Led::CLASS, mapped);
-    }
+/// The root set by [`set_sysfs_root`]
+static SYSFS_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// The root of sysfs, which can be moved using `BRIGHT_SYSFS_ROOT` or [`set_sysfs_root`], for
+/// example to use a chroot or a fake tree
+pub fn sysfs_root() -> PathBuf {
+    env::var_os("BRIGHT_SYSFS_ROOT")
+        .map(PathBuf::from)
+        .or_else(|| SYSFS_ROOT.get().cloned())
+        .unwrap_or_else(|| PathBuf::from("/sys"))
+}
+
+/// Moves the root of sysfs for the rest of the process unless `BRIGHT_SYSFS_ROOT` is set, which
+/// takes precedence. It can only be set once, later calls return the rejected root
+pub fn set_sysfs_root(root: PathBuf) -> Result<(), PathBuf> {
+    SYSFS_ROOT.set(root)
+}
+
+/// The entries of all classes which couldn't be turned into devices
+pub fn skipped_entries(settings: &Settings) -> Vec<discovery::SkippedEntry> {
+    let backlights = backlight::discover_backlights(settings).map(|report| report.skipped);
+    let leds = led::discover_leds().map(|report| report.skipped);
+    backlights.into_iter().chain(leds).flatten().collect()
+}
+
+/// Backlights controlling the same panel as a preferred one, see [`backlight::shadow`]
+pub fn shadowed_backlights(settings: &Settings) -> Vec<backlight::shadow::Shadowed> {
+    backlight::find_deduped_backlights(settings).map_or_else(Vec::new, |(_, shadowed)| shadowed)
+}
+
+/// The providers of devices which exist on their own, devices within a class are listed in this
+/// order
+pub fn hardware_providers() -> Vec<Box<dyn DeviceProvider>> {
+    vec![
+        Box::new(backlight::Provider) as Box<dyn DeviceProvider>,
+        Box::new(led::Provider),
+        #[cfg(target_os = "freebsd")]
+        Box::new(acpi_video::Provider),
+    ]
+}
+
+/// Every provider, the hardware ones followed by the virtual ones which control them
+pub fn providers() -> Vec<Box<dyn DeviceProvider>> {
+    let mut providers = hardware_providers();
+    providers.extend([
+        Box::new(all::Provider) as Box<dyn DeviceProvider>,
+        Box::new(group::Provider),
+        Box::new(upower::Provider),
+    ]);
+    providers
+}
 
+/// The devices of `providers` by class, only of `class` if it's given
+fn discover(
+    providers: &[Box<dyn DeviceProvider>],
+    class: Option<DeviceClass>,
+    settings: &Settings,
+    easings: &Easings,
+) -> BTreeMap<DeviceClass, Vec<Box<dyn Device>>> {
+    let mut map: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    let providers = providers
+        .iter()
+        .filter(|provider| class.is_none_or(|class| class == provider.class()));
+    for provider in providers {
+        for device in provider.discover(settings, easings) {
+            let device = floor::with_floor(device, settings, easings);
+            map.entry(device.class()).or_default().push(device);
+        }
+    }
     map
 }
 
-pub fn get_device<S: AsRef<str>>(dev: Option<S>) -> Result<Box<dyn Device>, DeviceNotFound> {
-    let devices = all_devices();
+pub fn all_devices(
+    settings: &Settings,
+    easings: &Easings,
+) -> BTreeMap<DeviceClass, Vec<Box<dyn Device>>> {
+    devices_of(None, settings, easings)
+}
+
+/// The devices of a class, `None` for every class. The devices of other classes aren't searched
+pub fn devices_of(
+    class: Option<DeviceClass>,
+    settings: &Settings,
+    easings: &Easings,
+) -> BTreeMap<DeviceClass, Vec<Box<dyn Device>>> {
+    discover(&providers(), class, settings, easings)
+}
 
+pub fn get_device<S: AsRef<str>>(
+    dev: Option<S>,
+    settings: &Settings,
+    easings: &Easings,
+) -> Result<Box<dyn Device>, DeviceNotFound> {
     let dev = dev
         .map(|d| d.as_ref().to_string())
         .or_else(|| env::var("BRIGHT_DEVICE").ok());
 
+    // device names can't contain slashes, so this is always a path
+    if let Some(dev) = dev.as_ref().filter(|dev| dev.contains('/')) {
+        return from_path(Path::new(dev), settings)
+            .map(|device| floor::with_floor(device, settings, easings));
+    }
+
     match dev {
-        Some(dev) => {
-            let backlight = devices.into_values().flatten().find_map(|device| {
-                let name = device.name()?;
-                (name == dev).then_some(device)
-            });
-            backlight.ok_or(DeviceNotFound::NoNamed { name: dev })
+        Some(dev) if let Some(pattern) = DevicePattern::parse(&dev) => {
+            let devices = all_devices(settings, easings);
+            let mut matches = find_matching(devices, &dev, pattern)?;
+            if matches.len() > 1 {
+                return Err(DeviceNotFound::Ambiguous {
+                    name: dev,
+                    candidates: matches.iter().map(|device| qualified(&**device)).collect(),
+                });
+            }
+            Ok(matches.remove(0))
         }
+        Some(dev) => find_named(&providers(), &dev, settings, easings),
         None => {
-            let dev = devices
-                .into_values()
-                .find_map(|list| list.into_iter().next());
-            dev.ok_or(DeviceNotFound::NoFound)
+            let devices = devices_of(settings.default_class(), settings, easings);
+            default_device(devices, settings).ok_or(DeviceNotFound::NoFound)
         }
     }
 }
+
+/// The device used without `--device`: backlights before LEDs and virtual devices, then by the
+/// `backlight_preference` of their type and the most levels. Shadowed backlights are skipped
+fn default_device(
+    devices: BTreeMap<DeviceClass, Vec<Box<dyn Device>>>,
+    settings: &Settings,
+) -> Option<Box<dyn Device>> {
+    let class = settings.default_class();
+    let preference = settings.backlight_preference();
+    let shadowed: Vec<_> = if class.is_none_or(|class| class == DeviceClass::Backlight) {
+        shadowed_backlights(settings)
+    } else {
+        Vec::new()
+    };
+    let is_shadowed = |device: &dyn Device| {
+        device.class() == DeviceClass::Backlight
+            && shadowed
+                .iter()
+                .any(|shadowed| shadowed.backlight.name() == device.name())
+    };
+
+    devices
+        .into_iter()
+        .filter(|(listed, _)| class.is_none_or(|class| class == *listed))
+        .flat_map(|(_, list)| list)
+        .filter(|device| !is_shadowed(&**device))
+        .min_by_key(|device| {
+            let rank = device.backlight_type().map_or(preference.len(), |ty| {
+                preference
+                    .iter()
+                    .position(|preferred| *preferred == ty)
+                    .unwrap_or(preference.len())
+            });
+            (device.class(), rank, Reverse(device.max()))
+        })
+}
+
+/// Like [`get_device`] but returns every device a pattern like `tpacpi::*` matches
+pub fn get_devices<S: AsRef<str>>(
+    dev: Option<S>,
+    settings: &Settings,
+    easings: &Easings,
+) -> Result<Vec<Box<dyn Device>>, DeviceNotFound> {
+    let dev = dev
+        .map(|d| d.as_ref().to_string())
+        .or_else(|| env::var("BRIGHT_DEVICE").ok());
+
+    match dev.as_deref().and_then(DevicePattern::parse) {
+        Some(pattern) => find_matching(
+            all_devices(settings, easings),
+            dev.as_deref().unwrap_or_default(),
+            pattern,
+        ),
+        None => get_device(dev, settings, easings).map(|device| vec![device]),
+    }
+}
+
+fn find_matching(
+    devices: BTreeMap<DeviceClass, Vec<Box<dyn Device>>>,
+    selector: &str,
+    pattern: Result<DevicePattern, regex::Error>,
+) -> Result<Vec<Box<dyn Device>>, DeviceNotFound> {
+    let pattern = pattern.map_err(|err| DeviceNotFound::InvalidPattern {
+        pattern: selector.to_string(),
+        reason: err.to_string(),
+    })?;
+
+    // virtual devices control the others, which would then be changed twice
+    let (matches, rest): (Vec<_>, Vec<_>) = devices
+        .into_iter()
+        .filter(|(class, _)| *class != DeviceClass::Virtual)
+        .flat_map(|(_, list)| list)
+        .partition(|device| {
+            device
+                .name()
+                .is_some_and(|name| pattern.matches(name, &qualified(&**device)))
+        });
+
+    if matches.is_empty() {
+        return Err(DeviceNotFound::NoNamed {
+            name: selector.to_string(),
+            candidates: rest
+                .iter()
+                .filter_map(|device| device.name().map(ToString::to_string))
+                .collect(),
+        });
+    }
+    Ok(matches)
+}
+
+/// The name qualified with the class like `leds:foo`, which is unique. It also keys the
+/// device's files in the state directory
+pub fn qualified(device: &(impl BrightnessRead + ?Sized)) -> String {
+    qualified_name(device.class(), device.name().unwrap_or(UNNAMED))
+}
+
+pub fn qualified_name(class: DeviceClass, name: &str) -> String {
+    format!("{}:{name}", class.sysfs_name())
+}
+
+/// Uses a device directory directly, like `/sys/class/backlight/intel_backlight`
+fn from_path(path: &Path, settings: &Settings) -> Result<Box<dyn Device>, DeviceNotFound> {
+    if let Ok(bl) = backlight::Backlight::try_new(path.to_path_buf()) {
+        return Ok(Box::new(bl.configured(settings)));
+    }
+
+    led::Led::try_new(path.to_path_buf())
+        .map(|led| Box::new(led) as Box<dyn Device>)
+        .map_err(|err| DeviceNotFound::InvalidPath {
+            path: path.to_path_buf(),
+            reason: discovery::chain(&err),
+        })
+}
+
+/// Finds a device by name or label, which can be qualified with its class like `leds:foo` if
+/// several classes have a device of that name
+fn find_named(
+    providers: &[Box<dyn DeviceProvider>],
+    selector: &str,
+    settings: &Settings,
+    easings: &Easings,
+) -> Result<Box<dyn Device>, DeviceNotFound> {
+    let (class, name) = match selector.split_once(':') {
+        Some((class, name)) => match class.parse::<DeviceClass>() {
+            Ok(class) => (Some(class), name),
+            // LEDs have colons in their names
+            Err(_) => (None, selector),
+        },
+        None => (None, selector),
+    };
+    let in_class = |device: &dyn Device| class.is_none_or(|class| class == device.class());
+
+    // names are preferred over labels, which might coincidentally be the same as another name
+    let mut matches: Vec<_> = providers
+        .iter()
+        .filter_map(|provider| provider.by_name(name, settings, easings))
+        .filter(|device| in_class(&**device))
+        .map(|device| floor::with_floor(device, settings, easings))
+        .collect();
+    // a real device wins over a virtual one of the same name like `all`, which can still be
+    // qualified like `virtual:all`
+    if matches
+        .iter()
+        .any(|device| device.class() != DeviceClass::Virtual)
+    {
+        matches.retain(|device| device.class() != DeviceClass::Virtual);
+    }
+    if matches.is_empty() {
+        // labels and the names to suggest need every device
+        let devices = discover(providers, None, settings, easings);
+        let available = devices
+            .values()
+            .flatten()
+            .filter_map(|device| device.name().map(ToString::to_string))
+            .collect();
+        matches = devices
+            .into_values()
+            .flatten()
+            .filter(|device| in_class(&**device))
+            .filter(|device| {
+                device
+                    .label()
+                    .is_some_and(|label| label.eq_ignore_ascii_case(name))
+            })
+            .collect();
+        if matches.is_empty() {
+            return Err(DeviceNotFound::NoNamed {
+                name: selector.to_string(),
+                candidates: available,
+            });
+        }
+    }
+
+    match matches.len() {
+        1 => Ok(matches.remove(0)),
+        _ => Err(DeviceNotFound::Ambiguous {
+            name: selector.to_string(),
+            candidates: matches.iter().map(|device| qualified(&**device)).collect(),
+        }),
+    }
+}
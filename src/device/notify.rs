@@ -0,0 +1,31 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::fd::AsRawFd,
+    path::Path,
+    time::Duration,
+};
+
+/// Blocks until sysfs signals a change of `attribute` or the timeout passed.
+///
+/// Drivers announce changes using `sysfs_notify`, which can't be observed using inotify but only
+/// by polling the attribute for `POLLPRI`. As not every driver does so, the timeout doubles as a
+/// polling interval.
+pub fn wait_for_change(attribute: &Path, timeout: Duration) -> io::Result<()> {
+    let mut file = File::open(attribute)?;
+    // sysfs only notifies about changes after the attribute was read
+    file.read_to_end(&mut Vec::new())?;
+
+    let mut fd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLPRI | libc::POLLERR,
+        revents: 0,
+    };
+    let timeout = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+
+    // SAFETY: `fd` is a single valid pollfd and the file outlives the call
+    match unsafe { libc::poll(&raw mut fd, 1, timeout) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
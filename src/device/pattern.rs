@@ -0,0 +1,95 @@
+//! Selecting several devices at once, using a glob like `tpacpi::*` or a regex like
+//! `re:^input\d+::capslock$`
+
+use regex::Regex;
+
+const REGEX_PREFIX: &str = "re:";
+
+#[derive(Debug, Clone)]
+pub enum DevicePattern {
+    /// `*` matches any number of characters and `?` exactly one
+    Glob(String),
+    Regex(Regex),
+}
+
+impl DevicePattern {
+    /// Returns `None` if the selector is a plain name
+    pub fn parse(selector: &str) -> Option<Result<Self, regex::Error>> {
+        if let Some(regex) = selector.strip_prefix(REGEX_PREFIX) {
+            return Some(Regex::new(regex).map(Self::Regex));
+        }
+        selector
+            .contains(['*', '?'])
+            .then(|| Ok(Self::Glob(selector.to_string())))
+    }
+
+    /// Matches either the name or the name qualified with the class, like `leds:foo`
+    pub fn matches(&self, name: &str, qualified: &str) -> bool {
+        match self {
+            Self::Glob(glob) => glob_matches(glob, name) || glob_matches(glob, qualified),
+            Self::Regex(regex) => regex.is_match(name) || regex.is_match(qualified),
+        }
+    }
+}
+
+fn glob_matches(glob: &str, text: &str) -> bool {
+    let glob: Vec<_> = glob.chars().collect();
+    let text: Vec<_> = text.chars().collect();
+
+    let (mut g, mut t) = (0, 0);
+    // where to continue if the text after the last `*` doesn't match
+    let mut backtrack = None;
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                backtrack = Some((g, t));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    g = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob() {
+        assert!(glob_matches("tpacpi::*", "tpacpi::kbd_backlight"));
+        assert!(glob_matches("*::capslock", "input3::capslock"));
+        assert!(glob_matches("input?::*lock", "input3::numlock"));
+        assert!(glob_matches("*", ""));
+        assert!(!glob_matches("input?::*", "input12::capslock"));
+        assert!(!glob_matches("tpacpi::*", "intel_backlight"));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(DevicePattern::parse("intel_backlight").is_none());
+        assert!(DevicePattern::parse("re:(").unwrap().is_err());
+
+        let pattern = DevicePattern::parse(r"re:^input\d+::capslock$")
+            .unwrap()
+            .unwrap();
+        assert!(pattern.matches("input12::capslock", "leds:input12::capslock"));
+        assert!(!pattern.matches("input::capslock", "leds:input::capslock"));
+
+        let pattern = DevicePattern::parse("leds:*").unwrap().unwrap();
+        assert!(pattern.matches("tpacpi::kbd_backlight", "leds:tpacpi::kbd_backlight"));
+        assert!(!pattern.matches("intel_backlight", "backlight:intel_backlight"));
+    }
+}
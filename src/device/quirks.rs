@@ -0,0 +1,102 @@
+use derive_more::Display;
+use std::{fs, path::Path, time::Duration};
+
+/// Workarounds for devices whose drivers misbehave
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum Quirk {
+    /// The controller drops writes which follow each other too quickly
+    #[display("at least {}ms between writes", _0.as_millis())]
+    WriteDelay(Duration),
+    /// Reading right after a write still returns the previous brightness
+    #[display("settles {}ms after writes", _0.as_millis())]
+    SettleDelay(Duration),
+    /// `actual_brightness` lags behind or is never updated, so `brightness` is read instead
+    #[display("actual_brightness is stale")]
+    StaleActual,
+    /// Only multiples of this many levels change the brightness visibly
+    #[display("steps of {_0} levels")]
+    MinStep(u32),
+}
+
+/// What a quirk is matched against
+#[derive(Debug, Clone, Copy)]
+enum Key {
+    /// The device's name, optionally ending in `*` to match a prefix
+    Name(&'static str),
+    /// The kernel driver of the device's parent, like `amdgpu`
+    Driver(&'static str),
+}
+
+const QUIRKS: &[(Key, Quirk)] = &[
+    (
+        Key::Name("tpacpi::kbd_backlight"),
+        Quirk::WriteDelay(Duration::from_millis(50)),
+    ),
+    (Key::Name("amdgpu_bl*"), Quirk::StaleActual),
+    (Key::Driver("amdgpu"), Quirk::StaleActual),
+];
+
+/// The quirks which apply to a device, each at most once
+pub fn quirks_for(name: &str, driver: Option<&str>) -> Vec<Quirk> {
+    let mut quirks = Vec::new();
+    let matching = QUIRKS.iter().filter(|(key, _)| match key {
+        Key::Name(pattern) => match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == *pattern,
+        },
+        Key::Driver(pattern) => driver == Some(*pattern),
+    });
+    for (_, quirk) in matching {
+        if !quirks.contains(quirk) {
+            quirks.push(*quirk);
+        }
+    }
+    quirks
+}
+
+/// The driver bound to the device behind a sysfs class entry, e.g. `amdgpu` for `amdgpu_bl1`
+pub fn driver(dev_path: &Path) -> Option<String> {
+    let driver = fs::read_link(dev_path.join("device").join("driver")).ok()?;
+    Some(driver.file_name()?.to_str()?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix;
+
+    #[test]
+    fn test_quirks_for() {
+        assert_eq!(quirks_for("amdgpu_bl1", None), vec![Quirk::StaleActual]);
+        assert_eq!(
+            quirks_for("amdgpu_bl1", Some("amdgpu")),
+            vec![Quirk::StaleActual]
+        );
+        assert_eq!(
+            quirks_for("eDP-1", Some("amdgpu")),
+            vec![Quirk::StaleActual]
+        );
+        assert_eq!(
+            quirks_for("tpacpi::kbd_backlight", Some("thinkpad_acpi")),
+            vec![Quirk::WriteDelay(Duration::from_millis(50))]
+        );
+        assert!(quirks_for("tpacpi::kbd_backlight_2", None).is_empty());
+        assert!(quirks_for("intel_backlight", Some("i915")).is_empty());
+    }
+
+    #[test]
+    fn test_driver() {
+        let root = tempfile::tempdir().unwrap();
+        let gpu = root.path().join("devices/pci0000:00/0000:03:00.0");
+        let drivers = root.path().join("bus/pci/drivers/amdgpu");
+        fs::create_dir_all(&gpu).unwrap();
+        fs::create_dir_all(&drivers).unwrap();
+        unix::fs::symlink(&drivers, gpu.join("driver")).unwrap();
+
+        let backlight = root.path().join("class/backlight/amdgpu_bl1");
+        fs::create_dir_all(&backlight).unwrap();
+        assert_eq!(driver(&backlight), None);
+        unix::fs::symlink(&gpu, backlight.join("device")).unwrap();
+        assert_eq!(driver(&backlight).as_deref(), Some("amdgpu"));
+    }
+}
@@ -0,0 +1,224 @@
+//! The keyboard backlight of UPower's `org.freedesktop.UPower.KbdBacklight`, which works without
+//! write permissions for sysfs and announces changes made by others, like a hotkey handled by the
+//! firmware
+
+use super::{
+    BrightnessRead, BrightnessWrite, Device, DeviceClass, DeviceProvider,
+    errors::{DeviceReadError, DeviceWriteError},
+    qualified_name,
+};
+use crate::{
+    brightness::perceptual::PerceptualMapper,
+    config::{Easings, settings::Settings},
+    meta::{Entries, Entry, Meta},
+    state::{cache_max, cached_max},
+};
+use std::{
+    io,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+pub const NAME: &str = "upower-kbd";
+
+const SERVICE: &str = "org.freedesktop.UPower";
+const OBJECT: &str = "/org/freedesktop/UPower/KbdBacklight";
+const INTERFACE: &str = "org.freedesktop.UPower.KbdBacklight";
+
+pub struct UpowerKeyboard {
+    max: u32,
+}
+
+impl UpowerKeyboard {
+    pub const CLASS: DeviceClass = DeviceClass::Virtual;
+
+    /// Returns `None` if UPower isn't running or there's no keyboard backlight. The maximum is
+    /// cached, so listing devices doesn't ask UPower every time
+    pub fn find() -> Option<Self> {
+        let key = qualified_name(Self::CLASS, NAME);
+        let max = match cached_max(&key) {
+            Some(max) => max,
+            None => {
+                let max = call("GetMaxBrightness", &[]).ok()?;
+                let _ = cache_max(&key, max);
+                max
+            }
+        };
+        (max > 0).then_some(Self { max })
+    }
+}
+
+/// The keyboard backlight if the `upower` setting enables it
+pub struct Provider;
+
+impl DeviceProvider for Provider {
+    fn class(&self) -> DeviceClass {
+        UpowerKeyboard::CLASS
+    }
+
+    fn discover(&self, settings: &Settings, _easings: &Easings) -> Vec<Box<dyn Device>> {
+        if !settings.upower() {
+            return Vec::new();
+        }
+        UpowerKeyboard::find()
+            .map(|keyboard| Box::new(keyboard) as Box<dyn Device>)
+            .into_iter()
+            .collect()
+    }
+
+    fn by_name(
+        &self,
+        name: &str,
+        settings: &Settings,
+        easings: &Easings,
+    ) -> Option<Box<dyn Device>> {
+        if name != NAME {
+            return None;
+        }
+        self.discover(settings, easings).pop()
+    }
+}
+
+impl BrightnessRead for UpowerKeyboard {
+    fn name(&self) -> Option<&str> {
+        Some(NAME)
+    }
+
+    fn class(&self) -> DeviceClass {
+        Self::CLASS
+    }
+
+    fn max(&self) -> u32 {
+        self.max
+    }
+
+    fn current(&self) -> Result<u32, DeviceReadError> {
+        Ok(call("GetBrightness", &[])?)
+    }
+
+    fn label(&self) -> Option<String> {
+        Some("UPower keyboard backlight".to_string())
+    }
+
+    /// Waits for the `BrightnessChanged` signal
+    fn wait_for_change(&self, timeout: Duration) -> io::Result<()> {
+        let start = Instant::now();
+        let status = wait_command(timeout).status()?;
+        // busctl fails once the timeout passed, which isn't an error here
+        if status.success() || start.elapsed() >= timeout {
+            Ok(())
+        } else {
+            Err(io::Error::other("busctl can't wait for BrightnessChanged"))
+        }
+    }
+}
+
+impl BrightnessWrite for UpowerKeyboard {
+    fn set(&self, value: u32) -> Result<u32, DeviceWriteError<u32>> {
+        if value > self.max {
+            return Err(DeviceWriteError::Overflow {
+                max: self.max,
+                provided: value,
+            });
+        }
+
+        call("SetBrightness", &["i", &value.to_string()]).map_err(DeviceWriteError::Write)?;
+        Ok(value)
+    }
+
+    fn slow_writes(&self) -> bool {
+        true
+    }
+}
+
+impl Meta for UpowerKeyboard {
+    fn meta<'a>(&'a self, easings: &'a Easings) -> Entries<'a> {
+        let entries = [
+            Entry::new("Device", || {
+                Some((NAME.to_string(), Some(format!("{SERVICE} {OBJECT}"))))
+            }),
+            Entry::new("Label", || Some((self.label()?, None))),
+            Entry::new("Current brightness", || {
+                let easing = easings.get_or_default(self.name(), Self::CLASS);
+                let cur = self.current().ok();
+                let perc = cur.map(|cur| PerceptualMapper::new(&easing, self.max).percent(cur));
+                Some((
+                    cur.map_or_else(|| String::from('?'), |n| n.to_string()),
+                    perc.map(|p| format!("{p}%")),
+                ))
+            }),
+            Entry::new("Max brightness", || Some((self.max.to_string(), None))),
+            Entry::new("Writes", || Some(("UPower".to_string(), None))),
+        ];
+        Box::new(entries.into_iter())
+    }
+}
+
+/// Calls a method of the keyboard backlight, all of them return an `int32` or nothing
+fn call(method: &str, arguments: &[&str]) -> io::Result<u32> {
+    let output = call_command(method, arguments).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "UPower refused {method}: {}",
+            stderr.trim()
+        )));
+    }
+
+    parse_reply(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unexpected reply of UPower"))
+}
+
+fn call_command(method: &str, arguments: &[&str]) -> Command {
+    let mut command = Command::new("busctl");
+    command
+        .args(["call", "--system", SERVICE, OBJECT, INTERFACE, method])
+        .args(arguments)
+        .stdin(Stdio::null());
+    command
+}
+
+fn wait_command(timeout: Duration) -> Command {
+    let mut command = Command::new("busctl");
+    command
+        .args(["wait", "--system"])
+        .arg(format!("--timeout={}ms", timeout.as_millis()))
+        .args([SERVICE, OBJECT, INTERFACE, "BrightnessChanged"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
+
+/// busctl prints replies like `i 3`, a method without a return value prints nothing
+fn parse_reply(reply: &str) -> Option<u32> {
+    match reply.split_whitespace().collect::<Vec<_>>()[..] {
+        [] => Some(0),
+        ["i", value] => value.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(parse_reply("i 3\n"), Some(3));
+        assert_eq!(parse_reply(""), Some(0));
+        assert_eq!(parse_reply("i -1\n"), None);
+        assert_eq!(parse_reply("s \"3\"\n"), None);
+    }
+
+    #[test]
+    fn test_commands() {
+        let command = call_command("SetBrightness", &["i", "2"]);
+        let args: Vec<_> = command.get_args().filter_map(|arg| arg.to_str()).collect();
+        assert_eq!(args[args.len() - 3..], ["SetBrightness", "i", "2"]);
+
+        let command = wait_command(Duration::from_secs(1));
+        let args: Vec<_> = command.get_args().filter_map(|arg| arg.to_str()).collect();
+        assert_eq!(args[..3], ["wait", "--system", "--timeout=1000ms"]);
+    }
+}
@@ -0,0 +1,181 @@
+//! Hotplug of backlights and LEDs, like a USB keyboard being plugged in, announced by the kernel
+//! as uevents on a netlink socket. The events arrive before udev applied its rules, so a new
+//! device might only be writable through logind at first
+
+use super::DeviceClass;
+use derive_more::Display;
+use std::{
+    io,
+    os::fd::{AsRawFd, OwnedFd},
+};
+
+/// A uevent is at most a page plus its header
+const BUFFER_SIZE: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum HotplugAction {
+    #[display("Added")]
+    Added,
+    #[display("Removed")]
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HotplugEvent {
+    pub action: HotplugAction,
+    pub class: DeviceClass,
+    pub name: String,
+}
+
+/// Subscribes to the kernel's uevents, iterating over it blocks until a device is added or removed
+pub struct Watcher {
+    socket: OwnedFd,
+}
+
+impl Watcher {
+    #[cfg(target_os = "linux")]
+    pub fn new() -> io::Result<Self> {
+        use std::{mem, os::fd::FromRawFd};
+
+        // SAFETY: plain socket creation, the result is checked
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the descriptor was just created and isn't owned by anything else
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // SAFETY: an all-zero `sockaddr_nl` is valid
+        let mut address: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        // the kernel's multicast group, udev rebroadcasts on another one
+        address.nl_groups = 1;
+        // SAFETY: `address` is a valid `sockaddr_nl` of the given size
+        let bound = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                (&raw const address).cast(),
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if bound == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { socket })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new() -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn receive(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        // SAFETY: the buffer is valid for writes of its length
+        let read = unsafe {
+            libc::recv(
+                self.socket.as_raw_fd(),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+                0,
+            )
+        };
+        usize::try_from(read).map_err(|_| io::Error::last_os_error())
+    }
+}
+
+impl Iterator for Watcher {
+    type Item = io::Result<HotplugEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buffer = [0; BUFFER_SIZE];
+        loop {
+            match self.receive(&mut buffer) {
+                Ok(read) => {
+                    if let Some(event) = parse_uevent(&buffer[..read]) {
+                        return Some(Ok(event));
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Parses a uevent like `add@/devices/…\0ACTION=add\0SUBSYSTEM=leds\0…`, `None` if it's about
+/// anything else than adding or removing a backlight or LED
+fn parse_uevent(message: &[u8]) -> Option<HotplugEvent> {
+    let fields = message.split(|b| *b == 0).skip(1).filter_map(|field| {
+        let field = str::from_utf8(field).ok()?;
+        field.split_once('=')
+    });
+
+    let (mut action, mut class, mut name) = (None, None, None);
+    for (key, value) in fields {
+        match key {
+            "ACTION" => {
+                action = match value {
+                    "add" => Some(HotplugAction::Added),
+                    "remove" => Some(HotplugAction::Removed),
+                    _ => return None,
+                }
+            }
+            "SUBSYSTEM" => {
+                class = match value {
+                    "backlight" => Some(DeviceClass::Backlight),
+                    "leds" => Some(DeviceClass::Leds),
+                    _ => return None,
+                }
+            }
+            "DEVPATH" => name = value.rsplit('/').next().map(ToString::to_string),
+            _ => {}
+        }
+    }
+
+    Some(HotplugEvent {
+        action: action?,
+        class: class?,
+        name: name.filter(|name| !name.is_empty())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uevent() {
+        let added = b"add@/devices/pci0000:00/usb1/1-2/0003:046D:C33C.0001/input/input3/input3::capslock\0\
+                      ACTION=add\0DEVPATH=/devices/pci0000:00/usb1/1-2/0003:046D:C33C.0001/input/input3/input3::capslock\0\
+                      SUBSYSTEM=leds\0SEQNUM=4242\0";
+        assert_eq!(
+            parse_uevent(added),
+            Some(HotplugEvent {
+                action: HotplugAction::Added,
+                class: DeviceClass::Leds,
+                name: "input3::capslock".to_string(),
+            })
+        );
+
+        let removed = b"remove@/devices/platform/amdgpu_bl1\0ACTION=remove\0\
+                        DEVPATH=/devices/pci0000:00/0000:03:00.0/backlight/amdgpu_bl1\0SUBSYSTEM=backlight\0";
+        assert_eq!(
+            parse_uevent(removed).map(|event| (event.action, event.class)),
+            Some((HotplugAction::Removed, DeviceClass::Backlight))
+        );
+
+        let changed =
+            b"change@/devices/x/leds/a\0ACTION=change\0DEVPATH=/devices/x/leds/a\0SUBSYSTEM=leds\0";
+        assert_eq!(parse_uevent(changed), None);
+        let other =
+            b"add@/devices/x/input9\0ACTION=add\0DEVPATH=/devices/x/input9\0SUBSYSTEM=input\0";
+        assert_eq!(parse_uevent(other), None);
+    }
+}
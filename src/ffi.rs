@@ -0,0 +1,266 @@
+//! A minimal C ABI, so desktop components which aren't written in Rust, like applets in C or Vala,
+//! can use bright. The header `include/bright.h` is generated using
+//! `cbindgen --config cbindgen.toml --output include/bright.h src/ffi.rs`, the library using
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+//!
+//! Every call loads the settings and easings like the command line does, a device name of `NULL`
+//! selects the default device. Strings returned by the library are owned by it
+
+use crate::{
+    brightness::ast::Ast,
+    config::{Easings, settings::Settings},
+    device::{Device, all_devices, get_device, qualified, set_sysfs_root},
+    state::{DeviceLock, invalidate_cached_brightness},
+};
+use std::{
+    ffi::{CStr, CString, c_char},
+    ptr,
+};
+
+/// The result of every call, `BRIGHT_STATUS_OK` if it succeeded
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrightStatus {
+    Ok = 0,
+    /// A required pointer was `NULL`
+    NullPointer,
+    /// A string wasn't valid UTF-8
+    InvalidUtf8,
+    /// The settings or easings can't be read
+    Config,
+    DeviceNotFound,
+    ReadFailed,
+    WriteFailed,
+    /// The expression can't be parsed or uses a disabled function
+    InvalidExpression,
+    EvaluationFailed,
+}
+
+/// The names of all devices, see [`bright_list_devices`]
+pub struct BrightDeviceList {
+    names: Vec<CString>,
+}
+
+struct Environment {
+    settings: Settings,
+    easings: Easings,
+}
+
+impl Environment {
+    fn load() -> Result<Self, BrightStatus> {
+        let settings = Settings::from_config().map_err(|_| BrightStatus::Config)?;
+        if let Some(root) = settings.sysfs_root() {
+            // it can only be set once, which is fine as the settings don't change while running
+            let _ = set_sysfs_root(root.to_path_buf());
+        }
+        let easings = Easings::from_config()
+            .map_err(|_| BrightStatus::Config)?
+            .unwrap_or_default();
+
+        Ok(Self { settings, easings })
+    }
+
+    fn device(&self, name: Option<&str>) -> Result<Box<dyn Device>, BrightStatus> {
+        get_device(name, &self.settings, &self.easings).map_err(|_| BrightStatus::DeviceNotFound)
+    }
+}
+
+/// # Safety
+/// `ptr` must be `NULL` or point to a NUL-terminated string
+unsafe fn optional_str<'a>(ptr: *const c_char) -> Result<Option<&'a str>, BrightStatus> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    // SAFETY: the caller guarantees a NUL-terminated string
+    let s = unsafe { CStr::from_ptr(ptr) };
+    s.to_str().map(Some).map_err(|_| BrightStatus::InvalidUtf8)
+}
+
+/// Writes the value if the status is `Ok`
+///
+/// # Safety
+/// `out` must be valid for writes
+unsafe fn write_out<T>(result: Result<T, BrightStatus>, out: *mut T) -> BrightStatus {
+    match result {
+        Ok(value) => {
+            // SAFETY: the caller guarantees that it's valid and it was checked for `NULL`
+            unsafe { out.write(value) };
+            BrightStatus::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+/// Lists all devices, free the list using [`bright_device_list_free`]
+///
+/// # Safety
+/// `list` must be valid for writes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bright_list_devices(list: *mut *mut BrightDeviceList) -> BrightStatus {
+    if list.is_null() {
+        return BrightStatus::NullPointer;
+    }
+
+    let result = Environment::load().map(|env| {
+        let names = all_devices(&env.settings, &env.easings)
+            .into_values()
+            .flatten()
+            .filter_map(|device| CString::new(device.name()?).ok())
+            .collect();
+        Box::into_raw(Box::new(BrightDeviceList { names }))
+    });
+    // SAFETY: checked for `NULL` and guaranteed to be valid by the caller
+    unsafe { write_out(result, list) }
+}
+
+/// # Safety
+/// `list` must be `NULL` or returned by [`bright_list_devices`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bright_device_list_len(list: *const BrightDeviceList) -> usize {
+    // SAFETY: the caller guarantees that it's a list
+    unsafe { list.as_ref() }.map_or(0, |list| list.names.len())
+}
+
+/// The name at `index`, or `NULL` if it's out of range. It lives as long as the list
+///
+/// # Safety
+/// `list` must be `NULL` or returned by [`bright_list_devices`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bright_device_list_get(
+    list: *const BrightDeviceList,
+    index: usize,
+) -> *const c_char {
+    // SAFETY: the caller guarantees that it's a list
+    unsafe { list.as_ref() }
+        .and_then(|list| list.names.get(index))
+        .map_or(ptr::null(), |name| name.as_ptr())
+}
+
+/// # Safety
+/// `list` must be `NULL` or returned by [`bright_list_devices`] and not be used afterwards
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bright_device_list_free(list: *mut BrightDeviceList) {
+    if !list.is_null() {
+        // SAFETY: it was created by `Box::into_raw` in `bright_list_devices`
+        drop(unsafe { Box::from_raw(list) });
+    }
+}
+
+/// Reads the current and the maximal brightness of a device
+///
+/// # Safety
+/// `device` must be `NULL` or a NUL-terminated string, `current` and `max` must be valid for
+/// writes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bright_get(
+    device: *const c_char,
+    current: *mut u32,
+    max: *mut u32,
+) -> BrightStatus {
+    if current.is_null() || max.is_null() {
+        return BrightStatus::NullPointer;
+    }
+
+    // SAFETY: guaranteed by the caller
+    let result = unsafe { optional_str(device) }.and_then(|name| {
+        let device = Environment::load()?.device(name)?;
+        let value = device.current().map_err(|_| BrightStatus::ReadFailed)?;
+        Ok((value, device.max()))
+    });
+    match result {
+        Ok((value, device_max)) => {
+            // SAFETY: checked for `NULL` and guaranteed to be valid by the caller
+            unsafe {
+                current.write(value);
+                max.write(device_max);
+            }
+            BrightStatus::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+/// Sets the brightness of a device to a hardware level without animating. Like the command line
+/// it waits for other invocations changing the device and keeps to its `min` setting
+///
+/// # Safety
+/// `device` must be `NULL` or a NUL-terminated string
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bright_set(device: *const c_char, value: u32) -> BrightStatus {
+    // SAFETY: guaranteed by the caller
+    let result = unsafe { optional_str(device) }.and_then(|name| {
+        let device = Environment::load()?.device(name)?;
+        let key = qualified(&*device);
+        // a failing lock doesn't stop the command line either
+        let _lock = DeviceLock::acquire(&key).ok();
+        let result = device.set(value).map_err(|_| BrightStatus::WriteFailed);
+        let _ = invalidate_cached_brightness(&key);
+        result
+    });
+    result.err().unwrap_or(BrightStatus::Ok)
+}
+
+/// Evaluates a brightness expression like `10%+` for a device without setting it, functions
+/// disabled in the settings are rejected
+///
+/// # Safety
+/// `device` must be `NULL` or a NUL-terminated string, `expression` must be a NUL-terminated
+/// string and `value` must be valid for writes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bright_evaluate(
+    device: *const c_char,
+    expression: *const c_char,
+    value: *mut u32,
+) -> BrightStatus {
+    if expression.is_null() || value.is_null() {
+        return BrightStatus::NullPointer;
+    }
+
+    // SAFETY: guaranteed by the caller
+    let result = unsafe { optional_str(device) }.and_then(|name| {
+        // SAFETY: guaranteed by the caller and checked for `NULL`
+        let expression = unsafe { optional_str(expression) }?.unwrap_or_default();
+        let ast: Ast = expression
+            .parse()
+            .map_err(|_| BrightStatus::InvalidExpression)?;
+        let env = Environment::load()?;
+        ast.check_functions(&env.settings.function_policy())
+            .map_err(|_| BrightStatus::InvalidExpression)?;
+
+        let device = env.device(name)?;
+        let easing = env.easings.get_or_default(device.name(), device.class());
+        ast.evaluate(&*device, &easing)
+            .map_err(|_| BrightStatus::EvaluationFailed)
+    });
+    // SAFETY: checked for `NULL` and guaranteed to be valid by the caller
+    unsafe { write_out(result, value) }
+}
+
+/// A static description of a status, which must not be freed
+#[unsafe(no_mangle)]
+pub extern "C" fn bright_status_message(status: BrightStatus) -> *const c_char {
+    let message = match status {
+        BrightStatus::Ok => c"success",
+        BrightStatus::NullPointer => c"a required pointer is NULL",
+        BrightStatus::InvalidUtf8 => c"a string isn't valid UTF-8",
+        BrightStatus::Config => c"can't read the settings or easings",
+        BrightStatus::DeviceNotFound => c"device not found",
+        BrightStatus::ReadFailed => c"can't read the brightness",
+        BrightStatus::WriteFailed => c"can't write the brightness",
+        BrightStatus::InvalidExpression => c"invalid brightness expression",
+        BrightStatus::EvaluationFailed => c"can't evaluate the brightness expression",
+    };
+    message.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_header_is_current() {
+        assert!(
+            include_str!(concat!(env!("OUT_DIR"), "/bright.h"))
+                == include_str!("../include/bright.h"),
+            "include/bright.h is outdated, regenerate it using cbindgen, see the module's docs"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Commands configured with `pre_set` and `post_set` which run around every change, e.g. to
+//! notify a screen locker or a recording indicator
+
+use derive_more::Display;
+use std::{
+    io,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+use thiserror::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
+pub enum Hook {
+    /// Runs before the first frame is written
+    #[display("pre_set")]
+    PreSet,
+    /// Runs after the last frame was written
+    #[display("post_set")]
+    PostSet,
+}
+
+/// What to do if a hook fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HookFailurePolicy {
+    /// Print a warning and continue
+    #[default]
+    Warn,
+    /// Stop, a failed `pre_set` hook prevents the change
+    Abort,
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("unknown hook failure policy `{_0}`, expected warn or abort")]
+pub struct UnknownPolicyError(String);
+
+impl FromStr for HookFailurePolicy {
+    type Err = UnknownPolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "abort" => Ok(Self::Abort),
+            _ => Err(UnknownPolicyError(s.to_string())),
+        }
+    }
+}
+
+/// The change a hook is run for, passed to it using environment variables
+pub struct SetEvent<'a> {
+    pub device: &'a str,
+    pub max: u32,
+    pub old: u32,
+    pub new: u32,
+    pub animated: bool,
+}
+
+/// Runs a hook's shell command and fails if it doesn't exit successfully
+pub fn run(hook: Hook, command: &str, event: &SetEvent) -> io::Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("BRIGHT_HOOK", hook.to_string())
+        .env("BRIGHT_DEVICE", event.device)
+        .env("BRIGHT_MAX", event.max.to_string())
+        .env("BRIGHT_OLD", event.old.to_string())
+        .env("BRIGHT_NEW", event.new.to_string())
+        .env("BRIGHT_ANIMATED", if event.animated { "1" } else { "0" })
+        .stdin(Stdio::null())
+        .status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{hook} hook `{command}` exited with {status}"
+        )))
+    }
+}
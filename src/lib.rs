@@ -0,0 +1,13 @@
+pub mod animation;
+pub mod brightness;
+pub mod config;
+pub mod desired_state;
+pub mod device;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod hooks;
+pub mod meta;
+pub mod output;
+pub mod session;
+pub mod snapshot;
+pub mod state;
@@ -1,194 +1,1952 @@
-mod animation;
-mod brightness;
 mod cli;
-mod config;
-mod device;
-mod meta;
-
-use crate::{
-    animation::{AnimationIter, easing::Easing},
-    brightness::ast::functions::restoration::write_brightness,
-    cli::{Args, Command, SetArgs},
-    config::{EasingFromFileError, Easings, MultilineEasingsParseError},
-    device::{UNNAMED, all_devices, errors::DeviceWriteError, get_device},
+mod soak;
+mod tune;
+#[cfg(feature = "self-update")]
+mod update;
+
+#[cfg(feature = "self-update")]
+use crate::cli::SelfCommand;
+use crate::cli::{
+    AnimationArgs, ApplyArgs, Args, Command, ConfigCommand, EasingCommand, IdleArgs, ImportArgs,
+    PipeArgs, PowerState, RecordArgs, ReplayArgs, SetArgs, SoakArgs, StartupArgs, WriteErrorPolicy,
+};
+use bright::{
+    animation::{
+        AnimationIter,
+        easing::Easing,
+        sink::{CommandSink, FrameSink},
+    },
+    brightness::{
+        ast::{
+            Ast, ChangeDirection, EvalContext,
+            functions::{self, FunctionPolicy, restoration::write_brightness},
+        },
+        perceptual::PerceptualMapper,
+    },
+    config::{
+        EasingDevice, EasingFromFileError, Easings, MultilineEasingsParseError,
+        settings::{DeviceSettings, DisplayOffPolicy, Settings},
+        write::{write_easings, write_setting},
+    },
+    desired_state::DesiredState,
+    device::{
+        BrightnessRead, Device, DeviceClass, UNNAMED, UnknownClassError, all_devices, als,
+        backlight::bl_power::BlPower,
+        devices_of,
+        errors::{DeviceNotFound, DeviceWriteError},
+        get_device, get_devices,
+        input::Keyboards,
+        notify::wait_for_change,
+        qualified, set_sysfs_root, shadowed_backlights, skipped_entries,
+        watch::{HotplugAction, Watcher},
+    },
+    hooks::{self, Hook, HookFailurePolicy, SetEvent},
+    meta::{Information, Meta},
+    output::{DeviceReport, ErrorReport, Formatter, OutputFormat, SensorReport},
+    session::{Session, SessionEvent},
+    snapshot::{Snapshot, SnapshotEntry},
+    state::{
+        DeviceLock, cache_brightness, cached_brightness, invalidate_cached_brightness,
+        recent_target, record_target,
+    },
+};
+use clap::{CommandFactory, Parser};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    num::NonZero,
+    process,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant},
 };
-use clap::Parser;
-use std::{fmt::Write, process};
 
-const UNDERLINE_FMT: &str = "\x1B[4m";
-const DEFAULT_FMT: &str = "\x1B[0m";
+/// How often a frame is retried using `--on-write-error retry`
+const WRITE_RETRIES: u8 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+/// How often to check whether the display is back on while an animation is paused
+const DISPLAY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How many frames in a row may fail using `--on-write-error skip`
+const MAX_CONSECUTIVE_FAILURES: u8 = 5;
+/// How long `bright soak` fades if neither `--duration` nor the setting is given
+const SOAK_DURATION: Duration = Duration::from_millis(20);
+
+/// Everything the handlers share
+struct Context {
+    easings: Easings,
+    settings: Settings,
+    policy: FunctionPolicy,
+    formatter: Box<dyn Formatter>,
+    /// Whether single animation frames are printed
+    progress: bool,
+    force_trigger: Option<String>,
+    power_off_at_zero: bool,
+    /// Set from another thread to stop the running animation, like a key press in `bright idle`
+    interrupt: Arc<AtomicBool>,
+}
 
 fn main() {
-    let Args { easing, command } = Args::parse();
+    // bare numbers in durations use the `duration_unit` setting, a broken config is only reported
+    // once a command needs it
+    let settings = Settings::from_config();
+    if let Ok(settings) = &settings {
+        cli::set_duration_unit(settings.duration_unit());
+    }
+    let Args {
+        easing,
+        command,
+        format,
+        sandbox,
+        force_trigger,
+        power_off_at_zero,
+        force,
+    } = Args::parse();
+    let formatter = format.formatter();
 
-    let easings = easing
-        .map(Easings::from)
-        .or_else(|| match Easings::from_config() {
-            Ok(val) => val,
-            Err(err) => {
-                let msg = match err {
-                    EasingFromFileError::NoPath => {
-                        String::from("A path for the config file could not be determined")
-                    }
-                    EasingFromFileError::ParseError {
-                        path,
-                        error: MultilineEasingsParseError::ParseError { line_number, error },
-                    } => format!(
-                        "Can't parse easing in {}:{line_number}: {error}",
-                        path.display()
-                    ),
-                    EasingFromFileError::ParseError {
-                        path,
-                        error:
-                            MultilineEasingsParseError::DuplicateDevice {
-                                line_number,
-                                device,
-                            },
-                    } => format!(
-                        "Config file {} has duplicated device {device} at line {line_number}",
-                        path.display()
-                    ),
-                    EasingFromFileError::ReadFile(ref err) => {
-                        format!("Can't read config file: {err}")
-                    }
-                };
+    // works even if the config is broken, scripts use it to find out what they can do
+    if let Command::Capabilities { json } = command {
+        capabilities_handler(json || format == OutputFormat::Json, &*formatter);
+        return;
+    }
+    #[cfg(feature = "self-update")]
+    if let Command::SelfUpdate(command) = command {
+        if let Err(err) = self_handler(command, &*formatter) {
+            eprintln!("{}", formatter.error(&err));
+            process::exit(1);
+        }
+        return;
+    }
+
+    let config_error = |message: String| -> ! {
+        eprintln!("{}", formatter.error(&ErrorReport::new("Config", message)));
+        process::exit(1);
+    };
+
+    let configured = || match Easings::from_config() {
+        Ok(val) => val.unwrap_or_default(),
+        Err(err) => {
+            let msg = match err {
+                EasingFromFileError::NoPath => {
+                    String::from("A path for the config file could not be determined")
+                }
+                EasingFromFileError::ParseError {
+                    path,
+                    error: MultilineEasingsParseError::ParseError { line_number, error },
+                } => format!(
+                    "Can't parse easing in {}:{line_number}: {error}",
+                    path.display()
+                ),
+                EasingFromFileError::ParseError {
+                    path,
+                    error: error @ MultilineEasingsParseError::UnknownClass { .. },
+                } => format!("Can't parse {}, {error}", path.display()),
+                EasingFromFileError::ParseError {
+                    path,
+                    error:
+                        MultilineEasingsParseError::DuplicateDevice {
+                            line_number,
+                            device,
+                        },
+                } => format!(
+                    "Config file {} has duplicated device {device} at line {line_number}",
+                    path.display()
+                ),
+                EasingFromFileError::ReadFile(ref err) => {
+                    format!("Can't read config file: {err}")
+                }
+            };
 
-                eprintln!("{msg}");
-                process::exit(1);
+            config_error(msg);
+        }
+    };
+    let easings = match easing {
+        // a bare easing replaces the configured ones, assignments only override their devices
+        Some(easing) if easing.devices().all(|dev| *dev == EasingDevice::Default) => easing,
+        Some(overrides) => {
+            let mut easings = configured();
+            for (device, easing) in overrides {
+                easings.insert(device, easing);
             }
-        })
-        .unwrap_or_default();
+            easings
+        }
+        None => configured(),
+    };
+
+    let mut settings = settings.unwrap_or_else(|err| config_error(err.to_string()));
+    if force {
+        settings.ignore_min();
+    }
+    // the hooks would run for each of the thousands of changes
+    if matches!(command, Command::Soak(_)) {
+        settings.ignore_hooks();
+    }
+    if let Some(root) = settings.sysfs_root() {
+        // nothing looked at sysfs yet, so this is the first time it's set
+        let _ = set_sysfs_root(root.to_path_buf());
+    }
+
+    let mut policy = settings.function_policy();
+    policy.sandbox |= sandbox;
+
+    let ctx = Context {
+        easings,
+        settings,
+        policy,
+        // a soak test would print thousands of frames
+        progress: formatter.progress() && !matches!(command, Command::Soak(_)),
+        formatter,
+        force_trigger,
+        power_off_at_zero,
+        interrupt: Arc::default(),
+    };
+
+    if ctx.settings.warn_unknown_devices() {
+        warn_unknown_devices(&ctx);
+    }
 
     let result = match command {
-        Command::List => {
-            list_handler(easings);
+        Command::List {
+            levels,
+            sensors,
+            verbose,
+            class,
+            all_classes,
+            follow,
+        } => {
+            let class = if all_classes {
+                None
+            } else {
+                class.or(ctx.settings.default_class())
+            };
+            list_handler(levels, sensors, verbose, class, &ctx);
+            if follow {
+                follow_handler(class, &ctx)
+            } else {
+                Ok(())
+            }
+        }
+        Command::Meta {
+            device,
+            levels,
+            sensor,
+        } => meta_handler(device, levels, sensor, &ctx),
+        Command::Get { device, no_cache } => get_handler(device, no_cache, &ctx),
+        Command::Module { device, interval } => module_handler(device, *interval, &ctx),
+        Command::Eval {
+            expression,
+            device,
+            explain,
+        } => eval_handler(&expression, device, explain, &ctx),
+        Command::Power { state, device } => power_handler(state, device, &ctx),
+        Command::Set(args) => set_handler(args, &ctx),
+        Command::Pipe(args) => pipe_handler(args, &ctx),
+        Command::Startup(args) => startup_handler(args, &ctx),
+        Command::Export => {
+            export_handler(&ctx);
             Ok(())
         }
-        Command::Meta { device } => meta_handler(device, easings),
-        Command::Set(args) => set_handler(args, easings),
+        Command::Import(args) => import_handler(args, &ctx),
+        Command::Apply(args) => apply_handler(args, &ctx),
+        Command::Easing(command) => easing_handler(command, &ctx),
+        Command::Config(command) => config_handler(command, &ctx),
+        Command::Soak(args) => soak_handler(args, &ctx),
+        Command::Idle(args) => idle_handler(args, &ctx),
+        Command::Record(args) => record_handler(args, &ctx),
+        Command::Replay(args) => replay_handler(args, &ctx),
+        Command::Capabilities { .. } => unreachable!("handled before loading the config"),
+        #[cfg(feature = "self-update")]
+        Command::SelfUpdate(_) => unreachable!("handled before loading the config"),
     };
     if let Err(err) = result {
-        eprintln!("{err}");
+        eprintln!("{}", ctx.formatter.error(&err));
         std::process::exit(1)
     }
 }
 
-fn list_handler(easings: Easings) {
-    for (class, devices) in all_devices() {
-        println!("{UNDERLINE_FMT}{class}{DEFAULT_FMT}:");
-        for device in devices {
-            let cur = device.current().ok();
-            let max = device.max();
+/// Warns about devices in the config files which don't exist, as their entries never apply
+fn warn_unknown_devices(ctx: &Context) {
+    let easing_devices = ctx.easings.devices().filter_map(|dev| match dev {
+        EasingDevice::Name(name) => Some(("easings", name.as_str())),
+        EasingDevice::Default | EasingDevice::Class(_) => None,
+    });
+    let setting_devices = ctx.settings.devices().map(|name| ("settings", name));
+    let mut configured = easing_devices.chain(setting_devices).peekable();
 
-            let easing = easings.get_or_default(device.name());
+    // don't look for devices if nothing is configured
+    if configured.peek().is_none() {
+        return;
+    }
 
-            let name = device.name().unwrap_or(UNNAMED);
-            print!("\t{name}");
+    let existing: Vec<_> = all_devices(&ctx.settings, &ctx.easings)
+        .into_values()
+        .flatten()
+        .filter_map(|device| device.name().map(ToString::to_string))
+        .collect();
+    for (file, name) in configured {
+        if !existing.iter().any(|existing| existing == name) {
+            eprintln!("Warning: `{name}` in the {file} isn't a known device");
+        }
+    }
+}
 
-            if let Some(path) = device.path() {
-                print!(" {}", path.display());
-            }
+fn capabilities_handler(json: bool, formatter: &dyn Formatter) {
+    let subcommands: Vec<_> = Args::command()
+        .get_subcommands()
+        .map(|command| command.get_name().to_string())
+        .collect();
+    let backends: Vec<_> = ["sysfs", "logind", "upower"]
+        .into_iter()
+        .chain(cfg!(target_os = "freebsd").then_some("sysctl"))
+        .collect();
+    let formats = ["text", "json", "csv", "waybar"];
+    let features: Vec<_> = [
+        ("mock", cfg!(feature = "mock")),
+        ("serde", cfg!(feature = "serde")),
+        ("ffi", cfg!(feature = "ffi")),
+        ("self-update", cfg!(feature = "self-update")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect();
+    let version = env!("CARGO_PKG_VERSION");
 
-            print!(
-                " {}/{max}",
-                cur.map_or_else(|| String::from('?'), |n| n.to_string())
+    if json {
+        let capabilities = serde_json::json!({
+            "version": version,
+            "subcommands": subcommands,
+            "backends": backends,
+            "functions": functions::NAMES,
+            "formats": formats,
+            "features": features,
+        });
+        println!("{capabilities}");
+        return;
+    }
+
+    let entry =
+        |name: &str, values: &[&str]| Information::new(name.to_string(), values.join(", "), None);
+    let subcommands: Vec<_> = subcommands.iter().map(String::as_str).collect();
+    let information = [
+        Information::new("Version".to_string(), version.to_string(), None),
+        entry("Subcommands", &subcommands),
+        entry("Backends", &backends),
+        entry("Functions", &functions::NAMES),
+        entry("Formats", &formats),
+        entry("Features", &features),
+    ];
+    println!("{}", formatter.information(&information));
+}
+
+fn list_handler(
+    levels: bool,
+    sensors: bool,
+    verbose: bool,
+    class: Option<DeviceClass>,
+    ctx: &Context,
+) {
+    if sensors {
+        let reports: Vec<_> = als::find_sensors().iter().map(SensorReport::new).collect();
+        println!("{}", ctx.formatter.sensors(&reports));
+        return;
+    }
+
+    let shadowed = shadowed_backlights(&ctx.settings);
+    let is_shadowed = |device: &dyn Device| {
+        device.class() == DeviceClass::Backlight
+            && shadowed
+                .iter()
+                .any(|shadowed| shadowed.backlight.name() == device.name())
+    };
+
+    let reports: Vec<_> = devices_of(class, &ctx.settings, &ctx.easings)
+        .into_values()
+        .flatten()
+        .filter(|device| verbose || !is_shadowed(&**device))
+        .map(|device| {
+            let report = DeviceReport::new(
+                &*device,
+                &ctx.easings.get_or_default(device.name(), device.class()),
             );
+            if levels { report.with_levels() } else { report }
+        })
+        .collect();
 
-            if let Some(cur) = cur {
-                let actual = f64::from(cur) / f64::from(max);
-                let user_facing = easing.from_actual(actual);
-                let perc = user_facing * 100.0;
-                println!(" ({perc}%)");
-            } else {
-                println!();
+    println!("{}", ctx.formatter.devices(&reports));
+
+    if verbose {
+        for entry in skipped_entries(&ctx.settings) {
+            eprintln!("Skipped {}: {}", entry.path.display(), entry.reason);
+        }
+        for shadowed in &shadowed {
+            eprintln!(
+                "Shadowed {}: controls the same panel as {}",
+                shadowed.backlight.name().unwrap_or(UNNAMED),
+                shadowed.by
+            );
+        }
+    }
+}
+
+/// Prints the backlights and LEDs which are added or removed until it's interrupted
+fn follow_handler(class: Option<DeviceClass>, ctx: &Context) -> Result<(), ErrorReport> {
+    let watcher =
+        Watcher::new().map_err(|err| format!("Can't follow added and removed devices: {err}"))?;
+    for event in watcher {
+        let event = event.map_err(|err| format!("Can't receive hotplug events: {err}"))?;
+        if class.is_some_and(|class| class != event.class) {
+            continue;
+        }
+
+        let qualified = format!("{}:{}", event.class.sysfs_name(), event.name);
+        let label = match event.action {
+            HotplugAction::Added => get_device(Some(&qualified), &ctx.settings, &ctx.easings)
+                .ok()
+                .and_then(|device| device.label()),
+            HotplugAction::Removed => None,
+        };
+        let information = [Information::new(event.action.to_string(), qualified, label)];
+        println!("{}", ctx.formatter.information(&information));
+        io::stdout()
+            .flush()
+            .map_err(|err| format!("Can't write to stdout: {err}"))?;
+    }
+    Ok(())
+}
+
+fn meta_handler(
+    device_name: Option<String>,
+    levels: bool,
+    sensor: Option<String>,
+    ctx: &Context,
+) -> Result<(), ErrorReport> {
+    if let Some(sensor) = sensor {
+        let sensor = als::get_sensor(&sensor)?;
+        println!(
+            "{}",
+            ctx.formatter.information(&sensor.information(&ctx.easings))
+        );
+        return Ok(());
+    }
+
+    let device = get_device(device_name, &ctx.settings, &ctx.easings)?;
+
+    let mut information = device.information(&ctx.easings);
+    if levels {
+        information.push(Information::new(
+            "Levels".to_string(),
+            (u64::from(device.max()) + 1).to_string(),
+            Some(format!("level:0 to level:{}", device.max())),
+        ));
+    }
+    println!("{}", ctx.formatter.information(&information));
+
+    Ok(())
+}
+
+fn get_handler(
+    device_name: Option<String>,
+    no_cache: bool,
+    ctx: &Context,
+) -> Result<(), ErrorReport> {
+    let device = get_device(device_name, &ctx.settings, &ctx.easings)?;
+    let easing = ctx.easings.get_or_default(device.name(), device.class());
+    let name = device.name().unwrap_or(UNNAMED);
+    let key = qualified(&*device);
+
+    let ttl = ctx.settings.cache_ttl().filter(|_| !no_cache);
+    let cached = ttl.and_then(|ttl| cached_brightness(&key, ttl));
+    let report = match cached {
+        Some(current) => DeviceReport::with_current(&*device, &easing, Some(current)),
+        None => {
+            let report = DeviceReport::new(&*device, &easing);
+            if device.fell_back() {
+                eprintln!(
+                    "Warning: actual_brightness of {name} is unreliable, read brightness instead"
+                );
+            }
+            if ttl.is_some()
+                && let Some(current) = report.current
+                && let Err(err) = cache_brightness(&key, current)
+            {
+                eprintln!("Can't cache the brightness: {err}");
             }
+            report
+        }
+    };
+
+    println!("{}", ctx.formatter.brightness(&report));
+
+    Ok(())
+}
+
+fn module_handler(
+    device_name: Option<String>,
+    interval: Duration,
+    ctx: &Context,
+) -> Result<(), ErrorReport> {
+    let device = get_device(device_name, &ctx.settings, &ctx.easings)?;
+    let easing = ctx.easings.get_or_default(device.name(), device.class());
+
+    let attribute = device.path().and_then(|path| match device.class() {
+        DeviceClass::Backlight => Some(path.join("actual_brightness")),
+        DeviceClass::Leds => Some(path.join("brightness")),
+        DeviceClass::Virtual => None,
+    });
+
+    let mut last_line = None;
+    loop {
+        let line = ctx
+            .formatter
+            .brightness(&DeviceReport::new(&*device, &easing));
+        if last_line.as_ref() != Some(&line) {
+            println!("{line}");
+            io::stdout()
+                .flush()
+                .map_err(|err| format!("Can't write to stdout: {err}"))?;
+            last_line = Some(line);
+        }
+
+        let waited = match attribute.as_deref() {
+            Some(attribute) => wait_for_change(attribute, interval),
+            None => device.wait_for_change(interval),
+        };
+        if waited.is_err() {
+            thread::sleep(interval);
         }
     }
 }
 
-fn meta_handler(device_name: Option<String>, easings: Easings) -> Result<(), String> {
-    let device = get_device(device_name).map_err(|err| err.to_string())?;
+fn eval_handler(
+    expression: &Ast,
+    device_name: Option<String>,
+    explain: bool,
+    ctx: &Context,
+) -> Result<(), ErrorReport> {
+    let device = get_device(device_name, &ctx.settings, &ctx.easings)?;
+    let invalid = |err| {
+        ErrorReport::new(
+            "InvalidBrightness",
+            format!("Can't evaluate the brightness: {err}"),
+        )
+    };
+    expression.check_functions(&ctx.policy).map_err(invalid)?;
 
-    for info in device.meta(&easings) {
-        println!("{info}");
+    let easing = ctx.easings.get_or_default(device.name(), device.class());
+    let mut eval_ctx = EvalContext::new(&*device, &easing);
+    if explain {
+        eval_ctx = eval_ctx.traced();
     }
+    let result = expression.evaluate_in(&eval_ctx);
+
+    let mut information: Vec<_> = eval_ctx
+        .trace()
+        .into_iter()
+        .map(|step| {
+            let notes = (!step.notes.is_empty()).then(|| step.notes.join(", "));
+            Information::new(
+                format!("{}{}", "  ".repeat(step.depth), step.expression),
+                step.value
+                    .map_or_else(|| "failed".to_string(), |value| value.to_string()),
+                notes,
+            )
+        })
+        .collect();
+    let value = match result {
+        Ok(value) => value,
+        Err(err) => {
+            if !information.is_empty() {
+                println!("{}", ctx.formatter.information(&information));
+            }
+            return Err(invalid(err));
+        }
+    };
+
+    // the trace already lists the warnings next to the expressions causing them
+    if !explain {
+        information.extend(
+            eval_ctx
+                .into_warnings()
+                .into_iter()
+                .map(|warning| Information::new("Warning".to_string(), warning.to_string(), None)),
+        );
+    }
+    let percent = PerceptualMapper::new(&easing, device.max()).percent(value);
+    information.push(Information::new(
+        "Result".to_string(),
+        value.to_string(),
+        Some(format!(
+            "{percent}% of {}",
+            device.name().unwrap_or(UNNAMED)
+        )),
+    ));
+    println!("{}", ctx.formatter.information(&information));
+    Ok(())
+}
+
+/// The devices chosen by `--device`, which can be repeated, `--all` or `--class`
+fn selected_devices(args: &SetArgs, ctx: &Context) -> Result<Vec<Box<dyn Device>>, ErrorReport> {
+    if args.all || args.class.is_some() {
+        let devices: Vec<_> = devices_of(args.class, &ctx.settings, &ctx.easings)
+            .into_iter()
+            .filter(|(class, _)| args.class.is_some() || *class != DeviceClass::Virtual)
+            .flat_map(|(_, devices)| devices)
+            .collect();
+        if devices.is_empty() {
+            return Err(DeviceNotFound::NoFound.into());
+        }
+        return Ok(devices);
+    }
+    if args.device.is_empty() {
+        return Ok(get_devices(None::<&str>, &ctx.settings, &ctx.easings)?);
+    }
+
+    let mut devices: Vec<Box<dyn Device>> = Vec::new();
+    for selector in &args.device {
+        for device in get_devices(Some(selector), &ctx.settings, &ctx.easings)? {
+            // overlapping selectors mustn't change a device twice
+            let duplicate = devices.iter().any(|selected| {
+                selected.class() == device.class() && selected.name() == device.name()
+            });
+            if !duplicate {
+                devices.push(device);
+            }
+        }
+    }
+    Ok(devices)
+}
+
+fn power_handler(
+    state: PowerState,
+    device_name: Option<String>,
+    ctx: &Context,
+) -> Result<(), ErrorReport> {
+    let device = get_device(device_name, &ctx.settings, &ctx.easings)?;
+    let name = device.name().unwrap_or(UNNAMED);
+    let Some(previous) = device.power() else {
+        let message = match device.class() {
+            DeviceClass::Leds => format!("{name} is an LED, only backlights have a `bl_power`"),
+            DeviceClass::Backlight => format!("{name} has no readable `bl_power`"),
+            DeviceClass::Virtual => format!("{name} is virtual, only backlights have a `bl_power`"),
+        };
+        return Err(ErrorReport::new("PowerUnsupported", message).with("device", name));
+    };
+
+    let power = match state {
+        PowerState::On => BlPower::On,
+        PowerState::Off => BlPower::Off,
+        PowerState::Toggle => previous.toggled(),
+    };
+    device.set_power(power).map_err(|err| {
+        ErrorReport::new("WriteFailed", format!("Writing bl_power: {err}"))
+            .with("device", name)
+            .with("io_error", err.kind().to_string())
+    })?;
+
+    let information = [
+        Information::new("Device".to_string(), name.to_string(), None),
+        Information::new(
+            "Power".to_string(),
+            power.to_string(),
+            Some(format!("previously {previous}")),
+        ),
+    ];
+    println!("{}", ctx.formatter.information(&information));
+    Ok(())
+}
+
+fn set_handler(args: SetArgs, ctx: &Context) -> Result<(), ErrorReport> {
+    let devices = selected_devices(&args, ctx)?;
+
+    // several devices are animated at the same time, one failing doesn't stop the others
+    let count = devices.len();
+    let args = &args;
+    let results: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = devices
+            .into_iter()
+            .map(|device| {
+                scope.spawn(move || {
+                    apply(
+                        &*device,
+                        &args.brightness,
+                        ctx,
+                        &args.animation,
+                        args.save,
+                        args.expires.map(Into::into),
+                    )
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("Applying doesn't panic"))
+            .collect()
+    });
+
+    let mut failed = 0;
+    for result in results {
+        match result {
+            Ok(summary) => println!("{}", ctx.formatter.information(&summary)),
+            Err(err) if count == 1 => return Err(err),
+            Err(err) => {
+                eprintln!("{}", ctx.formatter.error(&err));
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{failed} of {count} devices couldn't be set").into());
+    }
+    Ok(())
+}
+
+fn pipe_handler(args: PipeArgs, ctx: &Context) -> Result<(), ErrorReport> {
+    let device = get_device(args.device.as_deref(), &ctx.settings, &ctx.easings)?;
+    // scripts and sensors may jump around, which the rate limit smooths out
+    if let Some(rate) = ctx.settings.device(device.name()).max_rate {
+        return slew(&*device, rate, &args.animation, ctx);
+    }
+
+    for line in io::stdin().lines() {
+        let line = line.map_err(|err| format!("Can't read from stdin: {err}"))?;
+        let Some(brightness) = parse_line(&line) else {
+            continue;
+        };
+
+        match apply(&*device, &brightness, ctx, &args.animation, false, None) {
+            Ok(summary) => println!("{}", ctx.formatter.information(&summary)),
+            Err(err) => eprintln!("{}", ctx.formatter.error(&err)),
+        }
+    }
+
+    Ok(())
+}
+
+/// A line of `bright pipe`, `None` for empty and invalid ones
+fn parse_line(line: &str) -> Option<Ast> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    line.parse()
+        .map_err(|err| eprintln!("Can't parse `{line}`: {err}"))
+        .ok()
+}
+
+/// Moves the device towards the latest line read from stdin by at most `rate` perceived percent
+/// per second. Lines arriving while it moves replace the target, lines in between are skipped
+fn slew(
+    device: &dyn Device,
+    rate: f64,
+    animation: &AnimationArgs,
+    ctx: &Context,
+) -> Result<(), ErrorReport> {
+    let (lines, received) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            let stop = line.is_err();
+            if lines.send(line).is_err() || stop {
+                break;
+            }
+        }
+    });
+
+    let name = device.name().unwrap_or(UNNAMED);
+    let easing = ctx.easings.get_or_default(device.name(), device.class());
+    let mapper = PerceptualMapper::new(&easing, device.max());
+    let frame = animation.frame_duration();
+    // a device written through D-Bus jumps to the target instead
+    let step = if device.slow_writes() {
+        f64::INFINITY
+    } else {
+        rate / 100.0 * frame.as_secs_f64()
+    };
+
+    let mut position = mapper.perceived(device.current().map_err(|err| {
+        ErrorReport::new("ReadFailed", format!("Reading current brightness: {err}"))
+    })?);
+    let mut target = None;
+    let mut open = true;
+    loop {
+        // waits for the next line while there's nothing to do, otherwise only for a frame
+        let next = match (open, target) {
+            (false, _) => {
+                thread::sleep(frame);
+                None
+            }
+            (true, None) => match received.recv() {
+                Ok(line) => Some(line),
+                Err(_) => {
+                    open = false;
+                    None
+                }
+            },
+            (true, Some(_)) => match received.recv_timeout(frame) {
+                Ok(line) => Some(line),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => {
+                    open = false;
+                    None
+                }
+            },
+        };
+        for line in next.into_iter().chain(received.try_iter()) {
+            let line = line.map_err(|err| format!("Can't read from stdin: {err}"))?;
+            let Some(brightness) = parse_line(&line) else {
+                continue;
+            };
+            let level = brightness
+                .check_functions(&ctx.policy)
+                .map_err(|err| err.to_string())
+                .and_then(|()| {
+                    brightness
+                        .evaluate(device, &easing)
+                        .map_err(|err| err.to_string())
+                });
+            match level {
+                Ok(level) => target = Some(mapper.perceived(level.min(device.max()))),
+                Err(err) => eprintln!(
+                    "{}",
+                    ctx.formatter.error(&ErrorReport::new(
+                        "InvalidBrightness",
+                        format!("Can't evaluate `{line}`: {err}"),
+                    ))
+                ),
+            }
+        }
+
+        let Some(goal) = target else {
+            if open {
+                continue;
+            }
+            return Ok(());
+        };
+        position += (goal - position).clamp(-step, step);
+        let applied = device.set(mapper.level(position)).map_err(|err| {
+            ErrorReport::new("WriteFailed", format!("Writing the brightness: {err}"))
+                .with("device", name)
+        })?;
+        if ctx.progress {
+            println!("Updated: {applied}");
+        }
+        if position == goal {
+            target = None;
+            let summary = [
+                Information::new("Device".to_string(), name.to_string(), None),
+                Information::new("Finished".to_string(), applied.to_string(), None),
+            ];
+            println!("{}", ctx.formatter.information(&summary));
+        }
+    }
+}
+
+fn startup_handler(args: StartupArgs, ctx: &Context) -> Result<(), ErrorReport> {
+    let device = get_device(args.device.as_deref(), &ctx.settings, &ctx.easings)?;
+    let DeviceSettings {
+        startup_brightness,
+        startup_duration,
+        ..
+    } = ctx.settings.device(device.name());
+
+    let target = startup_brightness.unwrap_or_else(|| Ast::Function {
+        name: "restore".to_string(),
+        arguments: vec![],
+    });
+    // the target is determined before turning the device off, so it stays on if that fails
+    target.check_functions(&ctx.policy).map_err(|err| {
+        ErrorReport::new(
+            "InvalidBrightness",
+            format!("Can't evaluate the brightness: {err}"),
+        )
+    })?;
+    let easing = ctx.easings.get_or_default(device.name(), device.class());
+    let target = Ast::Literal {
+        direction: ChangeDirection::Abs,
+        value: target.evaluate(&*device, &easing).map_err(|err| {
+            ErrorReport::new(
+                "InvalidBrightness",
+                format!("Can't determine the startup brightness: {err}"),
+            )
+        })?,
+        percent: false,
+    };
+    let mut animation = args.animation;
+    animation.duration = animation
+        .duration
+        .or(startup_duration)
+        .or_else(|| Some(Duration::from_secs(1).into()));
+
+    if !args.from_current {
+        device
+            .set(0)
+            .map_err(|err| format!("Can't turn the device off: {err}"))?;
+    }
+
+    let summary = apply(&*device, &target, ctx, &animation, false, None)?;
+    println!("{}", ctx.formatter.information(&summary));
 
     Ok(())
 }
 
-fn set_handler(args: SetArgs, easings: Easings) -> Result<(), String> {
-    let device = get_device(args.device.as_deref()).map_err(|err| err.to_string())?;
+fn export_handler(ctx: &Context) {
+    let entries = all_devices(&ctx.settings, &ctx.easings)
+        .into_values()
+        .flatten()
+        // virtual devices only forward to the others, which are exported themselves
+        .filter(|device| device.class() != DeviceClass::Virtual)
+        .filter_map(|device| {
+            let name = device.name()?;
+            match device.current() {
+                Ok(brightness) => Some(SnapshotEntry {
+                    device: format!("{}:{name}", device.class().sysfs_name()),
+                    brightness,
+                }),
+                Err(err) => {
+                    eprintln!("Skipping {name}, its brightness can't be read: {err}");
+                    None
+                }
+            }
+        })
+        .collect();
+
+    println!("{}", ctx.formatter.snapshot(&Snapshot { entries }));
+}
+
+fn import_handler(args: ImportArgs, ctx: &Context) -> Result<(), ErrorReport> {
+    let content = match args.file.as_deref() {
+        Some(path) if path.as_os_str() != "-" => fs::read_to_string(path)
+            .map_err(|err| format!("Can't read {}: {err}", path.display()))?,
+        _ => io::read_to_string(io::stdin())
+            .map_err(|err| format!("Can't read from stdin: {err}"))?,
+    };
+    let snapshot: Snapshot = content
+        .parse()
+        .map_err(|err| ErrorReport::new("InvalidSnapshot", format!("Can't import: {err}")))?;
+
+    // a device which is gone doesn't stop the others from being restored
+    let mut failed = 0;
+    for entry in snapshot.entries {
+        let target = Ast::Literal {
+            direction: ChangeDirection::Abs,
+            value: entry.brightness,
+            percent: false,
+        };
+        let result = get_device(Some(&entry.device), &ctx.settings, &ctx.easings)
+            .map_err(ErrorReport::from)
+            .and_then(|device| apply(&*device, &target, ctx, &args.animation, false, None));
+        match result {
+            Ok(summary) => println!("{}", ctx.formatter.information(&summary)),
+            Err(err) => {
+                eprintln!("{}", ctx.formatter.error(&err));
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{failed} devices couldn't be imported").into());
+    }
+    Ok(())
+}
+
+fn apply_handler(args: ApplyArgs, ctx: &Context) -> Result<(), ErrorReport> {
+    let content = fs::read_to_string(&args.file)
+        .map_err(|err| format!("Can't read {}: {err}", args.file.display()))?;
+    let state: DesiredState = content.parse().map_err(|err| {
+        ErrorReport::new(
+            "InvalidState",
+            format!("Can't parse {}: {err}", args.file.display()),
+        )
+    })?;
+
+    let mut failed = 0;
+    for desired in state.devices {
+        let mut animation = args.animation.clone();
+        if !animation.no_animate {
+            animation.duration = animation.duration.or(desired.duration);
+        }
+
+        // `apply` leaves devices which already have the brightness unchanged
+        let result = get_device(Some(&desired.device), &ctx.settings, &ctx.easings)
+            .map_err(ErrorReport::from)
+            .and_then(|device| apply(&*device, &desired.brightness, ctx, &animation, false, None));
+        match result {
+            Ok(summary) => println!("{}", ctx.formatter.information(&summary)),
+            Err(err) => {
+                eprintln!("{}", ctx.formatter.error(&err));
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{failed} devices couldn't be changed").into());
+    }
+    Ok(())
+}
+
+/// Animates the device to the evaluated brightness and returns a summary of what happened
+fn apply(
+    device: &dyn Device,
+    brightness: &Ast,
+    ctx: &Context,
+    animation: &AnimationArgs,
+    save: bool,
+    expires: Option<Duration>,
+) -> Result<Vec<Information>, ErrorReport> {
     let name = device.name();
-    let easing = easings.get_or_default(name);
+    let easing = ctx.easings.get_or_default(name, device.class());
+    let settings = ctx.settings.device(name);
+    let animation = &animation.or_settings(&settings).map_err(|err| {
+        ErrorReport::new("InvalidAnimation", err).with("device", name.unwrap_or(UNNAMED))
+    })?;
+    let DeviceSettings {
+        verify_writes,
+        pause_when_off,
+        when_off,
+        power_off_at_zero,
+        min,
+        cap_frames,
+        pre_set,
+        post_set,
+        hook_failure,
+        ..
+    } = settings;
     let name = name.unwrap_or(UNNAMED);
+    let key = qualified(device);
 
-    println!("Updating device: '{name}'");
+    let mut summary = vec![Information::new(
+        "Device".to_string(),
+        name.to_string(),
+        None,
+    )];
 
-    let prev_brightness = device
-        .current()
-        .map_err(|err| format!("Reading current brightness: {err}"))?;
+    // waits for other invocations changing the device to stop
+    let lock = DeviceLock::acquire(&key)
+        .map_err(|err| eprintln!("Can't lock {name}, continuing anyway: {err}"))
+        .ok();
+    let superseded = || lock.as_ref().is_some_and(DeviceLock::superseded);
+    if superseded() {
+        summary.push(Information::new(
+            "Superseded".to_string(),
+            name.to_string(),
+            Some("a newer invocation took over".to_string()),
+        ));
+        return Ok(summary);
+    }
 
-    let desired_brightness = args
-        .brightness
-        .evaluate(&*device, &easing)
-        .map_err(|err| format!("While determening the brightness encountered an error: {err}"))?;
+    let prev_brightness = device.current().map_err(|err| {
+        ErrorReport::new("ReadFailed", format!("Reading current brightness: {err}"))
+    })?;
+    summary.push(Information::new(
+        "Previously".to_string(),
+        prev_brightness.to_string(),
+        None,
+    ));
+    if device.fell_back() {
+        summary.push(Information::new(
+            "Warning".to_string(),
+            "actual_brightness is unreliable".to_string(),
+            Some("read brightness instead".to_string()),
+        ));
+    }
 
-    if args.save {
-        let path = write_brightness(name, prev_brightness).map_err(|err| err.to_string())?;
-        println!(
-            "Wrote previous brightness of {prev_brightness} to {}",
-            path.display()
-        );
+    brightness.check_functions(&ctx.policy).map_err(|err| {
+        ErrorReport::new(
+            "InvalidBrightness",
+            format!("Can't evaluate the brightness: {err}"),
+        )
+    })?;
+    let coalesce = animation.coalesce.map(|window| *window);
+    let mut eval_ctx = EvalContext::new(device, &easing);
+    if let Some(target) = coalesce.and_then(|window| recent_target(&key, window)) {
+        summary.push(Information::new(
+            "Coalesced".to_string(),
+            target.to_string(),
+            Some("based on the target of the previous invocation".to_string()),
+        ));
+        eval_ctx = eval_ctx.with_current(target);
     }
+    let mut desired_brightness = brightness.evaluate_in(&eval_ctx).map_err(|err| {
+        ErrorReport::new(
+            "InvalidBrightness",
+            format!("While determening the brightness encountered an error: {err}"),
+        )
+    })?;
+    summary.extend(
+        eval_ctx
+            .into_warnings()
+            .into_iter()
+            .map(|warning| Information::new("Warning".to_string(), warning.to_string(), None)),
+    );
 
-    if i32::from(prev_brightness) == i32::from(desired_brightness) {
-        println!("Already at the desired brightness of {desired_brightness}");
-        return Ok(());
+    if desired_brightness > device.max() {
+        summary.push(Information::new(
+            "Clamped".to_string(),
+            device.max().to_string(),
+            Some(format!("{desired_brightness} exceeds the maximum")),
+        ));
+        desired_brightness = device.max();
+    }
+
+    // the device enforces the floor as well, this only reports it
+    if let Some(min) = min {
+        let min = min.evaluate(device, &easing).map_err(|err| {
+            ErrorReport::new(
+                "InvalidBrightness",
+                format!("Can't evaluate the `min` setting: {err}"),
+            )
+        })?;
+        let floor = min.min(prev_brightness);
+        if desired_brightness < floor {
+            summary.push(Information::new(
+                "Clamped".to_string(),
+                floor.to_string(),
+                Some(format!(
+                    "{desired_brightness} is below the `min` setting, use --force to go lower"
+                )),
+            ));
+            desired_brightness = floor;
+        }
+    }
+
+    if coalesce.is_some()
+        && let Err(err) = record_target(&key, desired_brightness)
+    {
+        eprintln!("Can't record the target for coalescing: {err}");
+    }
+
+    let power_off_at_zero =
+        (ctx.power_off_at_zero || power_off_at_zero.unwrap_or(false)) && device.power().is_some();
+    let set_power = |power| {
+        device.set_power(power).map_err(|err| {
+            ErrorReport::new("WriteFailed", format!("Writing bl_power: {err}"))
+                .with("device", name)
+                .with("io_error", err.kind().to_string())
+        })
+    };
+    // before the first frame, so the fade is visible and isn't paused for the display being off
+    if power_off_at_zero && desired_brightness > 0 && device.power() == Some(BlPower::Off) {
+        set_power(BlPower::On)?;
+        summary.push(Information::new(
+            "Power".to_string(),
+            BlPower::On.to_string(),
+            Some("the brightness is above 0".to_string()),
+        ));
+    }
+
+    // checked once before anything changes, a display turning off later pauses the animation
+    let starts_off = prev_brightness != desired_brightness
+        && animation.frame_count().get() > 1
+        && device.display_off();
+    let instant = match when_off.unwrap_or_default() {
+        _ if !starts_off => false,
+        DisplayOffPolicy::Pause => false,
+        DisplayOffPolicy::Instant => {
+            summary.push(Information::new(
+                "Instant".to_string(),
+                desired_brightness.to_string(),
+                Some("the display is off".to_string()),
+            ));
+            true
+        }
+        DisplayOffPolicy::Refuse => {
+            return Err(ErrorReport::new(
+                "DisplayOff",
+                format!("Not changing {name}, its display is off"),
+            )
+            .with("device", name)
+            .with("value", desired_brightness));
+        }
     };
 
-    println!("Previously: {prev_brightness}");
+    if save {
+        let path = write_brightness(device, prev_brightness, &easing, expires)
+            .map_err(|err| err.to_string())?;
+        let details = match expires {
+            Some(expires) => format!(
+                "{}, expires in {}",
+                path.display(),
+                humantime::format_duration(expires)
+            ),
+            None => path.display().to_string(),
+        };
+        summary.push(Information::new(
+            "Saved".to_string(),
+            prev_brightness.to_string(),
+            Some(details),
+        ));
+    }
+
+    let mut sinks: Vec<Box<dyn FrameSink>> = Vec::new();
+    if let Some(command) = animation.frame_cmd.clone() {
+        sinks.push(Box::new(CommandSink::new(command, name, device.max())));
+    }
 
     let mut last_applied = None;
-    let animation_values = AnimationIter::new(
-        (prev_brightness, desired_brightness),
-        device.max(),
-        args.frame_count(),
-        easing,
-    );
-    for (brightness, is_last) in animation_values {
-        match device.set(brightness) {
-            Ok(new) => {
-                last_applied = Some(new);
-                println!("Updated: {new}");
-            }
-            Err(DeviceWriteError::Write(err)) => {
-                let kind = err.kind();
-
-                let mut buffer = format!("Error: {kind}");
-                if let Some(os_error) = err.raw_os_error() {
-                    write!(buffer, "\nOS-Error: {os_error}")
-                        .expect("Writing into String is infallible");
-                }
+    if prev_brightness == desired_brightness {
+        summary.push(Information::new(
+            "Unchanged".to_string(),
+            desired_brightness.to_string(),
+            Some("already at the desired brightness".to_string()),
+        ));
+    } else {
+        let (frames, frame_duration) = if instant {
+            (NonZero::<usize>::MIN, Duration::ZERO)
+        } else if animation.frame_count().get() > 1 && device.slow_writes() {
+            summary.push(Information::new(
+                "Instant".to_string(),
+                desired_brightness.to_string(),
+                Some("every write is a D-Bus call, which is too slow to animate".to_string()),
+            ));
+            (NonZero::<usize>::MIN, Duration::ZERO)
+        } else if cap_frames.unwrap_or(true) {
+            animation
+                .capped_frames(prev_brightness.abs_diff(desired_brightness) / device.min_step())
+        } else {
+            (animation.frame_count(), animation.frame_duration())
+        };
+        if frames.get() > 1 {
+            let details = (frames < animation.frame_count()).then(|| {
+                format!(
+                    "capped from {} to the levels in between, {}ms each",
+                    animation.frame_count(),
+                    frame_duration.as_millis()
+                )
+            });
+            summary.push(Information::new(
+                "Frames".to_string(),
+                frames.to_string(),
+                details,
+            ));
+        }
 
-                if kind == std::io::ErrorKind::PermissionDenied {
-                    buffer.push_str("\nTipp: Set an udev rule or run with elevated priviliges");
-                    return Err(buffer);
+        let run_hook = |hook, command: &Option<String>, new| {
+            let Some(command) = command else {
+                return Ok(());
+            };
+            let event = SetEvent {
+                device: name,
+                max: device.max(),
+                old: prev_brightness,
+                new,
+                animated: frames.get() > 1,
+            };
+            match hooks::run(hook, command, &event) {
+                Err(err) if hook_failure.unwrap_or_default() == HookFailurePolicy::Abort => {
+                    Err(err.to_string())
                 }
-                eprintln!("{buffer}");
+                Err(err) => {
+                    eprintln!("Warning: {err}");
+                    Ok(())
+                }
+                Ok(()) => Ok(()),
             }
-            Err(DeviceWriteError::Overflow { max, provided }) => {
-                return Err(format!(
-                    "Tried setting the brightness to {provided} eventhough only {max} is supported"
+        };
+        run_hook(Hook::PreSet, &pre_set, desired_brightness)?;
+
+        let _trigger = match (device.trigger(), &ctx.force_trigger) {
+            (Some(active), Some(forced)) => {
+                summary.push(Information::new(
+                    "Trigger".to_string(),
+                    forced.clone(),
+                    Some(format!("replaces `{active}` while setting the brightness")),
                 ));
+                Some(TriggerGuard::replace(device, forced, active)?)
             }
-        }
+            (Some(active), None) => {
+                summary.push(Information::new(
+                    "Warning".to_string(),
+                    format!("the `{active}` trigger overrides the brightness"),
+                    Some("use `--force-trigger none` to stop it".to_string()),
+                ));
+                None
+            }
+            (None, _) => None,
+        };
+
+        let cache = CacheGuard { key: &key };
+        let mut animation_values = AnimationIter::new(
+            (prev_brightness, desired_brightness),
+            device.max(),
+            frames,
+            easing,
+        );
+        let mut failures = 0;
+        loop {
+            if superseded() {
+                summary.push(Information::new(
+                    "Interrupted".to_string(),
+                    last_applied.unwrap_or(prev_brightness).to_string(),
+                    Some("a newer invocation took over".to_string()),
+                ));
+                break;
+            }
+            if ctx.interrupt.load(Ordering::Relaxed) {
+                summary.push(Information::new(
+                    "Interrupted".to_string(),
+                    last_applied.unwrap_or(prev_brightness).to_string(),
+                    None,
+                ));
+                break;
+            }
+            // single writes still happen, so the brightness is right once the display is back on
+            if pause_when_off.unwrap_or(true) && frames.get() > 1 && device.display_off() {
+                summary.push(Information::new(
+                    "Paused".to_string(),
+                    last_applied.unwrap_or(prev_brightness).to_string(),
+                    Some("the display is off".to_string()),
+                ));
+                while device.display_off() && !superseded() {
+                    thread::sleep(DISPLAY_POLL_INTERVAL);
+                }
+                continue;
+            }
+            if animation.follow_actual
+                && let Some(last) = last_applied
+                && let Ok(actual) = device.current()
+                && actual != last
+            {
+                animation_values.resync(actual);
+            }
+            let Some((brightness, is_last)) = animation_values.next() else {
+                break;
+            };
+
+            let mut attempts = 0;
+            let result = loop {
+                match device.set(brightness) {
+                    Err(DeviceWriteError::Write(err))
+                        if animation.on_write_error == WriteErrorPolicy::Retry
+                            && attempts < WRITE_RETRIES
+                            && err.kind() != io::ErrorKind::PermissionDenied =>
+                    {
+                        attempts += 1;
+                        thread::sleep(RETRY_DELAY);
+                    }
+                    result => break result,
+                }
+            };
+
+            match result {
+                Ok(new) => {
+                    failures = 0;
+                    let new = if verify_writes.unwrap_or(false) {
+                        device.current().unwrap_or_else(|err| {
+                            eprintln!("Can't verify the written brightness: {err}");
+                            new
+                        })
+                    } else {
+                        new
+                    };
+
+                    // frames that don't change anything aren't worth a line
+                    if last_applied.replace(new) != Some(new) && ctx.progress {
+                        println!("Updated: {new}");
+                    }
+
+                    for sink in &mut sinks {
+                        if let Err(err) = sink.frame(new, is_last) {
+                            eprintln!("Frame hook failed: {err}");
+                        }
+                    }
+                }
+                Err(DeviceWriteError::Write(err)) => {
+                    let kind = err.kind();
+
+                    let mut buffer = format!("Error: {kind}");
+                    if let Some(os_error) = err.raw_os_error() {
+                        write!(buffer, "\nOS-Error: {os_error}")
+                            .expect("Writing into String is infallible");
+                    }
 
-        if !is_last {
-            std::thread::sleep(args.frame_duration());
+                    let write_failed = |message| {
+                        let report = ErrorReport::new("WriteFailed", message)
+                            .with("device", name)
+                            .with("value", brightness)
+                            .with("io_error", kind.to_string());
+                        match err.raw_os_error() {
+                            Some(os_error) => report.with("os_error", os_error),
+                            None => report,
+                        }
+                    };
+
+                    if kind == std::io::ErrorKind::PermissionDenied {
+                        buffer.push_str(
+                            "\nTipp: Set an udev rule, run with elevated priviliges or from the active session so logind can write for you",
+                        );
+                        return Err(ErrorReport {
+                            kind: "PermissionDenied",
+                            ..write_failed(buffer)
+                        });
+                    }
+
+                    failures += 1;
+                    match animation.on_write_error {
+                        WriteErrorPolicy::Skip if failures < MAX_CONSECUTIVE_FAILURES => {
+                            eprintln!("{buffer}");
+                        }
+                        WriteErrorPolicy::Skip => {
+                            return Err(write_failed(format!(
+                                "{buffer}\nGiving up after {failures} failed frames in a row"
+                            )));
+                        }
+                        WriteErrorPolicy::Retry => {
+                            return Err(write_failed(format!(
+                                "{buffer}\nGiving up after {attempts} retries"
+                            )));
+                        }
+                        WriteErrorPolicy::Abort => return Err(write_failed(buffer)),
+                    }
+                }
+                Err(DeviceWriteError::DeviceGone(_)) => {
+                    return Err(ErrorReport::new(
+                        "DeviceGone",
+                        format!("{name} disappeared, stopping the animation"),
+                    )
+                    .with("device", name));
+                }
+                Err(DeviceWriteError::Overflow { max, provided }) => {
+                    return Err(ErrorReport::new(
+                        "Overflow",
+                        format!(
+                            "Tried setting the brightness to {provided} eventhough only {max} is supported"
+                        ),
+                    )
+                    .with("max", max)
+                    .with("provided", provided));
+                }
+            }
+
+            if !is_last {
+                thread::sleep(frame_duration);
+            }
         }
+
+        // the hook may read the brightness itself
+        drop(cache);
+        let applied = last_applied.unwrap_or(prev_brightness);
+        run_hook(Hook::PostSet, &post_set, applied)
+            .map_err(|err| ErrorReport::from(err).with("finished", applied))?;
     }
 
     let actual_brightness = last_applied.unwrap_or(prev_brightness);
-    println!("Finished: {actual_brightness}");
+    // only once the last frame is written, an interrupted fade doesn't reach 0
+    if power_off_at_zero && actual_brightness == 0 && device.power() == Some(BlPower::On) {
+        set_power(BlPower::Off).map_err(|err| err.with("finished", actual_brightness))?;
+        summary.push(Information::new(
+            "Power".to_string(),
+            BlPower::Off.to_string(),
+            Some("the brightness is 0".to_string()),
+        ));
+    }
+    summary.push(Information::new(
+        "Finished".to_string(),
+        actual_brightness.to_string(),
+        None,
+    ));
+
+    Ok(summary)
+}
+
+/// Invalidates the cached brightness once frames may have been written, even if writing failed
+struct CacheGuard<'a> {
+    key: &'a str,
+}
+
+impl Drop for CacheGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = invalidate_cached_brightness(self.key) {
+            eprintln!("Can't invalidate the cached brightness: {err}");
+        }
+    }
+}
+
+/// Activates the previous LED trigger again once the brightness is set, even if it failed
+struct TriggerGuard<'a> {
+    device: &'a dyn Device,
+    previous: String,
+}
+
+impl<'a> TriggerGuard<'a> {
+    fn replace(device: &'a dyn Device, trigger: &str, previous: String) -> Result<Self, String> {
+        device
+            .set_trigger(trigger)
+            .map_err(|err| format!("Can't set the trigger to `{trigger}`: {err}"))?;
+        Ok(Self { device, previous })
+    }
+}
+
+impl Drop for TriggerGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.device.set_trigger(&self.previous) {
+            eprintln!(
+                "Can't activate the `{}` trigger again: {err}",
+                self.previous
+            );
+        }
+    }
+}
+
+/// Sets the brightness the device had before `bright soak` again, even if a cycle failed
+struct RestoreGuard<'a> {
+    device: &'a dyn Device,
+    brightness: u32,
+}
+
+impl Drop for RestoreGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.device.set(self.brightness) {
+            eprintln!("Can't restore the brightness {}: {err}", self.brightness);
+        }
+        if let Err(err) = invalidate_cached_brightness(&qualified(self.device)) {
+            eprintln!("Can't invalidate the cached brightness: {err}");
+        }
+    }
+}
+
+fn soak_handler(args: SoakArgs, ctx: &Context) -> Result<(), ErrorReport> {
+    let device = get_device(args.device, &ctx.settings, &ctx.easings)?;
+    let original = device.current().map_err(|err| {
+        ErrorReport::new("ReadFailed", format!("Reading current brightness: {err}"))
+    })?;
+    let step = device.max().div_ceil(4);
+    let other = if original >= step {
+        original - step
+    } else {
+        (original + step).min(device.max())
+    };
+    let level = |value| Ast::Literal {
+        direction: ChangeDirection::Abs,
+        value,
+        percent: false,
+    };
+
+    let settings = ctx.settings.device(device.name());
+    let animation = AnimationArgs {
+        duration: args
+            .animation
+            .duration
+            .or(settings.duration)
+            .or(Some(SOAK_DURATION.into())),
+        ..args.animation
+    };
+    let _restore = RestoreGuard {
+        device: &*device,
+        brightness: original,
+    };
+    let cycle = || -> Result<(), ErrorReport> {
+        apply(&*device, &level(other), ctx, &animation, false, None)?;
+        apply(&*device, &level(original), ctx, &animation, false, None)?;
+        Ok(())
+    };
+    let measure = || {
+        soak::Usage::measure()
+            .map_err(|err| format!("Can't measure the resources of the process: {err}"))
+    };
+
+    // the first cycle initializes what is kept for the whole process
+    cycle()?;
+    let baseline = measure()?;
+    for _ in 1..args.cycles {
+        cycle()?;
+    }
+    let usage = measure()?;
+
+    let information = [
+        Information::new(
+            "Device".to_string(),
+            device.name().unwrap_or(UNNAMED).to_string(),
+            None,
+        ),
+        Information::new("Cycles".to_string(), args.cycles.to_string(), None),
+        Information::new(
+            "Open files".to_string(),
+            usage.open_fds.to_string(),
+            Some(format!("{} after the first cycle", baseline.open_fds)),
+        ),
+        Information::new(
+            "Resident memory".to_string(),
+            format!("{} KiB", usage.resident),
+            Some(format!("{} KiB after the first cycle", baseline.resident)),
+        ),
+    ];
+    println!("{}", ctx.formatter.information(&information));
+
+    let leaks = usage.leaks(&baseline, args.max_fd_growth, args.max_memory_growth);
+    if leaks.is_empty() {
+        Ok(())
+    } else {
+        Err(ErrorReport::new(
+            "Leak",
+            format!("Leaking: {}", leaks.join(", ")),
+        ))
+    }
+}
+
+fn idle_handler(args: IdleArgs, ctx: &Context) -> Result<(), ErrorReport> {
+    let device = match args.device.or_else(|| env::var("BRIGHT_DEVICE").ok()) {
+        Some(name) => get_device(Some(name), &ctx.settings, &ctx.easings)?,
+        None => devices_of(Some(DeviceClass::Leds), &ctx.settings, &ctx.easings)
+            .remove(&DeviceClass::Leds)
+            .into_iter()
+            .flatten()
+            .find(|device| {
+                device
+                    .name()
+                    .is_some_and(|name| name.ends_with("kbd_backlight"))
+            })
+            .ok_or("No keyboard backlight found, choose one using --device")?,
+    };
+    let mut keyboards =
+        Keyboards::open().map_err(|err| format!("Can't read the keyboards: {err}"))?;
+
+    // the keyboards are read on their own thread, so a key press can stop the fade-out
+    let armed = Arc::new(Mutex::new(false));
+    let (keys, pressed) = mpsc::channel();
+    {
+        let armed = Arc::clone(&armed);
+        let interrupt = Arc::clone(&ctx.interrupt);
+        thread::spawn(move || {
+            loop {
+                let key = match keyboards.wait_for_key(None) {
+                    Ok(false) => continue,
+                    Ok(true) => Ok(()),
+                    Err(err) => Err(err),
+                };
+                let stop = key.is_err();
+                if armed.lock().is_ok_and(|armed| *armed) {
+                    interrupt.store(true, Ordering::Relaxed);
+                }
+                if keys.send(key).is_err() || stop {
+                    break;
+                }
+            }
+        });
+    }
+    let set_armed = |value| {
+        if let Ok(mut armed) = armed.lock() {
+            *armed = value;
+            ctx.interrupt.store(false, Ordering::Relaxed);
+        }
+    };
+    let wait_for_key = |timeout: Option<Duration>| {
+        let key = match timeout {
+            Some(timeout) => pressed.recv_timeout(timeout),
+            None => pressed.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match key {
+            Ok(Ok(())) => Ok(true),
+            Err(RecvTimeoutError::Timeout) => Ok(false),
+            Ok(Err(err)) => Err(format!("Can't read the keyboards: {err}")),
+            Err(RecvTimeoutError::Disconnected) => Err("Can't read the keyboards".to_string()),
+        }
+    };
+
+    let off = Ast::Literal {
+        direction: ChangeDirection::Abs,
+        value: 0,
+        percent: false,
+    };
+    let timeout = Duration::from(args.timeout);
+    let mut last_key = Instant::now();
+    loop {
+        if let Some(remaining) = timeout.checked_sub(last_key.elapsed())
+            && !remaining.is_zero()
+        {
+            if wait_for_key(Some(remaining))? {
+                last_key = Instant::now();
+            }
+            continue;
+        }
+
+        // a backlight which was turned off by hand stays off
+        let before = device.current().ok().filter(|current| *current > 0);
+        let interrupted = before.is_some() && {
+            set_armed(true);
+            // the fade back uses `before`, so the snapshot of the user's `set --save` stays
+            let summary = apply(&*device, &off, ctx, &args.animation, false, None);
+            let interrupted = ctx.interrupt.load(Ordering::Relaxed);
+            set_armed(false);
+            println!("{}", ctx.formatter.information(&summary?));
+            interrupted
+        };
+        if !interrupted {
+            while !wait_for_key(None)? {}
+        }
+        last_key = Instant::now();
+        if let Some(before) = before {
+            let back = Ast::Literal {
+                direction: ChangeDirection::Abs,
+                value: before,
+                percent: false,
+            };
+            let summary = apply(&*device, &back, ctx, &args.animation, false, None)?;
+            println!("{}", ctx.formatter.information(&summary));
+        }
+        io::stdout()
+            .flush()
+            .map_err(|err| format!("Can't write to stdout: {err}"))?;
+    }
+}
+
+fn record_handler(args: RecordArgs, ctx: &Context) -> Result<(), ErrorReport> {
+    let devices: Vec<_> = if args.device.is_empty() {
+        all_devices(&ctx.settings, &ctx.easings)
+            .into_iter()
+            // virtual devices only forward to the others, which are recorded themselves
+            .filter(|(class, _)| *class != DeviceClass::Virtual)
+            .flat_map(|(_, devices)| devices)
+            .collect()
+    } else {
+        let mut devices = Vec::new();
+        for selector in &args.device {
+            devices.extend(get_devices(Some(selector), &ctx.settings, &ctx.easings)?);
+        }
+        devices
+    };
+    if devices.is_empty() {
+        return Err(DeviceNotFound::NoFound.into());
+    }
+
+    let mut recorded: Vec<_> = devices
+        .into_iter()
+        .map(|device| {
+            let name = format!(
+                "{}:{}",
+                device.class().sysfs_name(),
+                device.name().unwrap_or(UNNAMED)
+            );
+            (device, name, None)
+        })
+        .collect();
+    let write_error = |err: io::Error| format!("Can't write {}: {err}", args.file.display());
+    let mut file = fs::File::create(&args.file).map_err(write_error)?;
+    let mut count = 0;
+    let start = Instant::now();
+    loop {
+        let at = start.elapsed();
+        for (device, name, last) in &mut recorded {
+            // a device which can't be read for a moment keeps its last brightness
+            if let Ok(brightness) = device.current()
+                && *last != Some(brightness)
+            {
+                let event = SessionEvent {
+                    at,
+                    device: name.clone(),
+                    brightness,
+                };
+                // a single write per line, so being killed cuts off at most the last one
+                file.write_all(format!("{}\n", event.to_json()).as_bytes())
+                    .map_err(write_error)?;
+                *last = Some(brightness);
+                count += 1;
+            }
+        }
+        if args.duration.is_some_and(|duration| at >= *duration) {
+            break;
+        }
+        thread::sleep(*args.interval);
+    }
+
+    let information = [Information::new(
+        "Recorded".to_string(),
+        format!("{count} events"),
+        Some(args.file.display().to_string()),
+    )];
+    println!("{}", ctx.formatter.information(&information));
+    Ok(())
+}
+
+fn replay_handler(args: ReplayArgs, ctx: &Context) -> Result<(), ErrorReport> {
+    let content = fs::read_to_string(&args.file)
+        .map_err(|err| format!("Can't read {}: {err}", args.file.display()))?;
+    let session: Session = content.parse().map_err(|err| {
+        ErrorReport::new(
+            "InvalidSession",
+            format!("Can't replay {}: {err}", args.file.display()),
+        )
+    })?;
+
+    // a device which is gone or fails doesn't stop the others from being replayed
+    let mut devices: BTreeMap<&str, Option<Box<dyn Device>>> = BTreeMap::new();
+    let mut failed = 0;
+    let start = Instant::now();
+    for event in &session.events {
+        thread::sleep(event.at.div_f64(args.speed).saturating_sub(start.elapsed()));
+
+        let device = devices.entry(&event.device).or_insert_with(|| {
+            get_device(Some(&event.device), &ctx.settings, &ctx.easings)
+                .map_err(|err| eprintln!("{}", ctx.formatter.error(&err.into())))
+                .ok()
+        });
+        let Some(device) = device else {
+            failed += 1;
+            continue;
+        };
+        if let Err(err) = device.set(event.brightness) {
+            let report = ErrorReport::new("WriteFailed", format!("Writing brightness: {err}"))
+                .with("device", event.device.as_str())
+                .with("value", event.brightness);
+            eprintln!("{}", ctx.formatter.error(&report));
+            failed += 1;
+        }
+    }
+
+    let information = [Information::new(
+        "Replayed".to_string(),
+        format!("{} events", session.events.len()),
+        Some(format!(
+            "in {}",
+            humantime::format_duration(session.length().div_f64(args.speed))
+        )),
+    )];
+    println!("{}", ctx.formatter.information(&information));
+
+    if failed > 0 {
+        return Err(format!("{failed} events couldn't be replayed").into());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "self-update")]
+fn self_handler(command: SelfCommand, formatter: &dyn Formatter) -> Result<(), ErrorReport> {
+    use update::Version;
+
+    let failed = |err: update::UpdateError| ErrorReport::new("UpdateFailed", err.to_string());
+    let installed = Version::current();
+    let release = update::latest_release().map_err(failed)?;
+    let newer = release.version > installed;
+
+    let information = match command {
+        SelfCommand::Check => vec![
+            Information::new("Installed".to_string(), installed.to_string(), None),
+            Information::new(
+                "Latest".to_string(),
+                release.version.to_string(),
+                Some(
+                    if newer {
+                        "update available"
+                    } else {
+                        "up to date"
+                    }
+                    .to_string(),
+                ),
+            ),
+        ],
+        SelfCommand::Update if !newer => vec![Information::new(
+            "Up to date".to_string(),
+            installed.to_string(),
+            None,
+        )],
+        SelfCommand::Update => {
+            let path = update::install(&release).map_err(failed)?;
+            vec![Information::new(
+                "Updated".to_string(),
+                format!("{installed} to {}", release.version),
+                Some(path.display().to_string()),
+            )]
+        }
+    };
+    println!("{}", formatter.information(&information));
+    Ok(())
+}
+
+fn easing_handler(command: EasingCommand, ctx: &Context) -> Result<(), ErrorReport> {
+    let parse_device = |device: &str| match device {
+        "default" => Ok(EasingDevice::Default),
+        device => device
+            .parse()
+            .map_err(|err: UnknownClassError| err.to_string()),
+    };
+
+    // `ctx.easings` may be overwritten by `--easing`, so the file is read again
+    let mut easings = Easings::from_config()
+        .map_err(|err| format!("Can't read the easings: {err}"))?
+        .unwrap_or_else(Easings::new);
+
+    let (device, easing) = match command {
+        EasingCommand::Tune { device } => {
+            let device = get_device(device, &ctx.settings, &ctx.easings)?;
+            let name = device
+                .name()
+                .ok_or("Only devices with a name can have an easing")?;
+            let previous = device.current().map_err(|err| {
+                ErrorReport::new("ReadFailed", format!("Reading current brightness: {err}"))
+            })?;
+
+            let start = tune::exponent(&easings.get_or_default(Some(name), device.class()));
+            let result = tune::tune(&*device, start.unwrap_or(2.2));
+            if let Err(err) = device.set(previous) {
+                eprintln!("Can't restore the brightness: {err}");
+            }
+
+            let Some(easing) = result.map_err(|err| format!("Can't tune the easing: {err}"))?
+            else {
+                return Ok(());
+            };
+            let device = EasingDevice::from(name);
+            easings.insert(device.clone(), easing.clone());
+            (device, easing.to_string())
+        }
+        EasingCommand::Set { device, easing } => {
+            easing
+                .verify()
+                .map_err(|err| format!("The easing {easing} can't be used, {err}"))?;
+
+            let device = parse_device(&device)?;
+            easings.insert(device.clone(), easing.clone());
+            (device, easing.to_string())
+        }
+        EasingCommand::Unset { device } => {
+            let device = parse_device(&device)?;
+            easings
+                .remove(&device)
+                .ok_or_else(|| format!("{device} has no easing"))?;
+            (device, String::from("unset"))
+        }
+    };
+
+    let path = write_easings(&easings).map_err(|err| format!("Can't save the easings: {err}"))?;
+    println!(
+        "{}",
+        ctx.formatter.information(&[
+            Information::new("Device".to_string(), device.to_string(), None),
+            Information::new("Easing".to_string(), easing, None),
+            Information::new("Saved to".to_string(), path.display().to_string(), None),
+        ])
+    );
+
+    Ok(())
+}
+
+fn config_handler(command: ConfigCommand, ctx: &Context) -> Result<(), ErrorReport> {
+    let (key, value) = match &command {
+        ConfigCommand::Set { key, value } => (key.as_str(), Some(value.as_str())),
+        ConfigCommand::Unset { key } => (key.as_str(), None),
+    };
+    let path = write_setting(key, value).map_err(|err| {
+        ErrorReport::new("InvalidSetting", format!("Can't save the setting: {err}"))
+            .with("key", key)
+    })?;
+    println!(
+        "{}",
+        ctx.formatter.information(&[
+            Information::new("Setting".to_string(), key.to_string(), None),
+            Information::new(
+                "Value".to_string(),
+                value.unwrap_or("unset").to_string(),
+                None
+            ),
+            Information::new("Saved to".to_string(), path.display().to_string(), None),
+        ])
+    );
+
     Ok(())
 }
@@ -1,6 +1,7 @@
 use crate::config::Easings;
 use std::fmt::Display;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Information {
     category: String,
     data: String,
@@ -15,6 +16,18 @@ impl Information {
             details,
         }
     }
+
+    pub fn category(&self) -> &str {
+        &self.category
+    }
+
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
 }
 
 impl Display for Information {
@@ -28,6 +41,87 @@ impl Display for Information {
     }
 }
 
+/// An entry of [`Meta::meta`], which is only computed once it is needed
+pub struct Entry<'a> {
+    category: &'static str,
+    compute: Box<dyn FnOnce() -> Option<(String, Option<String>)> + 'a>,
+}
+
+impl<'a> Entry<'a> {
+    /// `compute` returns the data and details, or `None` if they aren't available for the device
+    pub fn new(
+        category: &'static str,
+        compute: impl FnOnce() -> Option<(String, Option<String>)> + 'a,
+    ) -> Self {
+        Self {
+            category,
+            compute: Box::new(compute),
+        }
+    }
+
+    pub fn category(&self) -> &'static str {
+        self.category
+    }
+
+    pub fn compute(self) -> Option<Information> {
+        let (data, details) = (self.compute)()?;
+        Some(Information::new(self.category.to_string(), data, details))
+    }
+}
+
+pub type Entries<'a> = Box<dyn Iterator<Item = Entry<'a>> + 'a>;
+
 pub trait Meta {
-    fn meta(&self, easing: &Easings) -> Vec<Information>;
+    /// Everything worth knowing about a device. Nothing is read before an entry is computed, so
+    /// callers only interested in a few entries don't pay for the others
+    fn meta<'a>(&'a self, easings: &'a Easings) -> Entries<'a>;
+
+    /// Computes all available entries
+    fn information(&self, easings: &Easings) -> Vec<Information> {
+        self.meta(easings).filter_map(Entry::compute).collect()
+    }
+
+    /// Computes only the entry of a category, like `Current brightness`
+    fn field(&self, easings: &Easings, category: &str) -> Option<Information> {
+        self.meta(easings)
+            .find(|entry| entry.category() == category)?
+            .compute()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    struct Counting {
+        computed: Cell<usize>,
+    }
+
+    impl Meta for Counting {
+        fn meta<'a>(&'a self, _: &'a Easings) -> Entries<'a> {
+            let entry = |category| {
+                Entry::new(category, move || {
+                    self.computed.set(self.computed.get() + 1);
+                    Some((category.to_string(), None))
+                })
+            };
+            Box::new([entry("Device"), entry("Type"), entry("Power mode")].into_iter())
+        }
+    }
+
+    #[test]
+    fn test_lazy_entries() {
+        let easings = Easings::default();
+        let counting = Counting {
+            computed: Cell::new(0),
+        };
+
+        let info = counting.field(&easings, "Power mode").unwrap();
+        assert_eq!(info.to_string(), "Power mode: Power mode");
+        assert_eq!(counting.computed.get(), 1);
+
+        assert_eq!(counting.information(&easings).len(), 3);
+        assert_eq!(counting.computed.get(), 4);
+    }
 }
@@ -0,0 +1,558 @@
+use crate::{
+    animation::easing::Easing,
+    brightness::perceptual::PerceptualMapper,
+    device::{
+        BrightnessRead, DeviceClass, UNNAMED, als::AmbientLightSensor, errors::DeviceNotFound,
+    },
+    meta::Information,
+    snapshot::Snapshot,
+};
+use serde_json::{Map, Value, json};
+use std::{path::PathBuf, str::FromStr};
+use thiserror::Error;
+
+const UNDERLINE_FMT: &str = "\x1B[4m";
+const DEFAULT_FMT: &str = "\x1B[0m";
+
+/// A snapshot of a device's brightness, with the `serde` feature it's encoded like in the JSON output
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceReport {
+    pub class: DeviceClass,
+    pub name: String,
+    /// A human-friendly name reported by the hardware
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub label: Option<String>,
+    pub path: Option<PathBuf>,
+    pub current: Option<u32>,
+    pub max: u32,
+    /// The perceived brightness in percent
+    pub percent: Option<f64>,
+    /// The number of discrete hardware levels, only set if they should be shown
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub levels: Option<u64>,
+}
+
+impl DeviceReport {
+    pub fn new(device: &dyn BrightnessRead, easing: &dyn Easing) -> Self {
+        Self::with_current(device, easing, device.current().ok())
+    }
+
+    /// A report using a brightness which was read before
+    pub fn with_current(
+        device: &dyn BrightnessRead,
+        easing: &dyn Easing,
+        current: Option<u32>,
+    ) -> Self {
+        let max = device.max();
+        let mapper = PerceptualMapper::new(easing, max);
+        let percent = current.map(|cur| mapper.percent(cur));
+
+        Self {
+            class: device.class(),
+            name: device.name().unwrap_or(UNNAMED).to_string(),
+            label: device.label(),
+            path: device.path(),
+            current,
+            max,
+            percent,
+            levels: None,
+        }
+    }
+
+    /// Includes the number of hardware levels in the report
+    pub fn with_levels(self) -> Self {
+        Self {
+            levels: Some(u64::from(self.max) + 1),
+            ..self
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut json = json!({
+            "class": self.class.to_string(),
+            "name": self.name,
+            "path": self.path.as_ref().map(|path| path.display().to_string()),
+            "current": self.current,
+            "max": self.max,
+            "percent": self.percent,
+        });
+        if let Some(label) = &self.label {
+            json["label"] = label.as_str().into();
+        }
+        if let Some(levels) = self.levels {
+            json["levels"] = levels.into();
+        }
+        json
+    }
+
+    fn to_csv(&self) -> String {
+        csv_row(&[
+            self.class.to_string(),
+            self.name.clone(),
+            self.path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            self.current.map(|cur| cur.to_string()).unwrap_or_default(),
+            self.max.to_string(),
+            self.percent
+                .map(|perc| perc.to_string())
+                .unwrap_or_default(),
+            self.label.clone().unwrap_or_default(),
+        ])
+    }
+}
+
+/// A snapshot of an ambient light sensor
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SensorReport {
+    pub name: String,
+    pub label: Option<String>,
+    pub path: PathBuf,
+    pub lux: Option<f64>,
+}
+
+impl SensorReport {
+    pub fn new(sensor: &AmbientLightSensor) -> Self {
+        Self {
+            name: sensor.name().unwrap_or(UNNAMED).to_string(),
+            label: sensor.label(),
+            path: sensor.path.clone(),
+            lux: sensor.lux().ok(),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "label": self.label,
+            "path": self.path.display().to_string(),
+            "lux": self.lux,
+        })
+    }
+}
+
+/// An error a command failed with, which wrappers can tell apart by its kind
+#[derive(Debug)]
+pub struct ErrorReport {
+    pub kind: &'static str,
+    pub message: String,
+    /// Further machine readable information, like the device which wasn't found
+    pub fields: Map<String, Value>,
+}
+
+impl ErrorReport {
+    pub fn new(kind: &'static str, message: impl ToString) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+            fields: Map::new(),
+        }
+    }
+
+    pub fn with(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.fields.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+/// Errors without a specific kind
+impl From<String> for ErrorReport {
+    fn from(message: String) -> Self {
+        Self::new("Error", message)
+    }
+}
+
+impl From<&str> for ErrorReport {
+    fn from(message: &str) -> Self {
+        Self::new("Error", message)
+    }
+}
+
+impl From<DeviceNotFound> for ErrorReport {
+    fn from(err: DeviceNotFound) -> Self {
+        let message = err.to_string();
+        match err {
+            DeviceNotFound::NoNamed { name, candidates } => Self::new("DeviceNotFound", message)
+                .with("device", name)
+                .with("candidates", candidates),
+            DeviceNotFound::Ambiguous { name, candidates } => Self::new("AmbiguousDevice", message)
+                .with("device", name)
+                .with("candidates", candidates),
+            DeviceNotFound::NoFound => Self::new("NoDevice", message),
+            DeviceNotFound::InvalidPath { path, .. } => {
+                Self::new("InvalidDevicePath", message).with("path", path.display().to_string())
+            }
+            DeviceNotFound::InvalidPattern { pattern, .. } => {
+                Self::new("InvalidPattern", message).with("device", pattern)
+            }
+        }
+    }
+}
+
+/// Turns the results of the different commands into text
+pub trait Formatter: Sync {
+    /// Formats the devices found by `list`
+    fn devices(&self, devices: &[DeviceReport]) -> String;
+    /// Formats the information of `meta` and the summary of `set`
+    fn information(&self, information: &[Information]) -> String;
+    /// Formats a single device as done by `get`
+    fn brightness(&self, device: &DeviceReport) -> String;
+    /// Formats the sensors found by `list --sensors`
+    fn sensors(&self, sensors: &[SensorReport]) -> String;
+
+    /// Formats the brightnesses of `export`, a shell script unless the format is meant for
+    /// other programs
+    fn snapshot(&self, snapshot: &Snapshot) -> String {
+        snapshot.to_script()
+    }
+
+    /// Whether progress, like single animation frames, should be printed as well
+    fn progress(&self) -> bool {
+        false
+    }
+
+    /// Formats an error for stderr
+    fn error(&self, error: &ErrorReport) -> String {
+        error.message.clone()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Waybar,
+}
+
+impl OutputFormat {
+    pub fn formatter(self) -> Box<dyn Formatter> {
+        match self {
+            Self::Text => Box::new(Text),
+            Self::Json => Box::new(Json),
+            Self::Csv => Box::new(Csv),
+            Self::Waybar => Box::new(Waybar),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown format `{_0}`, expected one of text, json, csv or waybar")]
+pub struct UnknownFormatError(String);
+
+impl FromStr for OutputFormat {
+    type Err = UnknownFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "waybar" => Ok(Self::Waybar),
+            _ => Err(UnknownFormatError(s.to_string())),
+        }
+    }
+}
+
+pub struct Text;
+
+impl Formatter for Text {
+    fn devices(&self, devices: &[DeviceReport]) -> String {
+        let mut buffer = String::new();
+        let mut class = None;
+
+        for device in devices {
+            if class != Some(&device.class) {
+                class = Some(&device.class);
+                buffer.push_str(&format!("{UNDERLINE_FMT}{}{DEFAULT_FMT}:\n", device.class));
+            }
+
+            buffer.push_str(&format!("\t{}", device.name));
+            if let Some(label) = &device.label {
+                buffer.push_str(&format!(" \"{label}\""));
+            }
+            if let Some(path) = &device.path {
+                buffer.push_str(&format!(" {}", path.display()));
+            }
+            buffer.push_str(&format!(" {}", fraction(device)));
+            if let Some(perc) = device.percent {
+                buffer.push_str(&format!(" ({perc}%)"));
+            }
+            if let Some(levels) = device.levels {
+                buffer.push_str(&format!(" [{levels} levels]"));
+            }
+            buffer.push('\n');
+        }
+
+        buffer.trim_end().to_string()
+    }
+
+    fn information(&self, information: &[Information]) -> String {
+        information
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn brightness(&self, device: &DeviceReport) -> String {
+        match device.percent {
+            Some(perc) => format!("{} ({perc}%)", fraction(device)),
+            None => fraction(device),
+        }
+    }
+
+    fn sensors(&self, sensors: &[SensorReport]) -> String {
+        let mut buffer = format!("{UNDERLINE_FMT}Sensors{DEFAULT_FMT}:");
+        for sensor in sensors {
+            buffer.push_str(&format!("\n\t{}", sensor.name));
+            if let Some(label) = &sensor.label {
+                buffer.push_str(&format!(" \"{label}\""));
+            }
+            buffer.push_str(&format!(
+                " {} {}",
+                sensor.path.display(),
+                lux_text(sensor.lux)
+            ));
+        }
+        buffer
+    }
+
+    fn progress(&self) -> bool {
+        true
+    }
+}
+
+pub struct Json;
+
+impl Formatter for Json {
+    fn devices(&self, devices: &[DeviceReport]) -> String {
+        Value::Array(devices.iter().map(DeviceReport::to_json).collect()).to_string()
+    }
+
+    fn information(&self, information: &[Information]) -> String {
+        let list = information
+            .iter()
+            .map(|info| {
+                json!({
+                    "category": info.category(),
+                    "data": info.data(),
+                    "details": info.details(),
+                })
+            })
+            .collect();
+        Value::Array(list).to_string()
+    }
+
+    fn brightness(&self, device: &DeviceReport) -> String {
+        device.to_json().to_string()
+    }
+
+    fn sensors(&self, sensors: &[SensorReport]) -> String {
+        Value::Array(sensors.iter().map(SensorReport::to_json).collect()).to_string()
+    }
+
+    fn snapshot(&self, snapshot: &Snapshot) -> String {
+        snapshot.to_json().to_string()
+    }
+
+    fn error(&self, error: &ErrorReport) -> String {
+        let mut object = error.fields.clone();
+        object.insert("kind".to_string(), error.kind.into());
+        object.insert("message".to_string(), error.message.clone().into());
+        json!({ "error": object }).to_string()
+    }
+}
+
+pub struct Csv;
+
+impl Formatter for Csv {
+    fn devices(&self, devices: &[DeviceReport]) -> String {
+        let mut rows = vec!["class,name,path,current,max,percent,label".to_string()];
+        rows.extend(devices.iter().map(DeviceReport::to_csv));
+        rows.join("\n")
+    }
+
+    fn information(&self, information: &[Information]) -> String {
+        let mut rows = vec!["category,data,details".to_string()];
+        rows.extend(information.iter().map(|info| {
+            csv_row(&[
+                info.category().to_string(),
+                info.data().to_string(),
+                info.details().unwrap_or_default().to_string(),
+            ])
+        }));
+        rows.join("\n")
+    }
+
+    fn brightness(&self, device: &DeviceReport) -> String {
+        self.devices(std::slice::from_ref(device))
+    }
+
+    fn sensors(&self, sensors: &[SensorReport]) -> String {
+        let mut rows = vec!["name,path,lux,label".to_string()];
+        rows.extend(sensors.iter().map(|sensor| {
+            csv_row(&[
+                sensor.name.clone(),
+                sensor.path.display().to_string(),
+                sensor.lux.map(|lux| lux.to_string()).unwrap_or_default(),
+                sensor.label.clone().unwrap_or_default(),
+            ])
+        }));
+        rows.join("\n")
+    }
+}
+
+/// The format of waybar's custom modules, so no post-processing is needed
+pub struct Waybar;
+
+impl Waybar {
+    fn module(text: String, tooltip: String, percentage: Option<f64>) -> String {
+        json!({
+            "text": text,
+            "tooltip": tooltip,
+            "percentage": percentage.map(|perc| perc.round() as u8),
+        })
+        .to_string()
+    }
+}
+
+impl Formatter for Waybar {
+    fn devices(&self, devices: &[DeviceReport]) -> String {
+        let percentage = devices.iter().find_map(|device| device.percent);
+        let tooltip = devices
+            .iter()
+            .map(|device| format!("{}: {}", device.name, fraction(device)))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Self::module(percent_text(percentage), tooltip, percentage)
+    }
+
+    fn information(&self, information: &[Information]) -> String {
+        let text = information
+            .last()
+            .map(|info| info.data().to_string())
+            .unwrap_or_default();
+        Self::module(text, Text.information(information), None)
+    }
+
+    fn brightness(&self, device: &DeviceReport) -> String {
+        Self::module(
+            percent_text(device.percent),
+            format!("{}: {}", device.name, fraction(device)),
+            device.percent,
+        )
+    }
+
+    fn sensors(&self, sensors: &[SensorReport]) -> String {
+        let tooltip = sensors
+            .iter()
+            .map(|sensor| format!("{}: {}", sensor.name, lux_text(sensor.lux)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let text = sensors
+            .first()
+            .map(|sensor| lux_text(sensor.lux))
+            .unwrap_or_default();
+        Self::module(text, tooltip, None)
+    }
+}
+
+fn fraction(device: &DeviceReport) -> String {
+    let current = device
+        .current
+        .map_or_else(|| String::from('?'), |cur| cur.to_string());
+    format!("{current}/{}", device.max)
+}
+
+fn lux_text(lux: Option<f64>) -> String {
+    lux.map_or_else(|| String::from("? lux"), |lux| format!("{lux:.0} lux"))
+}
+
+fn percent_text(percent: Option<f64>) -> String {
+    percent.map_or_else(|| String::from('?'), |perc| format!("{perc:.0}%"))
+}
+
+fn csv_row(fields: &[String]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> DeviceReport {
+        DeviceReport {
+            class: DeviceClass::Backlight,
+            name: "intel_backlight".to_string(),
+            label: None,
+            path: None,
+            current: Some(420),
+            max: 1000,
+            percent: Some(42.0),
+            levels: None,
+        }
+    }
+
+    #[test]
+    fn test_waybar() {
+        assert_eq!(
+            Waybar.brightness(&report()),
+            r#"{"percentage":42,"text":"42%","tooltip":"intel_backlight: 420/1000"}"#
+        );
+    }
+
+    #[test]
+    fn test_json_error() {
+        let error = ErrorReport::from(DeviceNotFound::NoNamed {
+            name: "foo".to_string(),
+            candidates: vec!["intel_backlight".to_string()],
+        });
+        assert_eq!(
+            Json.error(&error),
+            r#"{"error":{"candidates":["intel_backlight"],"device":"foo","kind":"DeviceNotFound","message":"no device named 'foo' available, try one of intel_backlight"}}"#
+        );
+        assert_eq!(Text.error(&error), error.message);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let report = report().with_levels();
+        assert_eq!(serde_json::to_value(&report).unwrap(), report.to_json());
+        let decoded: DeviceReport = serde_json::from_value(report.to_json()).unwrap();
+        assert_eq!(decoded.levels, Some(1001));
+        assert_eq!(decoded.label, None);
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            Csv.brightness(&report()),
+            "class,name,path,current,max,percent,label\nBacklight,intel_backlight,,420,1000,42,"
+        );
+        assert_eq!(
+            csv_row(&["a,b".to_string(), "say \"hi\"".to_string()]),
+            r#""a,b","say ""hi""""#
+        );
+    }
+}
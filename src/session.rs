@@ -0,0 +1,167 @@
+//! The brightness changes of several devices over time, written by `bright record` and
+//! re-applied by `bright replay`. Sessions are stored as JSON lines, one event per line, so
+//! recording only appends to the file
+
+use serde_json::{Value, json};
+use std::{str::FromStr, time::Duration};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Session {
+    /// Ordered by their time
+    pub events: Vec<SessionEvent>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionEvent {
+    /// Since the recording started
+    pub at: Duration,
+    /// The device qualified with its class, like `backlight:intel_backlight`
+    pub device: String,
+    /// The raw hardware level
+    pub brightness: u32,
+}
+
+impl SessionEvent {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "at_ms": self.at.as_millis() as u64,
+            "device": self.device,
+            "brightness": self.brightness,
+        })
+    }
+}
+
+impl Session {
+    /// How long replaying it takes at the original speed
+    pub fn length(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, |event| event.at)
+    }
+}
+
+/// A final line without a line break is ignored if it's invalid, it was cut off by the recording
+/// being killed
+impl FromStr for Session {
+    type Err = SessionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut events = Vec::new();
+        let lines: Vec<_> = s.lines().collect();
+        for (index, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let cut_off = index == lines.len() - 1 && !s.ends_with('\n');
+            match parse_event(line, index + 1) {
+                Ok(event) => events.push(event),
+                Err(_) if cut_off => {}
+                Err(err) => return Err(err),
+            }
+        }
+        // edited files don't have to be ordered, the order of simultaneous events is kept
+        events.sort_by_key(|event| event.at);
+
+        Ok(Self { events })
+    }
+}
+
+fn parse_event(line: &str, number: usize) -> Result<SessionEvent, SessionParseError> {
+    let event: Value = serde_json::from_str(line).map_err(|source| SessionParseError::Json {
+        line: number,
+        source,
+    })?;
+    let at = event["at_ms"].as_u64().map(Duration::from_millis);
+    let device = event["device"].as_str();
+    let brightness = event["brightness"]
+        .as_u64()
+        .and_then(|brightness| u32::try_from(brightness).ok());
+    match (at, device, brightness) {
+        (Some(at), Some(device), Some(brightness)) => Ok(SessionEvent {
+            at,
+            device: device.to_string(),
+            brightness,
+        }),
+        _ => Err(SessionParseError::InvalidEvent { line: number }),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SessionParseError {
+    #[error("invalid JSON in line {line}: {source}")]
+    Json {
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error(
+        "the event in line {line} needs a non-negative `at_ms`, a `device` and a non-negative `brightness`"
+    )]
+    InvalidEvent { line: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let session = Session {
+            events: vec![
+                SessionEvent {
+                    at: Duration::ZERO,
+                    device: "backlight:intel_backlight".to_string(),
+                    brightness: 420,
+                },
+                SessionEvent {
+                    at: Duration::from_millis(1500),
+                    device: "leds:tpacpi::kbd_backlight".to_string(),
+                    brightness: 1,
+                },
+            ],
+        };
+        let lines: String = session
+            .events
+            .iter()
+            .map(|event| format!("{}\n", event.to_json()))
+            .collect();
+        assert_eq!(lines.parse::<Session>().unwrap(), session);
+        assert_eq!(session.length(), Duration::from_millis(1500));
+
+        let unordered = r#"{"at_ms": 20, "device": "a", "brightness": 2}
+
+{"at_ms": 10, "device": "b", "brightness": 1}
+"#;
+        let devices: Vec<_> = unordered
+            .parse::<Session>()
+            .unwrap()
+            .events
+            .into_iter()
+            .map(|event| event.device)
+            .collect();
+        assert_eq!(devices, ["b", "a"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            "[]\n".parse::<Session>(),
+            Err(SessionParseError::InvalidEvent { line: 1 })
+        ));
+        assert!(matches!(
+            r#"{"at_ms": 0, "device": "a", "brightness": 1}
+{"at_ms": -1, "device": "a", "brightness": 1}
+"#
+            .parse::<Session>(),
+            Err(SessionParseError::InvalidEvent { line: 2 })
+        ));
+        assert!(matches!(
+            "{\"at_ms\": 0,\n".parse::<Session>(),
+            Err(SessionParseError::Json { line: 1, .. })
+        ));
+
+        // a recording which was killed while writing a line
+        let cut_off = r#"{"at_ms": 0, "device": "a", "brightness": 1}
+{"at_ms": 5"#;
+        assert_eq!(cut_off.parse::<Session>().unwrap().events.len(), 1);
+    }
+}
@@ -0,0 +1,214 @@
+//! The brightness of all devices, written by `bright export` as a shell script of `bright set`
+//! commands or as JSON and read back by `bright import`
+
+use serde_json::{Value, json};
+use std::str::FromStr;
+use thiserror::Error;
+
+const SHEBANG: &str = "#!/bin/sh";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    /// The device qualified with its class, like `backlight:intel_backlight`
+    pub device: String,
+    /// The raw hardware level
+    pub brightness: u32,
+}
+
+impl Snapshot {
+    pub fn to_script(&self) -> String {
+        let mut lines = vec![
+            SHEBANG.to_string(),
+            "# written by `bright export`, replay it using `bright import`".to_string(),
+        ];
+        lines.extend(self.entries.iter().map(|entry| {
+            format!(
+                "bright set --device {} {}",
+                quote(&entry.device),
+                entry.brightness
+            )
+        }));
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> Value {
+        self.entries
+            .iter()
+            .map(|entry| json!({ "device": entry.device, "brightness": entry.brightness }))
+            .collect()
+    }
+
+    fn from_json(s: &str) -> Result<Self, SnapshotParseError> {
+        let value: Value = serde_json::from_str(s)?;
+        let entries = value
+            .as_array()
+            .ok_or(SnapshotParseError::NoArray)?
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let device = entry["device"].as_str();
+                let brightness = entry["brightness"]
+                    .as_u64()
+                    .and_then(|brightness| u32::try_from(brightness).ok());
+                match (device, brightness) {
+                    (Some(device), Some(brightness)) => Ok(SnapshotEntry {
+                        device: device.to_string(),
+                        brightness,
+                    }),
+                    _ => Err(SnapshotParseError::InvalidEntry { index }),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { entries })
+    }
+
+    fn from_script(s: &str) -> Result<Self, SnapshotParseError> {
+        let mut entries = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || SnapshotParseError::InvalidLine {
+                line_number: i,
+                line: line.to_string(),
+            };
+            let words = split_words(line).ok_or_else(invalid)?;
+            let [bright, set, flag, device, brightness] = words.as_slice() else {
+                return Err(invalid());
+            };
+            if bright != "bright" || set != "set" || flag != "--device" {
+                return Err(invalid());
+            }
+
+            entries.push(SnapshotEntry {
+                device: device.clone(),
+                brightness: brightness.parse().map_err(|_| invalid())?,
+            });
+        }
+
+        Ok(Self { entries })
+    }
+}
+
+/// Accepts both the script and the JSON written by `bright export`
+impl FromStr for Snapshot {
+    type Err = SnapshotParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim_start().starts_with('[') {
+            Self::from_json(s)
+        } else {
+            Self::from_script(s)
+        }
+    }
+}
+
+/// Quotes a word for the shell if needed, LED names often contain colons
+fn quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-.:/+@%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Splits a line into words the way the shell would for the quoting done by [`quote`], returns
+/// `None` if a quote isn't closed
+fn split_words(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_default().push(chars.next()?),
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+
+    Some(words)
+}
+
+#[derive(Debug, Error)]
+pub enum SnapshotParseError {
+    #[error("invalid JSON: {_0}")]
+    Json(
+        #[from]
+        #[source]
+        serde_json::Error,
+    ),
+    #[error("the JSON isn't a list of devices")]
+    NoArray,
+    #[error("entry {index} needs a `device` and a non-negative `brightness`")]
+    InvalidEntry { index: usize },
+    #[error("line {l} isn't of the form `bright set --device <device> <level>`: {line}", l = line_number + 1)]
+    InvalidLine { line_number: usize, line: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            entries: vec![
+                SnapshotEntry {
+                    device: "backlight:intel_backlight".to_string(),
+                    brightness: 420,
+                },
+                SnapshotEntry {
+                    device: "leds:tpacpi::kbd's light".to_string(),
+                    brightness: 1,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let script = snapshot().to_script();
+        assert!(script.contains("bright set --device backlight:intel_backlight 420\n"));
+        assert!(script.starts_with(SHEBANG));
+        assert!(script.contains(r"bright set --device 'leds:tpacpi::kbd'\''s light' 1"));
+        assert_eq!(script.parse::<Snapshot>().unwrap(), snapshot());
+
+        let json = snapshot().to_json().to_string();
+        assert_eq!(json.parse::<Snapshot>().unwrap(), snapshot());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            "\nbright get".parse::<Snapshot>(),
+            Err(SnapshotParseError::InvalidLine { line_number: 1, .. })
+        ));
+        assert!(matches!(
+            "bright set --device 'foo 1".parse::<Snapshot>(),
+            Err(SnapshotParseError::InvalidLine { .. })
+        ));
+        assert!(matches!(
+            r#"[{"device": "foo", "brightness": -1}]"#.parse::<Snapshot>(),
+            Err(SnapshotParseError::InvalidEntry { index: 0 })
+        ));
+    }
+}
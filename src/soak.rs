@@ -0,0 +1,78 @@
+use std::{fs, io};
+
+/// What a leaking process accumulates, read from `/proc/self`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub open_fds: usize,
+    /// The resident memory in KiB
+    pub resident: u64,
+}
+
+impl Usage {
+    pub fn measure() -> io::Result<Self> {
+        let open_fds = fs::read_dir("/proc/self/fd")?.count();
+        let status = fs::read_to_string("/proc/self/status")?;
+        let resident = resident(&status)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no VmRSS in status"))?;
+
+        Ok(Self { open_fds, resident })
+    }
+
+    /// Describes what grew more than allowed since `baseline`
+    pub fn leaks(&self, baseline: &Self, max_fd_growth: usize, max_growth: u64) -> Vec<String> {
+        let mut leaks = Vec::new();
+        if self.open_fds > baseline.open_fds + max_fd_growth {
+            leaks.push(format!(
+                "{} files are open instead of {}",
+                self.open_fds, baseline.open_fds
+            ));
+        }
+        if self.resident > baseline.resident + max_growth {
+            leaks.push(format!(
+                "the resident memory grew from {} KiB to {} KiB",
+                baseline.resident, self.resident
+            ));
+        }
+        leaks
+    }
+}
+
+/// Parses a line like `VmRSS: 4321 kB`
+fn resident(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leaks() {
+        assert_eq!(resident("Name:\tbright\nVmRSS:\t    4321 kB\n"), Some(4321));
+        assert_eq!(resident("Name:\tbright\n"), None);
+
+        let baseline = Usage {
+            open_fds: 4,
+            resident: 4000,
+        };
+        let after = Usage {
+            open_fds: 5,
+            resident: 4500,
+        };
+        assert!(after.leaks(&baseline, 1, 1024).is_empty());
+        assert_eq!(
+            after.leaks(&baseline, 0, 100),
+            [
+                "5 files are open instead of 4",
+                "the resident memory grew from 4000 KiB to 4500 KiB"
+            ]
+        );
+    }
+}
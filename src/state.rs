@@ -0,0 +1,468 @@
+use std::{
+    env,
+    fmt::{self, Display},
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::{fd::AsRawFd, unix::fs::FileExt},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+/// How long a cached maximum is used, the state directory is usually emptied on boot anyway
+const MAX_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The directory for files shared between invocations, like saved brightnesses and locks.
+/// It can be moved using `BRIGHT_STATE_DIR`.
+///
+/// The files of a device are named after its class-qualified name like
+/// `backlight:intel_backlight` (see [`crate::device::qualified`]), so devices of different classes
+/// don't share them and no name collides with the subdirectories
+pub fn state_dir() -> PathBuf {
+    env::var_os("BRIGHT_STATE_DIR").map_or_else(
+        || PathBuf::from(format!("/tmp/{}", env!("CARGO_PKG_NAME"))),
+        PathBuf::from,
+    )
+}
+
+/// The target of the last coalescing invocation for a device, if it was recorded within `window`
+pub fn recent_target(device: &str, window: Duration) -> Option<u32> {
+    read_recent("targets", device, window)
+}
+
+/// Records the target of a coalescing invocation, see [`recent_target`]
+pub fn record_target(device: &str, value: u32) -> io::Result<()> {
+    write_value("targets", device, value)
+}
+
+/// The brightness `get` read within the last `ttl`, so status bars polling several times a
+/// second don't have to read sysfs every time
+pub fn cached_brightness(device: &str, ttl: Duration) -> Option<u32> {
+    read_recent("cache", device, ttl)
+}
+
+pub fn cache_brightness(device: &str, value: u32) -> io::Result<()> {
+    write_value("cache", device, value)
+}
+
+/// Removes the cached brightness, which is done after every write
+pub fn invalidate_cached_brightness(device: &str) -> io::Result<()> {
+    match fs::remove_file(state_dir().join("cache").join(device)) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// The maximum of a device which is slow to ask for, like UPower's keyboard backlight
+pub fn cached_max(device: &str) -> Option<u32> {
+    read_recent("max", device, MAX_TTL)
+}
+
+pub fn cache_max(device: &str, value: u32) -> io::Result<()> {
+    write_value("max", device, value)
+}
+
+/// Reads a value from `state_dir/kind/device` if it was written within `window`
+fn read_recent(kind: &str, device: &str, window: Duration) -> Option<u32> {
+    let path = state_dir().join(kind).join(device);
+    let age = fs::metadata(&path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+
+    if age > window {
+        return None;
+    }
+    read_value(&path).ok()
+}
+
+fn write_value(kind: &str, device: &str, value: u32) -> io::Result<()> {
+    let dir = state_dir().join(kind);
+    fs::create_dir_all(&dir)?;
+    write_atomic(&dir.join(device), &value.to_string())
+}
+
+/// Replaces the content of `path` so it never ends up empty or half written, even if the
+/// process is killed or the system crashes mid-write.
+///
+/// The content is written to a temporary file next to `path`, synced and then renamed over it.
+pub fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{name}.{}.tmp", process::id()));
+
+    let result = File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(content.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// The version of the format brightnesses are saved in by `set --save`. Files written before
+/// there was a format only contain the brightness
+const SAVED_FORMAT: u32 = 1;
+
+/// A brightness saved by `set --save` for `restore()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedBrightness {
+    pub value: u32,
+    /// The device's maximum when the brightness was saved, so it can be restored on another one
+    pub max: Option<u32>,
+    /// When the brightness was saved, unknown for files from older versions
+    pub saved_at: Option<SystemTime>,
+    /// The easing which was in effect when the brightness was saved
+    pub easing: Option<String>,
+    /// The version of bright which saved the brightness
+    pub version: Option<String>,
+    /// After this point the brightness mustn't be restored anymore
+    pub expires_at: Option<SystemTime>,
+}
+
+impl SavedBrightness {
+    pub fn new(value: u32, easing: &impl Display) -> Self {
+        Self {
+            value,
+            max: None,
+            saved_at: Some(SystemTime::now()),
+            easing: Some(easing.to_string()),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            expires_at: None,
+        }
+    }
+
+    pub fn with_max(self, max: u32) -> Self {
+        Self {
+            max: Some(max),
+            ..self
+        }
+    }
+
+    /// Lets the brightness expire `after` it was saved
+    pub fn expiring(self, after: Duration) -> Self {
+        Self {
+            expires_at: self.saved_at.map(|saved_at| saved_at + after),
+            ..self
+        }
+    }
+
+    /// How long ago the brightness expired, `None` if it is still valid
+    pub fn expired_for(&self) -> Option<Duration> {
+        SystemTime::now().duration_since(self.expires_at?).ok()
+    }
+
+    /// How long ago the brightness was saved
+    pub fn age(&self) -> Option<Duration> {
+        SystemTime::now().duration_since(self.saved_at?).ok()
+    }
+
+    /// Parses both the current `key = value` format and bare numbers of older versions.
+    /// Unknown keys and malformed lines are skipped, so newer versions can add fields
+    fn parse(content: &str) -> Result<Self, StateParseError> {
+        if let Ok(value) = content.trim().parse() {
+            return Ok(Self {
+                value,
+                max: None,
+                saved_at: None,
+                easing: None,
+                version: None,
+                expires_at: None,
+            });
+        }
+
+        let mut value = None;
+        let mut max = None;
+        let mut saved_at = None;
+        let mut easing = None;
+        let mut version = None;
+        let mut expires_at = None;
+        for line in content.lines() {
+            let Some((key, val)) = line.split_once('=') else {
+                continue;
+            };
+            let val = val.trim();
+            match key.trim() {
+                "format" if val != SAVED_FORMAT.to_string() => {
+                    return Err(StateParseError::UnsupportedFormat(val.to_string()));
+                }
+                "value" => value = val.parse().ok(),
+                "max" => max = val.parse().ok(),
+                "saved_at" => saved_at = humantime::parse_rfc3339(val).ok(),
+                "easing" => easing = Some(val.to_string()),
+                "version" => version = Some(val.to_string()),
+                "expires_at" => expires_at = humantime::parse_rfc3339(val).ok(),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            value: value.ok_or(StateParseError::Invalid)?,
+            max,
+            saved_at,
+            easing,
+            version,
+            expires_at,
+        })
+    }
+}
+
+impl Display for SavedBrightness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "format = {SAVED_FORMAT}")?;
+        writeln!(f, "value = {}", self.value)?;
+        if let Some(max) = self.max {
+            writeln!(f, "max = {max}")?;
+        }
+        if let Some(saved_at) = self.saved_at {
+            writeln!(
+                f,
+                "saved_at = {}",
+                humantime::format_rfc3339_seconds(saved_at)
+            )?;
+        }
+        if let Some(easing) = &self.easing {
+            writeln!(f, "easing = {easing}")?;
+        }
+        if let Some(version) = &self.version {
+            writeln!(f, "version = {version}")?;
+        }
+        if let Some(expires_at) = self.expires_at {
+            writeln!(
+                f,
+                "expires_at = {}",
+                humantime::format_rfc3339_seconds(expires_at)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a brightness saved by `set --save`, see [`read_state`]
+pub fn read_saved(path: &Path) -> Result<SavedBrightness, StateReadError> {
+    read_state(path, SavedBrightness::parse)
+}
+
+/// Reads a single number like a cached brightness, see [`read_state`]
+pub fn read_value(path: &Path) -> Result<u32, StateReadError> {
+    read_state(path, |content| {
+        content.trim().parse().map_err(|_| StateParseError::Invalid)
+    })
+}
+
+/// Reads a state file.
+///
+/// Files which can't be parsed, e.g. ones truncated by a crash before writes were atomic, are
+/// moved aside to `<name>.corrupt`, so the next write starts from a clean state without losing
+/// them. Files in a format of a newer version are left alone.
+fn read_state<T>(
+    path: &Path,
+    parse: impl FnOnce(&str) -> Result<T, StateParseError>,
+) -> Result<T, StateReadError> {
+    let content = fs::read_to_string(path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => StateReadError::Missing(path.to_path_buf()),
+        _ => StateReadError::Read {
+            path: path.to_path_buf(),
+            source: err,
+        },
+    })?;
+
+    parse(&content).map_err(|err| match err {
+        StateParseError::UnsupportedFormat(format) => StateReadError::UnsupportedFormat {
+            path: path.to_path_buf(),
+            format,
+        },
+        StateParseError::Invalid => {
+            let mut corrupt = path.as_os_str().to_owned();
+            corrupt.push(".corrupt");
+            let corrupt = PathBuf::from(corrupt);
+            StateReadError::Corrupted {
+                path: path.to_path_buf(),
+                moved_to: fs::rename(path, &corrupt).is_ok().then_some(corrupt),
+                content,
+            }
+        }
+    })
+}
+
+/// Why the content of a state file can't be used
+#[derive(Debug)]
+enum StateParseError {
+    Invalid,
+    /// The `format` of a newer version
+    UnsupportedFormat(String),
+}
+
+#[derive(Debug, Error)]
+pub enum StateReadError {
+    #[error("{} doesn't exist", _0.display())]
+    Missing(PathBuf),
+    #[error("can't read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(
+        "{} was corrupted{}",
+        path.display(),
+        moved_to.as_ref().map_or_else(String::new, |to| format!(" and has been moved to {}", to.display()))
+    )]
+    Corrupted {
+        path: PathBuf,
+        /// Where the file was moved to, `None` if moving it failed
+        moved_to: Option<PathBuf>,
+        content: String,
+    },
+    #[error("{} has the unknown format {format}, it was likely saved by a newer version", path.display())]
+    UnsupportedFormat { path: PathBuf, format: String },
+}
+
+/// An advisory lock so only one invocation changes a device at a time.
+///
+/// The lock file contains the pid of the newest invocation which wants the device. The holder
+/// checks it between frames and stops as soon as somebody else claimed the device, so the newest
+/// invocation always takes over instead of interleaving frames.
+pub struct DeviceLock {
+    file: File,
+    pid: u32,
+}
+
+impl DeviceLock {
+    /// Claims the device and blocks until the current holder released it
+    pub fn acquire(device: &str) -> io::Result<Self> {
+        let dir = state_dir().join("locks");
+        fs::create_dir_all(&dir)?;
+        Self::acquire_path(&dir.join(device))
+    }
+
+    fn acquire_path(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let pid = process::id();
+
+        // the claim is announced before waiting, so the holder knows it should stop
+        file.set_len(0)?;
+        file.write_all_at(pid.to_string().as_bytes(), 0)?;
+
+        loop {
+            // SAFETY: the file descriptor is valid as long as `file` lives
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+                break;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+
+        Ok(Self { file, pid })
+    }
+
+    /// Whether a newer invocation claimed the device
+    pub fn superseded(&self) -> bool {
+        let mut buffer = [0; 16];
+        let Ok(read) = self.file.read_at(&mut buffer, 0) else {
+            return false;
+        };
+
+        // a claim which is being written can't be parsed, it will be seen on the next check
+        std::str::from_utf8(&buffer[..read])
+            .ok()
+            .and_then(|content| content.trim().parse::<u32>().ok())
+            .is_some_and(|pid| pid != self.pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_superseded() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("intel_backlight");
+
+        let lock = DeviceLock::acquire_path(&path).unwrap();
+        assert!(!lock.superseded());
+
+        // another invocation announcing its claim
+        fs::write(&path, (process::id() + 1).to_string()).unwrap();
+        assert!(lock.superseded());
+    }
+
+    #[test]
+    fn test_atomic_roundtrip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("intel_backlight");
+
+        write_atomic(&path, "420").unwrap();
+        write_atomic(&path, "42").unwrap();
+        assert_eq!(read_value(&path).unwrap(), 42);
+        // no temporary files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_corrupted_value() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("intel_backlight");
+
+        assert!(matches!(read_value(&path), Err(StateReadError::Missing(_))));
+
+        // what a crash between truncating and writing used to leave behind
+        fs::write(&path, "").unwrap();
+        assert!(matches!(
+            read_value(&path),
+            Err(StateReadError::Corrupted {
+                moved_to: Some(_),
+                ..
+            })
+        ));
+        assert!(!path.exists());
+        assert!(dir.path().join("intel_backlight.corrupt").exists());
+    }
+
+    #[test]
+    fn test_saved_brightness() {
+        let saved = SavedBrightness::new(420, &"x^2").with_max(1000);
+        let parsed = SavedBrightness::parse(&saved.to_string()).ok().unwrap();
+        assert_eq!(parsed.value, 420);
+        assert_eq!(parsed.max, Some(1000));
+        assert_eq!(parsed.easing.as_deref(), Some("x^2"));
+        assert!(parsed.age().unwrap() < Duration::from_secs(2));
+
+        // files written before the format existed
+        let legacy = SavedBrightness::parse("420").ok().unwrap();
+        assert_eq!(legacy.value, 420);
+        assert_eq!(legacy.saved_at, None);
+
+        assert_eq!(SavedBrightness::parse("format = 1\neasing = x").ok(), None);
+        // a line which can't be parsed doesn't make the rest unusable
+        let odd = SavedBrightness::parse("format = 1\nvalue = 420\n<<<<<<<\nmax = ?\n").unwrap();
+        assert_eq!(odd.value, 420);
+        assert_eq!(odd.max, None);
+        assert!(matches!(
+            SavedBrightness::parse("format = 2\nvalue = 420\n"),
+            Err(StateParseError::UnsupportedFormat(format)) if format == "2"
+        ));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let saved = SavedBrightness::new(420, &"x").expiring(Duration::from_secs(3600));
+        let parsed = SavedBrightness::parse(&saved.to_string()).ok().unwrap();
+        assert_eq!(parsed.expired_for(), None);
+
+        let mut expired = parsed;
+        expired.expires_at = Some(SystemTime::now() - Duration::from_secs(60));
+        assert!(expired.expired_for().unwrap() >= Duration::from_secs(60));
+    }
+}
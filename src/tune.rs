@@ -0,0 +1,152 @@
+use bright::{
+    animation::easing::EasingKind, brightness::perceptual::PerceptualMapper, device::Device,
+};
+use std::{
+    io::{self, IsTerminal, Read, Write},
+    mem::MaybeUninit,
+};
+
+/// The perceived brightnesses the easing can be compared at
+const REFERENCES: [f64; 5] = [0.1, 0.25, 0.5, 0.75, 0.9];
+const STEP: f64 = 0.1;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Cancel,
+    Other,
+}
+
+impl Key {
+    fn decode(bytes: &[u8]) -> Self {
+        match bytes {
+            b"\x1b[A" => Self::Up,
+            b"\x1b[B" => Self::Down,
+            b"\x1b[C" => Self::Right,
+            b"\x1b[D" => Self::Left,
+            b"\r" | b"\n" => Self::Enter,
+            // escape, ctrl-c, ctrl-d and q
+            b"\x1b" | b"\x03" | b"\x04" | b"q" => Self::Cancel,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Puts the terminal into non-canonical mode without echo until it is dropped
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let mut termios = MaybeUninit::uninit();
+        // SAFETY: tcgetattr initializes `termios` if it succeeds
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            termios.assume_init()
+        };
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        // SAFETY: `raw` is a valid termios copied from the terminal
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw const raw) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: `original` was read from the same terminal
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw const self.original);
+        }
+    }
+}
+
+/// Lets the user adjust the exponent of a polynomial easing using the arrow keys while the device
+/// shows the resulting brightness at a reference percentage.
+/// Returns `None` if the user cancelled
+pub fn tune(device: &dyn Device, mut exponent: f64) -> io::Result<Option<EasingKind>> {
+    if !io::stdin().is_terminal() {
+        return Err(io::Error::other("tuning requires an interactive terminal"));
+    }
+
+    let _raw = RawMode::enable()?;
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut reference = REFERENCES.len() / 2;
+    let mut buffer = [0; 8];
+
+    loop {
+        let easing = polynomial(exponent);
+        let value = PerceptualMapper::new(&easing, device.max()).level(REFERENCES[reference]);
+        let value = device.set(value).map_err(io::Error::other)?;
+        write!(
+            stdout,
+            "\r\x1b[2Kx^{exponent:.1} at {}%: {value}/{} (↑↓ exponent, ←→ reference, enter to save, q to cancel)",
+            REFERENCES[reference] * 100.0,
+            device.max(),
+        )?;
+        stdout.flush()?;
+
+        let read = stdin.read(&mut buffer)?;
+        match Key::decode(&buffer[..read]) {
+            Key::Up => exponent += STEP,
+            Key::Down => exponent = (exponent - STEP).max(STEP),
+            Key::Right => reference = (reference + 1).min(REFERENCES.len() - 1),
+            Key::Left => reference = reference.saturating_sub(1),
+            Key::Enter => {
+                writeln!(stdout, "\r")?;
+                return Ok(Some(easing));
+            }
+            Key::Cancel => {
+                writeln!(stdout, "\r")?;
+                return Ok(None);
+            }
+            Key::Other => {}
+        }
+    }
+}
+
+/// The exponent of a polynomial easing like `x^2.2`
+pub fn exponent(easing: &EasingKind) -> Option<f64> {
+    easing.to_string().strip_prefix("x^")?.parse().ok()
+}
+
+fn polynomial(exponent: f64) -> EasingKind {
+    // rounding avoids exponents like 2.3000000000000003 ending up in the config
+    format!("x^{}", (exponent * 10.0).round() / 10.0)
+        .parse()
+        .expect("positive exponents are valid polynomials")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(Key::decode(b"\x1b[A"), Key::Up);
+        assert_eq!(Key::decode(b"\x1b[D"), Key::Left);
+        assert_eq!(Key::decode(b"\r"), Key::Enter);
+        assert_eq!(Key::decode(b"q"), Key::Cancel);
+        assert_eq!(Key::decode(b"x"), Key::Other);
+    }
+
+    #[test]
+    fn test_exponent() {
+        assert_eq!(exponent(&polynomial(2.2000000000000006)), Some(2.2));
+        assert_eq!(exponent(&"3^x".parse().unwrap()), None);
+    }
+}
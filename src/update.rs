@@ -0,0 +1,260 @@
+//! `bright self`, which updates a binary installed from the GitHub releases. Every release has an
+//! asset per target like `bright-x86_64-unknown-linux-gnu` and its minisign signature
+//! `bright-x86_64-unknown-linux-gnu.minisig`, downloads are done using curl
+
+use derive_more::Display;
+use minisign_verify::{PublicKey, Signature};
+use serde_json::Value;
+use std::{
+    env, fs, io,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+use thiserror::Error;
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/Dlurak/bright/releases/latest";
+const TARGET: &str = env!("BRIGHT_TARGET");
+/// The key releases are signed with, builds without it can only check for updates
+const PUBLIC_KEY: Option<&str> = option_env!("BRIGHT_UPDATE_PUBLIC_KEY");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display)]
+#[display("{major}.{minor}.{patch}")]
+pub struct Version {
+    major: u64,
+    minor: u64,
+    patch: u64,
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("`{_0}` isn't a version like `1.2.3`")]
+pub struct InvalidVersion(String);
+
+/// Accepts tags like `v1.2.3`
+impl FromStr for Version {
+    type Err = InvalidVersion;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidVersion(s.to_string());
+        let mut parts = s.strip_prefix('v').unwrap_or(s).split('.');
+        let mut part = || -> Result<u64, InvalidVersion> {
+            parts
+                .next()
+                .ok_or_else(invalid)?
+                .parse()
+                .map_err(|_| invalid())
+        };
+        let version = Self {
+            major: part()?,
+            minor: part()?,
+            patch: part()?,
+        };
+        match parts.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(version),
+        }
+    }
+}
+
+impl Version {
+    pub fn current() -> Self {
+        env!("CARGO_PKG_VERSION")
+            .parse()
+            .expect("The crate's version is valid")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    pub version: Version,
+    /// The URLs of the binary for this target and its signature, if the release has them
+    pub assets: Option<(String, String)>,
+}
+
+#[derive(Debug, Error)]
+pub enum UpdateError {
+    #[error("can't download {url}: {reason}")]
+    Download { url: String, reason: String },
+    #[error("unexpected release information: {_0}")]
+    InvalidRelease(String),
+    #[error("the latest release has no binary for {TARGET}")]
+    NoAsset,
+    #[error(
+        "this build has no public key to verify updates, build it with BRIGHT_UPDATE_PUBLIC_KEY"
+    )]
+    NoPublicKey,
+    #[error("the signature doesn't match the download: {_0}")]
+    Signature(
+        #[from]
+        #[source]
+        minisign_verify::Error,
+    ),
+    #[error("can't replace {}: {source}", path.display())]
+    Install {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+pub fn latest_release() -> Result<Release, UpdateError> {
+    let json = download(LATEST_RELEASE)?;
+    parse_release(&String::from_utf8_lossy(&json), TARGET)
+}
+
+/// Downloads and verifies the release's binary and replaces the running one with it, returns the
+/// path of the binary
+pub fn install(release: &Release) -> Result<PathBuf, UpdateError> {
+    let public_key = PUBLIC_KEY.ok_or(UpdateError::NoPublicKey)?;
+    let (binary_url, signature_url) = release.assets.as_ref().ok_or(UpdateError::NoAsset)?;
+
+    let binary = download(binary_url)?;
+    let signature = download(signature_url)?;
+    verify(&binary, &String::from_utf8_lossy(&signature), public_key)?;
+
+    let path = env::current_exe().map_err(|source| UpdateError::Install {
+        path: PathBuf::from("bright"),
+        source,
+    })?;
+    replace(&path, &binary).map_err(|source| UpdateError::Install {
+        path: path.clone(),
+        source,
+    })?;
+    Ok(path)
+}
+
+fn download(url: &str) -> Result<Vec<u8>, UpdateError> {
+    let failed = |reason: String| UpdateError::Download {
+        url: url.to_string(),
+        reason,
+    };
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--max-time", "300"])
+        .args(["--header", "Accept: application/vnd.github+json"])
+        .args([
+            "--user-agent",
+            concat!("bright/", env!("CARGO_PKG_VERSION")),
+        ])
+        .arg(url)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| failed(format!("can't run curl: {err}")))?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// Parses the release as returned by GitHub's API
+fn parse_release(json: &str, target: &str) -> Result<Release, UpdateError> {
+    let invalid = |reason: String| UpdateError::InvalidRelease(reason);
+    let release: Value = serde_json::from_str(json).map_err(|err| invalid(err.to_string()))?;
+    let version = release["tag_name"]
+        .as_str()
+        .ok_or_else(|| invalid("no `tag_name`".to_string()))?
+        .parse()
+        .map_err(|err: InvalidVersion| invalid(err.to_string()))?;
+
+    let binary = format!("bright-{target}");
+    let signature = format!("{binary}.minisig");
+    let url = |name: &str| {
+        release["assets"]
+            .as_array()?
+            .iter()
+            .find(|asset| asset["name"] == name)?["browser_download_url"]
+            .as_str()
+            .map(ToString::to_string)
+    };
+
+    Ok(Release {
+        version,
+        assets: url(&binary).zip(url(&signature)),
+    })
+}
+
+fn verify(binary: &[u8], signature: &str, public_key: &str) -> Result<(), UpdateError> {
+    let public_key = PublicKey::from_base64(public_key.trim())?;
+    let signature = Signature::decode(signature)?;
+    public_key.verify(binary, &signature, false)?;
+    Ok(())
+}
+
+/// Writes next to the binary first, so it's replaced at once or not at all
+fn replace(path: &PathBuf, binary: &[u8]) -> io::Result<()> {
+    let temporary = path.with_file_name(format!(".bright-update-{}", std::process::id()));
+    let result = fs::write(&temporary, binary)
+        .and_then(|()| fs::set_permissions(&temporary, fs::Permissions::from_mode(0o755)))
+        .and_then(|()| fs::rename(&temporary, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert_eq!(
+            "v1.2.3".parse(),
+            Ok(Version {
+                major: 1,
+                minor: 2,
+                patch: 3
+            })
+        );
+        assert!("1.2".parse::<Version>().is_err());
+        assert!("1.2.3.4".parse::<Version>().is_err());
+        assert!("1.2.3-rc1".parse::<Version>().is_err());
+        assert!("0.10.0".parse::<Version>().unwrap() > "0.9.9".parse().unwrap());
+        assert_eq!(Version::current().to_string(), env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_parse_release() {
+        let json = r#"{"tag_name": "v0.2.0", "assets": [
+            {"name": "bright-x86_64-unknown-linux-gnu", "browser_download_url": "https://example.com/bin"},
+            {"name": "bright-x86_64-unknown-linux-gnu.minisig", "browser_download_url": "https://example.com/sig"}
+        ]}"#;
+        let release = parse_release(json, "x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(release.version.to_string(), "0.2.0");
+        assert_eq!(
+            release.assets,
+            Some((
+                "https://example.com/bin".to_string(),
+                "https://example.com/sig".to_string()
+            ))
+        );
+
+        let other = parse_release(json, "aarch64-unknown-linux-gnu").unwrap();
+        assert_eq!(other.assets, None);
+        assert!(matches!(
+            parse_release("{}", TARGET),
+            Err(UpdateError::InvalidRelease(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify() {
+        // the example of minisign-verify
+        let public_key = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+        let signature = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==";
+
+        assert!(verify(b"test", signature, public_key).is_ok());
+        assert!(matches!(
+            verify(b"tampered", signature, public_key),
+            Err(UpdateError::Signature(_))
+        ));
+    }
+}
@@ -0,0 +1,1416 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+const BACKLIGHT: &str = "bright_test_backlight";
+const LED: &str = "bright_test_led";
+
+/// A copy of the fake sysfs tree in `tests/fixtures`, so tests can write to it
+fn sysfs() -> TempDir {
+    fn copy(from: &Path, to: &Path) -> io::Result<()> {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let target = to.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                copy(&entry.path(), &target)?;
+            } else {
+                fs::copy(entry.path(), target)?;
+            }
+        }
+        Ok(())
+    }
+
+    let root = TempDir::new().unwrap();
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sysfs");
+    copy(&fixtures, root.path()).unwrap();
+    root
+}
+
+fn bright(root: &TempDir) -> Command {
+    let mut cmd = Command::cargo_bin("bright").unwrap();
+    cmd.env("BRIGHT_SYSFS_ROOT", root.path())
+        .env("BRIGHT_CONFIG", root.path().join("easings"))
+        .env("BRIGHT_SETTINGS", root.path().join("settings"))
+        .env("BRIGHT_STATE_DIR", root.path().join("state"))
+        .env_remove("BRIGHT_DEVICE");
+    cmd
+}
+
+fn read(root: &TempDir, class: &str, device: &str, file: &str) -> String {
+    let path = root
+        .path()
+        .join("class")
+        .join(class)
+        .join(device)
+        .join(file);
+    fs::read_to_string(path).unwrap().trim_end().to_string()
+}
+
+#[test]
+fn test_list() {
+    let root = sysfs();
+    bright(&root)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(BACKLIGHT))
+        .stdout(predicate::str::contains("500/1000 (50%)"))
+        .stdout(predicate::str::contains(LED))
+        .stdout(predicate::str::contains("50/100 (50%)"));
+}
+
+#[test]
+fn test_list_verbose() {
+    let root = sysfs();
+    let broken = root.path().join("class/leds/broken");
+    fs::create_dir(&broken).unwrap();
+    fs::write(broken.join("brightness"), "0\n").unwrap();
+
+    bright(&root)
+        .arg("list")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+    bright(&root)
+        .args(["list", "--verbose"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(LED))
+        .stderr(predicate::str::contains(
+            "broken: only 1 of 2 required files were found",
+        ));
+}
+
+#[test]
+fn test_list_class() {
+    let root = sysfs();
+    bright(&root)
+        .args(["list", "--class", "leds"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(LED))
+        .stdout(predicate::str::contains(BACKLIGHT).not());
+
+    fs::write(root.path().join("settings"), "default_class = leds\n").unwrap();
+    bright(&root)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(BACKLIGHT).not());
+    bright(&root)
+        .args(["list", "--class", "backlight"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(BACKLIGHT));
+    bright(&root)
+        .args(["list", "--all-classes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(BACKLIGHT))
+        .stdout(predicate::str::contains(LED));
+    bright(&root)
+        .args(["get", "--format", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(r#""name":"{LED}""#)));
+}
+
+#[test]
+fn test_sensors() {
+    let root = sysfs();
+    bright(&root)
+        .args(["list", "--sensors"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("iio:device0 \"acpi-als\""))
+        .stdout(predicate::str::contains("100 lux"));
+    bright(&root)
+        .args(["meta", "--sensor", "acpi-als"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Illuminance: 100 (lux)"));
+}
+
+#[test]
+fn test_sysfs_root_setting() {
+    let root = sysfs();
+    fs::write(
+        root.path().join("settings"),
+        format!("sysfs_root = {}\n", root.path().display()),
+    )
+    .unwrap();
+
+    bright(&root)
+        .env_remove("BRIGHT_SYSFS_ROOT")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(BACKLIGHT));
+}
+
+#[test]
+fn test_meta() {
+    let root = sysfs();
+    bright(&root)
+        .args(["meta", "--device", BACKLIGHT])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Max brightness: 1000"))
+        .stdout(predicate::str::contains("Power mode: on"))
+        .stdout(predicate::str::contains("Type: raw"));
+}
+
+#[test]
+fn test_get() {
+    let root = sysfs();
+    bright(&root)
+        .args(["get", "--device", LED, "--format", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""current":50,"max":100"#));
+
+    bright(&root)
+        .args(["get", "--device", BACKLIGHT, "--format", "waybar"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""text":"50%""#));
+}
+
+#[test]
+fn test_set() {
+    let root = sysfs();
+    bright(&root)
+        .args(["set", "--device", LED, "20"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Finished: 20"));
+    assert_eq!(read(&root, "leds", LED, "brightness"), "20");
+
+    bright(&root)
+        .args(["set", "--device", LED, "10%+"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "leds", LED, "brightness"), "30");
+
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "--duration", "100ms", "80%"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Finished: 800"));
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "800");
+
+    bright(&root)
+        .args([
+            "set",
+            "--device",
+            LED,
+            "--duration",
+            "100ms",
+            "--follow-actual",
+            "60",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Finished: 60"));
+    assert_eq!(read(&root, "leds", LED, "brightness"), "60");
+}
+
+#[test]
+fn test_save_and_restore() {
+    let root = sysfs();
+    bright(&root)
+        .args(["set", "--device", LED, "--save", "10"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Saved: 50"));
+    assert_eq!(read(&root, "leds", LED, "brightness"), "10");
+
+    bright(&root)
+        .args(["set", "--device", LED, "restore"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "leds", LED, "brightness"), "50");
+
+    // brightnesses saved by older versions under the bare name are still restored
+    let state = root.path().join("state");
+    fs::remove_file(state.join(format!("leds:{LED}"))).unwrap();
+    fs::write(state.join(LED), "20").unwrap();
+    bright(&root)
+        .args(["set", "--device", LED, "restore"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "leds", LED, "brightness"), "20");
+}
+
+#[test]
+fn test_restore_other_device() {
+    let root = sysfs();
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "--save", "1000"])
+        .assert()
+        .success();
+
+    // the saved 500 of 1000 is rescaled to the LED's maximum of 100
+    bright(&root)
+        .args([
+            "set",
+            "--device",
+            LED,
+            &format!("restore(\"backlight:{BACKLIGHT}\")"),
+        ])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "leds", LED, "brightness"), "50");
+
+    // without a class the name is of the LED's class
+    bright(&root)
+        .args(["set", "--device", LED, &format!("restore(\"{BACKLIGHT}\")")])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!("leds:{BACKLIGHT}")));
+
+    bright(&root)
+        .args(["set", "--device", LED, "restore(5)"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected a quoted device name"));
+
+    // names can't leave the state directory
+    let outside = root.path().join("victim");
+    fs::write(&outside, "not a saved brightness").unwrap();
+    for name in [outside.to_str().unwrap(), "..", "../victim"] {
+        bright(&root)
+            .args(["set", "--device", LED, &format!("restore(\"{name}\")")])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("isn't a device name"));
+    }
+    assert!(outside.exists());
+}
+
+#[test]
+fn test_device_path() {
+    let root = sysfs();
+    let path = root.path().join("class/backlight").join(BACKLIGHT);
+    bright(&root)
+        .args(["set", "--device", path.to_str().unwrap(), "700"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "700");
+
+    bright(&root)
+        .args(["get", "--device", root.path().to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("isn't a device"));
+}
+
+#[test]
+fn test_device_pattern() {
+    let root = sysfs();
+    bright(&root)
+        .args(["set", "--device", "bright_test_*", "0"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "0");
+    assert_eq!(read(&root, "leds", LED, "brightness"), "0");
+
+    bright(&root)
+        .args(["set", "--device", "re:^leds:bright_.+$", "100"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "leds", LED, "brightness"), "100");
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "0");
+
+    // commands for a single device need a pattern which matches only one
+    bright(&root)
+        .args(["get", "--device", "bright_test_*"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("matches several devices"));
+}
+
+#[test]
+fn test_capabilities() {
+    let root = sysfs();
+    fs::write(root.path().join("settings"), "not a setting").unwrap();
+
+    // a broken config doesn't stop scripts from finding out what is supported
+    let output = bright(&root)
+        .args(["capabilities", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let capabilities: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(
+        capabilities["subcommands"]
+            .as_array()
+            .unwrap()
+            .contains(&"set".into())
+    );
+    assert!(
+        capabilities["functions"]
+            .as_array()
+            .unwrap()
+            .contains(&"restore".into())
+    );
+}
+
+#[test]
+fn test_led_trigger() {
+    let root = sysfs();
+    let trigger = root.path().join("class/leds").join(LED).join("trigger");
+    fs::write(&trigger, "none [timer] heartbeat\n").unwrap();
+
+    bright(&root)
+        .args(["set", "--device", LED, "10"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "the `timer` trigger overrides the brightness",
+        ));
+
+    bright(&root)
+        .args(["set", "--device", LED, "--force-trigger", "none", "20"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Trigger: none"));
+    assert_eq!(read(&root, "leds", LED, "brightness"), "20");
+    // the fake file doesn't mark the active trigger like sysfs does
+    assert_eq!(read(&root, "leds", LED, "trigger"), "timer");
+}
+
+#[test]
+fn test_set_several_devices() {
+    let root = sysfs();
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "--device", LED, "0"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "0");
+    assert_eq!(read(&root, "leds", LED, "brightness"), "0");
+
+    // each device uses its own maximum
+    bright(&root)
+        .args(["set", "--all", "100%"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "1000");
+    assert_eq!(read(&root, "leds", LED, "brightness"), "100");
+
+    bright(&root)
+        .args(["set", "--class", "leds", "10"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "leds", LED, "brightness"), "10");
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "1000");
+
+    // the devices are animated at the same time, not one after another
+    let start = Instant::now();
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "--device", LED, "20%"])
+        .args(["-d", "600ms"])
+        .assert()
+        .success();
+    assert!(start.elapsed() < Duration::from_millis(1100));
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "200");
+    assert_eq!(read(&root, "leds", LED, "brightness"), "20");
+}
+
+#[test]
+fn test_device_group() {
+    let root = sysfs();
+    fs::write(
+        root.path().join("settings"),
+        format!("@pair = {BACKLIGHT}, {LED}, unplugged"),
+    )
+    .unwrap();
+
+    bright(&root)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("@pair"));
+
+    // both are at half of their maximum, so they stay proportional to it
+    bright(&root)
+        .args(["set", "--device", "@pair", "--no-animate", "250"])
+        .assert()
+        .success();
+    let backlight: u32 = read(&root, "backlight", BACKLIGHT, "brightness")
+        .parse()
+        .unwrap();
+    let led: u32 = read(&root, "leds", LED, "brightness").parse().unwrap();
+    assert!(backlight < 500);
+    assert_eq!(backlight, led * 10);
+
+    bright(&root)
+        .args(["set", "--device", "@pair", "0"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "0");
+    assert_eq!(read(&root, "leds", LED, "brightness"), "0");
+
+    // the backlight's `actual_brightness` is still at half, raising doesn't cut off either of them
+    fs::write(
+        root.path().join("class/leds").join(LED).join("brightness"),
+        "80",
+    )
+    .unwrap();
+    bright(&root)
+        .args(["set", "--device", "@pair", "100%"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Finished: 1000"));
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "1000");
+    assert_eq!(read(&root, "leds", LED, "brightness"), "100");
+}
+
+#[test]
+fn test_unknown_device() {
+    let root = sysfs();
+    bright(&root)
+        .args(["set", "--device", "never_existing", "10"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no device named 'never_existing'"));
+}
+
+#[test]
+fn test_json_error() {
+    let root = sysfs();
+    bright(&root)
+        .args(["--format", "json", "get", "--device", "never_existing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::starts_with(r#"{"error":{"candidates":["#))
+        .stderr(predicate::str::contains(
+            r#""device":"never_existing","kind":"DeviceNotFound""#,
+        ));
+}
+
+#[test]
+fn test_unknown_configured_device() {
+    let root = sysfs();
+    fs::write(
+        root.path().join("settings"),
+        format!(
+            "warn_unknown_devices = true\n{BACKLIGHT}.verify_writes = true\n\
+             intel_backlite.verify_writes = true\n"
+        ),
+    )
+    .unwrap();
+
+    bright(&root)
+        .args(["get", "--device", LED])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "`intel_backlite` in the settings isn't a known device",
+        ))
+        .stderr(predicate::str::contains(BACKLIGHT).not());
+
+    // off by default, as it looks at every device
+    fs::write(
+        root.path().join("settings"),
+        "intel_backlite.verify_writes = true\n",
+    )
+    .unwrap();
+    bright(&root)
+        .args(["get", "--device", LED])
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn test_easing_set_and_unset() {
+    let root = sysfs();
+    let easings = root.path().join("easings");
+    fs::write(&easings, "# keep me\nx^2\n").unwrap();
+
+    bright(&root)
+        .args(["easing", "set", BACKLIGHT, "x^3"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&easings).unwrap(),
+        format!("# keep me\nx^2\n{BACKLIGHT} = x^3\n")
+    );
+    bright(&root)
+        .args(["get", "--device", BACKLIGHT])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("500/1000 (79."));
+
+    bright(&root)
+        .args(["easing", "set", "default", "x / 2"])
+        .assert()
+        .failure();
+    bright(&root)
+        .args(["easing", "set", "class:leds", "range(0.5, 2, x)"])
+        .assert()
+        .failure();
+
+    bright(&root)
+        .args(["easing", "unset", BACKLIGHT])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&easings).unwrap(), "# keep me\nx^2\n");
+}
+
+#[test]
+fn test_config_set_and_unset() {
+    let root = sysfs();
+    let settings = root.path().join("settings");
+    fs::write(&settings, "# keep me\nverify_writes = true\n").unwrap();
+
+    bright(&root)
+        .args(["config", "set", &format!("{LED}.min"), "20"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&settings).unwrap(),
+        format!("# keep me\nverify_writes = true\n{LED}.min = 20\n")
+    );
+    bright(&root)
+        .args(["set", "--device", LED, "0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Clamped: 20"));
+
+    // the file isn't changed if the settings would become invalid
+    for (key, value) in [
+        ("verify_writes", "maybe"),
+        ("foo", "1"),
+        ("verify_writes", "true\nsandbox = false"),
+        ("a = b", "1"),
+    ] {
+        bright(&root)
+            .args(["config", "set", key, value])
+            .assert()
+            .failure();
+    }
+
+    bright(&root)
+        .args(["config", "unset", &format!("{LED}.min")])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&settings).unwrap(),
+        "# keep me\nverify_writes = true\n"
+    );
+}
+
+#[test]
+fn test_easing_override() {
+    let root = sysfs();
+    fs::write(root.path().join("easings"), format!("{LED} = x^2\n")).unwrap();
+
+    // the configured easing of the LED stays
+    bright(&root)
+        .args(["--easing", &format!("{BACKLIGHT}=x^3,default=x"), "get"])
+        .args(["--device", LED])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("50/100 (70.7"));
+    bright(&root)
+        .args(["--easing", "x", "get", "--device", LED])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(50%)"));
+    bright(&root)
+        .args(["--easing", "foo=y", "list"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid easing `foo=y`"));
+}
+
+#[test]
+fn test_all_backlights() {
+    let root = sysfs();
+    bright(&root)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("all 500/1000 (50%)"));
+
+    bright(&root)
+        .args(["set", "--device", "all", "80%"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Finished: 800"));
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "800");
+
+    // a real device of that name comes first
+    let leds = root.path().join("class/leds");
+    fs::create_dir(leds.join("all")).unwrap();
+    for file in ["brightness", "max_brightness"] {
+        fs::copy(leds.join(LED).join(file), leds.join("all").join(file)).unwrap();
+    }
+    bright(&root)
+        .args(["get", "--device", "all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("50/100"));
+    bright(&root)
+        .args(["get", "--device", "virtual:all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/1000"));
+}
+
+#[test]
+fn test_shadowed_backlight() {
+    let root = sysfs();
+    let class = root.path().join("class/backlight");
+    let firmware = class.join("bright_test_firmware");
+    fs::create_dir(&firmware).unwrap();
+    for entry in fs::read_dir(class.join(BACKLIGHT)).unwrap() {
+        let entry = entry.unwrap();
+        fs::copy(entry.path(), firmware.join(entry.file_name())).unwrap();
+    }
+    fs::write(firmware.join("type"), "firmware\n").unwrap();
+
+    bright(&root)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("bright_test_firmware"))
+        .stdout(predicate::str::contains(BACKLIGHT).not());
+    bright(&root)
+        .args(["list", "--verbose"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(BACKLIGHT))
+        .stderr(predicate::str::contains(format!(
+            "Shadowed {BACKLIGHT}: controls the same panel as bright_test_firmware"
+        )));
+
+    fs::write(root.path().join("settings"), "backlight_preference = raw\n").unwrap();
+    bright(&root)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(BACKLIGHT))
+        .stdout(predicate::str::contains("bright_test_firmware").not());
+
+    // shadowed backlights can still be selected by name
+    bright(&root)
+        .args(["get", "--device", "bright_test_firmware"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_pipe_max_rate() {
+    let root = sysfs();
+    fs::write(
+        root.path().join("settings"),
+        format!("{LED}.max_rate = 500\n"),
+    )
+    .unwrap();
+
+    // 50% at 500% per second take 100ms
+    let start = Instant::now();
+    bright(&root)
+        .args(["pipe", "--device", LED])
+        .write_stdin("100%\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Finished: 100"));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert_eq!(read(&root, "leds", LED, "brightness"), "100");
+}
+
+#[test]
+fn test_soak() {
+    let root = sysfs();
+    // hooks aren't run for every change
+    fs::write(
+        root.path().join("settings"),
+        "pre_set = false\nhook_failure = abort\n",
+    )
+    .unwrap();
+    bright(&root)
+        .args(["soak", "--device", LED, "--cycles", "50", "-d", "5ms"])
+        .args(["--fps", "1000"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Cycles: 50"))
+        .stdout(predicate::str::contains("Updated").not());
+    assert_eq!(read(&root, "leds", LED, "brightness"), "50");
+}
+
+#[test]
+fn test_record_replay() {
+    let root = sysfs();
+    let session = root.path().join("session.jsonl");
+    bright(&root)
+        .args(["record", "--device", LED, "--duration", "0"])
+        .arg(&session)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Recorded: 1 events"));
+    let recorded = fs::read_to_string(&session).unwrap();
+    assert!(recorded.contains(&format!("\"leds:{LED}\"")));
+    assert!(recorded.contains("\"brightness\":50"));
+    assert_eq!(recorded.lines().count(), 1);
+
+    fs::write(
+        &session,
+        format!(
+            "{{\"at_ms\": 0, \"device\": \"leds:{LED}\", \"brightness\": 10}}\n\
+             {{\"at_ms\": 200, \"device\": \"leds:{LED}\", \"brightness\": 80}}\n"
+        ),
+    )
+    .unwrap();
+    bright(&root)
+        .args(["replay", "--speed", "4x"])
+        .arg(&session)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Replayed: 2 events (in 50ms)"));
+    assert_eq!(read(&root, "leds", LED, "brightness"), "80");
+
+    fs::write(
+        &session,
+        "{\"at_ms\": 0, \"device\": \"nonexistent\", \"brightness\": 1}\n",
+    )
+    .unwrap();
+    bright(&root)
+        .arg("replay")
+        .arg(&session)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("1 events couldn't be replayed"));
+}
+
+#[test]
+fn test_eval_explain() {
+    let root = sysfs();
+    bright(&root)
+        .args(["eval", "--device", LED, "--explain", "clamp(10, 20+, 60)"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "clamp(10, 20+, 60): 60 (70 is clamped to 60)",
+        ))
+        .stdout(predicate::str::contains(
+            "  20+: 70 (the device's brightness is 50)",
+        ))
+        .stdout(predicate::str::contains("Result: 60"));
+    // evaluating doesn't change the device
+    assert_eq!(read(&root, "leds", LED, "brightness"), "50");
+
+    bright(&root)
+        .args(["eval", "--device", LED, "90%+"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Warning: 140% is clamped to 100%"))
+        .stdout(predicate::str::contains("Result: 100"));
+}
+
+#[test]
+fn test_coalesce() {
+    let root = sysfs();
+    for _ in 0..3 {
+        bright(&root)
+            .args(["set", "--device", BACKLIGHT, "--coalesce", "1min", "50+"])
+            .assert()
+            .success();
+    }
+    // `actual_brightness` of the fake tree never changes, so without coalescing this is 550
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "650");
+}
+
+#[test]
+fn test_get_cache() {
+    let root = sysfs();
+    fs::write(root.path().join("settings"), "cache_ttl = 1min\n").unwrap();
+    let brightness = root.path().join("class/leds").join(LED).join("brightness");
+
+    bright(&root)
+        .args(["get", "--device", LED])
+        .assert()
+        .success();
+    fs::write(&brightness, "70").unwrap();
+    bright(&root)
+        .args(["get", "--device", LED])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("50/100"));
+    bright(&root)
+        .args(["get", "--device", LED, "--no-cache"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("70/100"));
+
+    // writing invalidates the cache
+    bright(&root)
+        .args(["set", "--device", LED, "20"])
+        .assert()
+        .success();
+    bright(&root)
+        .args(["get", "--device", LED])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("20/100"));
+
+    // also when a hook fails after writing
+    fs::write(
+        root.path().join("settings"),
+        "cache_ttl = 1min\npost_set = false\nhook_failure = abort\n",
+    )
+    .unwrap();
+    bright(&root)
+        .args(["set", "--device", LED, "30"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("post_set hook `false` exited"));
+    bright(&root)
+        .args(["get", "--device", LED])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("30/100"));
+}
+
+#[test]
+fn test_levels() {
+    let root = sysfs();
+    bright(&root)
+        .args(["list", "--levels"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("50/100 (50%) [101 levels]"));
+    bright(&root)
+        .args(["meta", "--device", LED, "--levels"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Levels: 101 (level:0 to level:100)",
+        ));
+
+    bright(&root)
+        .args(["set", "--device", LED, "level:7"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "leds", LED, "brightness"), "7");
+    bright(&root)
+        .args(["set", "--device", LED, "level:101"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("level 101 doesn't exist"));
+}
+
+#[test]
+fn test_label() {
+    let root = sysfs();
+    let device = root.path().join("class/leds").join(LED).join("device");
+    fs::create_dir(&device).unwrap();
+    fs::write(device.join("name"), "Test Keyboard\n").unwrap();
+
+    bright(&root)
+        .args(["meta", "--device", LED])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Label: Test Keyboard"));
+    bright(&root)
+        .args(["set", "--device", "test keyboard", "10"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "leds", LED, "brightness"), "10");
+
+    // without the panel's EDID only the machine is known
+    let dmi = root.path().join("class/dmi/id");
+    fs::create_dir_all(&dmi).unwrap();
+    fs::write(dmi.join("sys_vendor"), "ACME\n").unwrap();
+    fs::write(dmi.join("product_name"), "Laptop 13\n").unwrap();
+    bright(&root)
+        .args(["meta", "--device", BACKLIGHT])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Label: Built-in display of ACME Laptop 13 (raw)",
+        ));
+}
+
+#[test]
+fn test_qualified_device() {
+    let root = sysfs();
+    // a LED with the same name as the backlight
+    let duplicate = root.path().join("class/leds").join(BACKLIGHT);
+    fs::create_dir(&duplicate).unwrap();
+    fs::write(duplicate.join("brightness"), "1").unwrap();
+    fs::write(duplicate.join("max_brightness"), "1").unwrap();
+
+    bright(&root)
+        .args(["get", "--device", BACKLIGHT])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "choose one of backlight:{BACKLIGHT}, leds:{BACKLIGHT}"
+        )));
+    bright(&root)
+        .args(["get", "--device", &format!("leds:{BACKLIGHT}")])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("1/1"));
+    bright(&root)
+        .args(["get", "--device", &format!("backlight:{BACKLIGHT}")])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("500/1000"));
+
+    // both devices have their own saved brightness
+    bright(&root)
+        .args([
+            "set",
+            "--device",
+            &format!("leds:{BACKLIGHT}"),
+            "--save",
+            "0",
+        ])
+        .assert()
+        .success();
+    bright(&root)
+        .args([
+            "set",
+            "--device",
+            &format!("backlight:{BACKLIGHT}"),
+            "restore",
+        ])
+        .assert()
+        .failure();
+    bright(&root)
+        .args(["set", "--device", &format!("leds:{BACKLIGHT}"), "restore"])
+        .assert()
+        .success();
+    assert_eq!(read(&root, "leds", BACKLIGHT, "brightness"), "1");
+}
+
+#[test]
+fn test_export_and_import() {
+    let root = sysfs();
+    let output = bright(&root).arg("export").assert().success();
+    let script = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    assert!(script.contains(&format!("bright set --device leds:{LED} 50")));
+    assert!(script.contains(&format!("bright set --device backlight:{BACKLIGHT} 500")));
+
+    bright(&root)
+        .args(["set", "--device", LED, "5"])
+        .assert()
+        .success();
+    bright(&root)
+        .arg("import")
+        .write_stdin(script)
+        .assert()
+        .success();
+    assert_eq!(read(&root, "leds", LED, "brightness"), "50");
+
+    let output = bright(&root)
+        .args(["export", "--format", "json"])
+        .assert()
+        .success();
+    let json = root.path().join("brightness.json");
+    fs::write(&json, &output.get_output().stdout).unwrap();
+    bright(&root)
+        .args(["set", "--device", LED, "5"])
+        .assert()
+        .success();
+    bright(&root).arg("import").arg(&json).assert().success();
+    assert_eq!(read(&root, "leds", LED, "brightness"), "50");
+}
+
+#[test]
+fn test_apply_state() {
+    let root = sysfs();
+    let state = root.path().join("state.toml");
+    fs::write(
+        &state,
+        format!(
+            "{BACKLIGHT} = \"50%\"\n\n[\"leds:{LED}\"]\nbrightness = 20\nduration = \"50ms\"\n"
+        ),
+    )
+    .unwrap();
+
+    bright(&root)
+        .arg("apply")
+        .arg(&state)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Unchanged: 500"))
+        .stdout(predicate::str::contains("Finished: 20"));
+    assert_eq!(read(&root, "leds", LED, "brightness"), "20");
+
+    fs::write(&state, "unknown_device = 1").unwrap();
+    bright(&root)
+        .arg("apply")
+        .arg(&state)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no device named 'unknown_device'"));
+}
+
+#[test]
+fn test_frame_cap() {
+    let root = sysfs();
+    bright(&root)
+        .args(["set", "--device", LED, "53", "-d", "300ms", "--fps", "100"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Frames: 3 (capped from 30 to the levels in between, 100ms each)",
+        ));
+    assert_eq!(read(&root, "leds", LED, "brightness"), "53");
+}
+
+#[test]
+fn test_configured_animation() {
+    let root = sysfs();
+    fs::write(
+        root.path().join("settings"),
+        format!("fps = 50\n{LED}.duration = 100ms\n"),
+    )
+    .unwrap();
+
+    bright(&root)
+        .args(["set", "--device", LED, "60"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Frames: 5"));
+    bright(&root)
+        .args(["set", "--device", LED, "70", "--no-animate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Frames").not());
+    assert_eq!(read(&root, "leds", LED, "brightness"), "70");
+
+    // `--fps` relies on the configured duration, the backlight has none
+    bright(&root)
+        .args(["set", "--device", LED, "80", "--fps", "20"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Frames: 2"));
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "80", "--fps", "10"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("`--fps` requires `--duration`"));
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "500");
+
+    // `--follow-actual` as well
+    bright(&root)
+        .args(["set", "--device", LED, "90", "--follow-actual"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Finished: 90"));
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "80", "--follow-actual"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "`--follow-actual` requires `--duration`",
+        ));
+}
+
+#[test]
+fn test_hooks() {
+    let root = sysfs();
+    let log = root.path().join("hooks.log");
+    fs::write(
+        root.path().join("settings"),
+        format!(
+            "pre_set = echo $BRIGHT_HOOK $BRIGHT_DEVICE $BRIGHT_OLD $BRIGHT_NEW >> {log}\n\
+             post_set = echo $BRIGHT_HOOK $BRIGHT_NEW $BRIGHT_ANIMATED >> {log}\n\
+             {BACKLIGHT}.pre_set = false\n\
+             {BACKLIGHT}.hook_failure = abort\n",
+            log = log.display()
+        ),
+    )
+    .unwrap();
+
+    bright(&root)
+        .args(["set", "--device", LED, "60"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&log).unwrap(),
+        format!("pre_set {LED} 50 60\npost_set 60 0\n")
+    );
+
+    // the failing hook prevents the change
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "600"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pre_set hook `false` exited"));
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "500");
+}
+
+#[test]
+fn test_startup() {
+    let root = sysfs();
+    bright(&root)
+        .args(["startup", "--device", LED, "--duration", "100ms"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("doesn't exist"));
+
+    bright(&root)
+        .args(["set", "--device", LED, "--save", "10"])
+        .assert()
+        .success();
+    bright(&root)
+        .args(["startup", "--device", LED, "--duration", "100ms"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Previously: 0"))
+        .stdout(predicate::str::contains("Finished: 50"));
+    assert_eq!(read(&root, "leds", LED, "brightness"), "50");
+
+    fs::write(
+        root.path().join("settings"),
+        format!("{LED}.startup_brightness = 30%\nstartup_duration = 50ms\n"),
+    )
+    .unwrap();
+    bright(&root)
+        .args(["startup", "--device", LED, "--from-current"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Previously: 50"))
+        .stdout(predicate::str::contains("Finished: 30"));
+}
+
+#[test]
+fn test_pause_when_off() {
+    let root = sysfs();
+    let backlight = root.path().join("class/backlight").join(BACKLIGHT);
+    fs::write(backlight.join("bl_power"), "4").unwrap();
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin!("bright"))
+        .env("BRIGHT_SYSFS_ROOT", root.path())
+        .env("BRIGHT_CONFIG", root.path().join("easings"))
+        .env("BRIGHT_SETTINGS", root.path().join("settings"))
+        .env("BRIGHT_STATE_DIR", root.path().join("state"))
+        .args(["set", "--device", BACKLIGHT, "--duration", "100ms", "80%"])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(400));
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "500");
+    assert!(child.try_wait().unwrap().is_none());
+
+    fs::write(backlight.join("bl_power"), "0").unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "800");
+}
+
+#[test]
+fn test_power() {
+    let root = sysfs();
+    let bl_power = || read(&root, "backlight", BACKLIGHT, "bl_power");
+
+    bright(&root)
+        .args(["power", "off", "--device", BACKLIGHT])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Power: off (previously on)"));
+    assert_eq!(bl_power(), "4");
+    bright(&root)
+        .args(["power", "toggle", "--device", BACKLIGHT])
+        .assert()
+        .success();
+    assert_eq!(bl_power(), "0");
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "500");
+
+    bright(&root)
+        .args(["power", "off", "--device", LED])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "only backlights have a `bl_power`",
+        ));
+}
+
+#[test]
+fn test_power_off_at_zero() {
+    let root = sysfs();
+    let bl_power = || read(&root, "backlight", BACKLIGHT, "bl_power");
+
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "--duration", "50ms", "10%-"])
+        .arg("--power-off-at-zero")
+        .assert()
+        .success();
+    assert_eq!(bl_power(), "0");
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "--duration", "50ms", "0"])
+        .arg("--power-off-at-zero")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Power: off"));
+    assert_eq!(bl_power(), "4");
+
+    // powered on first, otherwise the fade would wait for the display
+    fs::write(root.path().join("settings"), "power_off_at_zero = true\n").unwrap();
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "--duration", "50ms", "50%"])
+        .timeout(Duration::from_secs(5))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Power: on"));
+    assert_eq!(bl_power(), "0");
+}
+
+#[test]
+fn test_idle_without_keyboards() {
+    let root = sysfs();
+    bright(&root)
+        .arg("idle")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No keyboard backlight found"));
+    // the fake tree has no input devices
+    bright(&root)
+        .args(["idle", "--device", LED])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Can't read the keyboards"));
+}
+
+#[test]
+fn test_min() {
+    let root = sysfs();
+    fs::write(
+        root.path().join("settings"),
+        format!("{BACKLIGHT}.min = 100\n"),
+    )
+    .unwrap();
+    let brightness = || read(&root, "backlight", BACKLIGHT, "brightness");
+
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Clamped: 100"));
+    assert_eq!(brightness(), "100");
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "0", "--force"])
+        .assert()
+        .success();
+    assert_eq!(brightness(), "0");
+
+    // below the floor already, so it's raised only as far as asked
+    let backlight = root.path().join("class/backlight").join(BACKLIGHT);
+    fs::write(backlight.join("actual_brightness"), "0").unwrap();
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "50"])
+        .assert()
+        .success();
+    assert_eq!(brightness(), "50");
+
+    // devices changed through a virtual one keep their floor too
+    fs::write(backlight.join("actual_brightness"), "500").unwrap();
+    bright(&root)
+        .args(["set", "--device", "all", "0"])
+        .assert()
+        .success();
+    assert_eq!(brightness(), "100");
+}
+
+#[test]
+fn test_default_device() {
+    let root = sysfs();
+    let firmware = root.path().join("class/backlight/acpi_video0");
+    fs::create_dir_all(&firmware).unwrap();
+    for (file, content) in [
+        ("brightness", "10"),
+        ("actual_brightness", "10"),
+        ("max_brightness", "15"),
+        ("bl_power", "0"),
+        ("type", "firmware"),
+    ] {
+        fs::write(firmware.join(file), content).unwrap();
+    }
+
+    let default_name = || {
+        let output = bright(&root)
+            .args(["get", "--format", "json"])
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    // firmware is preferred although the raw backlight has more levels
+    assert!(default_name().contains(r#""name":"acpi_video0""#));
+
+    fs::write(
+        root.path().join("settings"),
+        "backlight_preference = raw, firmware\n",
+    )
+    .unwrap();
+    assert!(default_name().contains(&format!(r#""name":"{BACKLIGHT}""#)));
+}
+
+#[test]
+fn test_when_off() {
+    let root = sysfs();
+    let backlight = root.path().join("class/backlight").join(BACKLIGHT);
+    fs::write(backlight.join("bl_power"), "4").unwrap();
+
+    fs::write(root.path().join("settings"), "when_off = refuse\n").unwrap();
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "--duration", "10s", "80%"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("display is off"));
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "500");
+
+    fs::write(root.path().join("settings"), "when_off = instant\n").unwrap();
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "--duration", "10s", "80%"])
+        .timeout(Duration::from_secs(5))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Instant: 800"));
+    assert_eq!(read(&root, "backlight", BACKLIGHT, "brightness"), "800");
+}
+
+#[test]
+fn test_read_fallback() {
+    let root = sysfs();
+    let backlight = root.path().join("class/backlight").join(BACKLIGHT);
+    fs::write(backlight.join("actual_brightness"), "0").unwrap();
+
+    bright(&root)
+        .args(["get", "--device", BACKLIGHT])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0/1000"));
+
+    fs::write(
+        root.path().join("settings"),
+        format!("{BACKLIGHT}.read_fallback = true\n"),
+    )
+    .unwrap();
+    bright(&root)
+        .args(["get", "--device", BACKLIGHT])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("500/1000"))
+        .stderr(predicate::str::contains("actual_brightness of"));
+    bright(&root)
+        .args(["set", "--device", BACKLIGHT, "600"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("actual_brightness is unreliable"))
+        .stdout(predicate::str::contains("Previously: 500"));
+}
@@ -0,0 +1,98 @@
+#![cfg(feature = "ffi")]
+
+use bright::ffi::*;
+use std::{
+    env,
+    ffi::{CStr, CString},
+    fs, io,
+    path::{Path, PathBuf},
+    ptr,
+};
+use tempfile::TempDir;
+
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+// a single test, as the environment is shared by the whole process
+#[test]
+fn test_c_abi() {
+    let root = TempDir::new().unwrap();
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sysfs");
+    copy(&fixtures, root.path()).unwrap();
+    // SAFETY: no other thread reads the environment
+    unsafe {
+        env::set_var("BRIGHT_SYSFS_ROOT", root.path());
+        env::set_var("BRIGHT_CONFIG", root.path().join("easings"));
+        env::set_var("BRIGHT_SETTINGS", root.path().join("settings"));
+        env::set_var("BRIGHT_STATE_DIR", root.path().join("state"));
+        env::remove_var("BRIGHT_DEVICE");
+    }
+    let backlight = CString::new("bright_test_backlight").unwrap();
+
+    unsafe {
+        let mut list = ptr::null_mut();
+        assert_eq!(bright_list_devices(&mut list), BrightStatus::Ok);
+        let names: Vec<_> = (0..bright_device_list_len(list))
+            .map(|i| {
+                CStr::from_ptr(bright_device_list_get(list, i))
+                    .to_str()
+                    .unwrap()
+            })
+            .collect();
+        assert!(names.contains(&"bright_test_backlight"));
+        assert!(names.contains(&"bright_test_led"));
+        assert!(bright_device_list_get(list, names.len()).is_null());
+        bright_device_list_free(list);
+
+        let (mut current, mut max) = (0, 0);
+        assert_eq!(
+            bright_get(backlight.as_ptr(), &mut current, &mut max),
+            BrightStatus::Ok
+        );
+        assert_eq!((current, max), (500, 1000));
+
+        let mut value = 0;
+        let expression = CString::new("10%+").unwrap();
+        assert_eq!(
+            bright_evaluate(backlight.as_ptr(), expression.as_ptr(), &mut value),
+            BrightStatus::Ok
+        );
+        assert_eq!(value, 600);
+        let invalid = CString::new("max(").unwrap();
+        assert_eq!(
+            bright_evaluate(backlight.as_ptr(), invalid.as_ptr(), &mut value),
+            BrightStatus::InvalidExpression
+        );
+
+        assert_eq!(bright_set(backlight.as_ptr(), 300), BrightStatus::Ok);
+        let written = root
+            .path()
+            .join("class/backlight/bright_test_backlight/brightness");
+        assert_eq!(fs::read_to_string(written).unwrap().trim_end(), "300");
+
+        let missing = CString::new("missing").unwrap();
+        assert_eq!(
+            bright_set(missing.as_ptr(), 1),
+            BrightStatus::DeviceNotFound
+        );
+        assert_eq!(
+            bright_get(backlight.as_ptr(), ptr::null_mut(), &mut max),
+            BrightStatus::NullPointer
+        );
+        assert_eq!(
+            CStr::from_ptr(bright_status_message(BrightStatus::DeviceNotFound)),
+            c"device not found"
+        );
+    }
+}